@@ -0,0 +1,126 @@
+//! Runs queries through the pipeline of the frontends and describes the
+//! responses as JSON, for checking Yozuk in scripts and CI.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    plan_response, run_plan, EngineOptions, Lang, Limits, PlanItem, PlanStatus, ResponsePlan,
+};
+use yozuk_sdk::prelude::*;
+
+pub struct Batch {
+    zuk: Arc<Yozuk>,
+    limits: Limits,
+    engine: EngineOptions,
+    lang: Lang,
+}
+
+impl Batch {
+    /// Queries are cancelled after `limits.command_timeout`.
+    pub fn new(limits: Limits, engine: EngineOptions, lang: Lang) -> Self {
+        Self {
+            zuk: Arc::new(engine.build()),
+            limits,
+            engine,
+            lang,
+        }
+    }
+
+    /// Runs every non-empty line of `input` and prints a JSON array with the
+    /// results when the input ends.
+    ///
+    /// Returns whether all queries succeeded; unrecognized queries count as
+    /// failures. With `fail_fast`, the first failure ends the batch.
+    pub fn run<R: BufRead, W: Write>(
+        &self,
+        input: R,
+        output: &mut W,
+        fail_fast: bool,
+    ) -> Result<bool> {
+        let mut results = vec![];
+        let mut success = true;
+        for line in input.lines() {
+            let line = line?;
+            let query = line.trim();
+            if query.is_empty() {
+                continue;
+            }
+            let plan = self.plan(query);
+            success &= plan.status == PlanStatus::Success;
+            results.push(self.result(query, plan));
+            if fail_fast && !success {
+                break;
+            }
+        }
+        serde_json::to_writer_pretty(&mut *output, &results)?;
+        writeln!(output)?;
+        Ok(success)
+    }
+
+    fn plan(&self, query: &str) -> ResponsePlan {
+        let mut user = UserContext {
+            locale: Some(self.lang.code().into()),
+            ..Default::default()
+        };
+        self.engine.apply(&mut user);
+
+        let zuk = self.zuk.clone();
+        let limits = self.limits.clone();
+        let text = query.to_string();
+        run_plan(&self.limits, query, move || {
+            plan_response(&zuk, &text, vec![], &user, &limits)
+        })
+    }
+
+    /// Describes the response to `query` as a JSON object.
+    fn result(&self, query: &str, plan: ResponsePlan) -> Value {
+        let items = plan
+            .items
+            .into_iter()
+            .map(|item| match item {
+                PlanItem::Text(text) => json!({ "type": "text", "text": text }),
+                PlanItem::CodeBlock { lang, text } => {
+                    json!({ "type": "code", "lang": lang, "text": text })
+                }
+                PlanItem::File {
+                    name,
+                    media_type,
+                    data,
+                } => json!({
+                    "type": "file",
+                    "name": name,
+                    "media_type": media_type.to_string(),
+                    "size": data.len(),
+                }),
+                PlanItem::Apology { suggestions } => json!({
+                    "type": "apology",
+                    "text": self.lang.tr("unrecognized", &[]),
+                    "suggestions": suggestions,
+                }),
+                PlanItem::Error { category } => json!({
+                    "type": "error",
+                    "category": category.name(),
+                    "text": category.message(self.lang),
+                }),
+                PlanItem::Section { title } => json!({ "type": "section", "title": title }),
+            })
+            .collect::<Vec<_>>();
+        let status = match plan.status {
+            PlanStatus::Success => "success",
+            PlanStatus::Failure => "failure",
+            PlanStatus::Unrecognized => "unrecognized",
+        };
+        json!({
+            "query": query,
+            "status": status,
+            "command": plan.command,
+            "category": plan.category.map(|category| category.name()),
+            "error": plan.error,
+            "duration_ms": plan.duration.as_millis() as u64,
+            "items": items,
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use yozuk_batch::Batch;
+use yozuk_bot_core::{
+    init_logging, EngineOptions, Lang, Limits, Location, LogFormat, TextPolicy, Timezone,
+};
+
+/// Runs one query per line and prints the responses as a JSON array.
+///
+/// Exits with status 1 if any query failed or wasn't understood.
+#[derive(Parser)]
+#[clap(author, version, about)]
+pub struct Args {
+    /// Read queries from this file instead of stdin
+    #[clap(long)]
+    pub input: Option<PathBuf>,
+
+    /// Stop at the first query that didn't succeed
+    #[clap(long)]
+    pub fail_fast: bool,
+
+    /// Cancel queries that take longer than this many seconds
+    #[clap(long, default_value = "30")]
+    pub command_timeout: u64,
+
+    /// Language of canned replies: "en", "de" or "ja"
+    #[clap(long, default_value = "en")]
+    pub lang: Lang,
+
+    /// How text outputs are sent: "file", "inline" or the maximum inline length
+    #[clap(long, default_value = "inline")]
+    pub text_policy: TextPolicy,
+
+    /// Timezone of the user, e.g. "Europe/Berlin"
+    #[clap(long)]
+    pub timezone: Option<Timezone>,
+
+    /// Location of the user as "<latitude>,<longitude>"
+    #[clap(long)]
+    pub location: Option<Location>,
+}
+
+fn main() -> Result<()> {
+    init_logging(LogFormat::Pretty)?;
+    let args = Args::parse();
+    let limits = Limits {
+        text_policy: args.text_policy,
+        command_timeout: Duration::from_secs(args.command_timeout),
+        ..Default::default()
+    };
+    let engine = EngineOptions {
+        timezone: args.timezone,
+        location: args.location,
+    };
+    let input: Box<dyn BufRead> = match &args.input {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        )),
+        None => Box::new(io::stdin().lock()),
+    };
+    let batch = Batch::new(limits, engine, args.lang);
+    // Queries that timed out may still be running, so the process exits
+    // without waiting for them.
+    let success = batch.run(input, &mut io::stdout(), args.fail_fast)?;
+    process::exit(if success { 0 } else { 1 });
+}
@@ -0,0 +1,107 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use yozuk_batch::Batch;
+use yozuk_bot_core::{EngineOptions, Lang, Limits};
+
+fn run(input: &str, timeout: Duration, fail_fast: bool) -> (bool, Value) {
+    let limits = Limits {
+        command_timeout: timeout,
+        ..Default::default()
+    };
+    let batch = Batch::new(limits, EngineOptions::default(), Lang::En);
+    let mut output = vec![];
+    let success = batch.run(input.as_bytes(), &mut output, fail_fast).unwrap();
+    (success, serde_json::from_slice(&output).unwrap())
+}
+
+#[test]
+fn results_are_printed_as_a_json_array() {
+    let (success, results) = run(
+        "aGVsbG8= base64 decode\n\nhello to QRCode\n",
+        Duration::from_secs(30),
+        false,
+    );
+    assert!(success);
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["query"], "aGVsbG8= base64 decode");
+    assert_eq!(results[0]["status"], "success");
+    assert_eq!(results[0]["error"], Value::Null);
+    assert_eq!(results[0]["items"][0]["text"], "hello");
+    assert_eq!(
+        results[1]["items"][0],
+        json!({
+            "type": "file",
+            "name": "qrcode.png",
+            "media_type": "image/png",
+            "size": 383,
+        })
+    );
+}
+
+#[test]
+fn every_query_is_reported() {
+    let (success, results) = run("md5\n!!!\n1 + 2\n", Duration::from_secs(30), false);
+    assert!(!success);
+    let statuses = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["status"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, ["failure", "unrecognized", "success"]);
+    assert_eq!(results[0]["category"], "command_error");
+    assert_eq!(results[0]["items"][0]["type"], "error");
+    assert_eq!(results[1]["category"], "no_match");
+    assert_eq!(results[1]["items"][0]["type"], "apology");
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_failure() {
+    let (success, results) = run("1 + 2\nmd5\n1 + 3\n", Duration::from_secs(30), true);
+    assert!(!success);
+    assert_eq!(results.as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn slow_queries_time_out() {
+    let (success, results) = run("hello to QRCode\n", Duration::ZERO, false);
+    assert!(!success);
+    assert_eq!(results[0]["status"], "failure");
+    assert_eq!(results[0]["category"], "timeout");
+    assert_eq!(results[0]["error"], "timed out after 0 s");
+}
+
+fn run_binary(args: &[&str], input: &str) -> (Option<i32>, Value) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_yozuk-batch"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code(),
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn failures_set_the_exit_status() {
+    let (status, results) = run_binary(&[], "1 + 2\n");
+    assert_eq!(status, Some(0));
+    assert_eq!(results[0]["status"], "success");
+
+    let (status, results) = run_binary(&["--fail-fast"], "md5\n1 + 2\n");
+    assert_eq!(status, Some(1));
+    assert_eq!(results.as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Slow mode up to this long is waited out between the pages of a reply;
+/// replies that would have to wait longer are sent privately.
+pub const MAX_PAGE_DELAY: Duration = Duration::from_secs(10);
+
+/// What the bot may do in a chat, as far as replying is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub send: bool,
+    pub attach: bool,
+    /// Time the bot has to wait between two messages, if slow mode applies to it.
+    pub slow_mode: Option<Duration>,
+}
+
+impl Access {
+    pub const FULL: Self = Self {
+        send: true,
+        attach: true,
+        slow_mode: None,
+    };
+
+    /// Decides where a reply of `pages` messages goes.
+    pub fn delivery(&self, pages: usize) -> Delivery {
+        if !self.send {
+            return Delivery::Private;
+        }
+        let page_delay = match self.slow_mode {
+            Some(delay) if pages > 1 && delay > MAX_PAGE_DELAY => return Delivery::Private,
+            Some(delay) if pages > 1 => Some(delay),
+            _ => None,
+        };
+        Delivery::Chat {
+            attach: self.attach,
+            page_delay,
+        }
+    }
+}
+
+/// Where and how a reply is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Reply in the chat of the request, waiting `page_delay` between messages.
+    /// Files are inlined unless `attach` is set.
+    Chat {
+        attach: bool,
+        page_delay: Option<Duration>,
+    },
+    /// Reply in a private chat with the sender, or skip the reply if there is none.
+    Private,
+}
+
+/// Permissions of the bot in a channel, as platforms with per-channel
+/// permissions report them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelPermissions {
+    /// Whether the channel is a thread, whose own send permission applies.
+    pub thread: bool,
+    pub send_messages: bool,
+    pub send_messages_in_threads: bool,
+    pub attach_files: bool,
+    /// Members who can manage the channel or its messages are exempt from slow mode.
+    pub moderator: bool,
+    pub slow_mode: Option<Duration>,
+}
+
+impl ChannelPermissions {
+    pub fn access(&self) -> Access {
+        Access {
+            send: if self.thread {
+                self.send_messages_in_threads
+            } else {
+                self.send_messages
+            },
+            attach: self.attach_files,
+            slow_mode: self
+                .slow_mode
+                .filter(|delay| !self.moderator && !delay.is_zero()),
+        }
+    }
+}
+
+/// Looks up what the bot may do in a chat.
+#[async_trait]
+pub trait PermissionSource<K>: Send + Sync {
+    async fn access(&self, chat: &K) -> Result<Access>;
+}
+
+/// Reuses the results of a [`PermissionSource`] for a while.
+#[derive(Debug)]
+pub struct AccessCache<K> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, Access)>>,
+}
+
+impl<K> AccessCache<K>
+where
+    K: Eq + Hash + Display + Send + Sync,
+{
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    /// Looks up the access to `chat`, reusing results younger than the TTL.
+    ///
+    /// Failed lookups are not cached and grant [`Access::FULL`], since
+    /// sending may still work and its errors are reported anyway.
+    pub async fn get<S>(&self, source: &S, chat: K) -> Access
+    where
+        S: PermissionSource<K> + ?Sized,
+    {
+        if let Some((checked, access)) = self.entries.lock().await.get(&chat) {
+            if checked.elapsed() < self.ttl {
+                return *access;
+            }
+        }
+        match source.access(&chat).await {
+            Ok(access) => {
+                self.entries
+                    .lock()
+                    .await
+                    .insert(chat, (Instant::now(), access));
+                access
+            }
+            Err(err) => {
+                tracing::warn!(chat = %chat, "failed to look up permissions: {}", err);
+                Access::FULL
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Whether an address is on the public internet, so that fetching a
+/// user-supplied URL from it can't reach the bot's own host, its private
+/// network or a cloud metadata service.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        // Also covers 169.254.169.254, the metadata service of most clouds.
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space (RFC 6598), where some clouds put their metadata services.
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments.
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking (RFC 2544).
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved for future use.
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ip);
+    }
+    let [a, b, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local addresses (fc00::/7).
+        || (a & 0xfe00) == 0xfc00
+        // Link-local addresses (fe80::/10).
+        || (a & 0xffc0) == 0xfe80
+        // Documentation (2001:db8::/32).
+        || (a == 0x2001 && b == 0xdb8))
+}
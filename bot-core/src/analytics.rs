@@ -0,0 +1,55 @@
+use crate::i18n::Lang;
+use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An executed command, without any of the user's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandEvent {
+    /// Name of the skill, e.g. "calc".
+    pub command: String,
+    pub lang: Lang,
+    pub success: bool,
+    /// When the message that ran the command was sent, if the platform says so.
+    pub sent_at: Option<SystemTime>,
+}
+
+/// Receives an event for every command run by [`crate::plan_response`].
+pub trait AnalyticsSink: Debug + Send + Sync {
+    fn record(&self, event: &CommandEvent);
+}
+
+#[derive(Debug, Default)]
+pub struct NoopAnalytics;
+
+impl AnalyticsSink for NoopAnalytics {
+    fn record(&self, _: &CommandEvent) {}
+}
+
+/// Writes events to the log under the `analytics` target.
+#[derive(Debug)]
+pub struct LogAnalytics {
+    platform: &'static str,
+}
+
+impl LogAnalytics {
+    pub fn new(platform: &'static str) -> Self {
+        Self { platform }
+    }
+}
+
+impl AnalyticsSink for LogAnalytics {
+    fn record(&self, event: &CommandEvent) {
+        tracing::info!(
+            target: "analytics",
+            platform = self.platform,
+            command = %event.command,
+            lang = event.lang.code(),
+            success = event.success,
+            sent_at = ?event
+                .sent_at
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            "command"
+        );
+    }
+}
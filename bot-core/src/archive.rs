@@ -0,0 +1,155 @@
+use crate::category::ErrorCategory;
+use crate::i18n::Lang;
+use crate::memory::Attachment;
+use flate2::read::MultiGzDecoder;
+use std::fmt;
+use std::io::{Cursor, Read};
+use yozuk_helper_filetype::guess_media_type;
+use zip::ZipArchive;
+
+const ZIP_TYPES: &[&str] = &["zip", "x-zip", "x-zip-compressed"];
+const GZIP_TYPES: &[&str] = &["gzip", "x-gzip"];
+
+/// Bounds on unpacking archive attachments, which protect against archives
+/// that expand to huge or countless files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    /// Larger archives are passed on as they are.
+    pub max_archive_size: usize,
+
+    /// Maximum size of the extracted file.
+    pub max_extracted_size: usize,
+
+    /// Archives with more entries than this, directories included, are
+    /// rejected without looking at them.
+    pub max_entries: usize,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_archive_size: 10 * 1024 * 1024,
+            max_extracted_size: 10 * 1024 * 1024,
+            max_entries: 16,
+        }
+    }
+}
+
+/// Why an archive attachment was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnpackError {
+    /// The archive holds no file or more than one.
+    FileCount,
+    /// The file would be larger than [`ArchiveLimits::max_extracted_size`].
+    TooLarge(usize),
+    Invalid(String),
+}
+
+impl UnpackError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::TooLarge(_) => ErrorCategory::InputTooLarge,
+            _ => ErrorCategory::CommandError,
+        }
+    }
+
+    /// Explains the rejection to the sender.
+    pub fn message(&self, lang: Lang) -> String {
+        match self {
+            Self::FileCount => lang.tr("archive-file-count", &[]),
+            Self::TooLarge(max) => lang.tr("archive-too-large", &[("size", &max.to_string())]),
+            Self::Invalid(_) => lang.tr("archive-invalid", &[]),
+        }
+    }
+}
+
+impl fmt::Display for UnpackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileCount => write!(f, "archive doesn't contain exactly one file"),
+            Self::TooLarge(max) => write!(f, "extracted file exceeds {} bytes", max),
+            Self::Invalid(err) => write!(f, "invalid archive: {}", err),
+        }
+    }
+}
+
+/// Replaces ZIP and gzip attachments with the single file they contain,
+/// whose media type is guessed from its content.
+///
+/// Only attachments declared as archives by their media type or file name
+/// are unpacked, since documents like .docx or .epub are ZIP files too.
+/// Other attachments, streamed ones and archives over
+/// [`ArchiveLimits::max_archive_size`] are kept as they are.
+pub fn unpack_archives(
+    attachments: Vec<Attachment>,
+    limits: &ArchiveLimits,
+) -> Result<Vec<Attachment>, UnpackError> {
+    attachments
+        .into_iter()
+        .map(|attachment| {
+            if attachment.is_streamed() || attachment.data.len() > limits.max_archive_size {
+                return Ok(attachment);
+            }
+            let data = if is_declared(&attachment, ZIP_TYPES, ".zip") {
+                unzip(&attachment.data, limits)?
+            } else if is_declared(&attachment, GZIP_TYPES, ".gz") {
+                read_limited(MultiGzDecoder::new(&attachment.data[..]), limits)?
+            } else {
+                return Ok(attachment);
+            };
+            let media_type = guess_media_type(&data);
+            Ok(Attachment::new(data, media_type))
+        })
+        .collect()
+}
+
+fn is_declared(attachment: &Attachment, subtypes: &[&str], extension: &str) -> bool {
+    let media_type = &attachment.media_type;
+    let by_type = media_type.ty() == "application"
+        && subtypes
+            .iter()
+            .any(|&subtype| media_type.subty() == subtype);
+    let by_name = match &attachment.name {
+        Some(name) => name.to_ascii_lowercase().ends_with(extension),
+        None => false,
+    };
+    by_type || by_name
+}
+
+fn unzip(data: &[u8], limits: &ArchiveLimits) -> Result<Vec<u8>, UnpackError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(invalid)?;
+    if archive.len() > limits.max_entries {
+        return Err(UnpackError::FileCount);
+    }
+    let mut files = vec![];
+    for index in 0..archive.len() {
+        if archive.by_index_raw(index).map_err(invalid)?.is_file() {
+            files.push(index);
+        }
+    }
+    let file = match files[..] {
+        [index] => archive.by_index(index).map_err(invalid)?,
+        _ => return Err(UnpackError::FileCount),
+    };
+    // The declared size may be forged, so the read is bounded as well.
+    if file.size() > limits.max_extracted_size as u64 {
+        return Err(UnpackError::TooLarge(limits.max_extracted_size));
+    }
+    read_limited(file, limits)
+}
+
+fn read_limited<R: Read>(reader: R, limits: &ArchiveLimits) -> Result<Vec<u8>, UnpackError> {
+    let mut data = vec![];
+    reader
+        .take(limits.max_extracted_size as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(invalid)?;
+    if data.len() > limits.max_extracted_size {
+        return Err(UnpackError::TooLarge(limits.max_extracted_size));
+    }
+    Ok(data)
+}
+
+fn invalid<E: fmt::Display>(err: E) -> UnpackError {
+    UnpackError::Invalid(err.to_string())
+}
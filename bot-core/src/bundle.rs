@@ -0,0 +1,74 @@
+use crate::plan::{Limits, PlanItem};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+/// Replaces the file items with a single ZIP archive if there are more than
+/// `limits.bundle_threshold` of them.
+///
+/// The files are left untouched if the archive would exceed `limits.max_file_size`.
+pub fn bundle_files(items: &mut Vec<PlanItem>, name: &str, limits: &Limits) {
+    let threshold = match limits.bundle_threshold {
+        Some(threshold) => threshold,
+        None => return,
+    };
+    let count = items
+        .iter()
+        .filter(|item| matches!(item, PlanItem::File { .. }))
+        .count();
+    if count <= threshold {
+        return;
+    }
+
+    let data = match archive(items) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+    if matches!(limits.max_file_size, Some(max) if data.len() > max) {
+        return;
+    }
+
+    let index = items
+        .iter()
+        .position(|item| matches!(item, PlanItem::File { .. }))
+        .unwrap_or(items.len());
+    items.retain(|item| !matches!(item, PlanItem::File { .. }));
+    items.insert(
+        index,
+        PlanItem::File {
+            name: format!("{}.zip", name),
+            media_type: media_type!(APPLICATION / ZIP).into(),
+            data,
+        },
+    );
+}
+
+fn archive(items: &[PlanItem]) -> zip::result::ZipResult<Bytes> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut names = HashSet::new();
+    for item in items {
+        if let PlanItem::File { name, data, .. } = item {
+            zip.start_file(unique_name(&mut names, name), FileOptions::default())?;
+            zip.write_all(data)?;
+        }
+    }
+    Ok(zip.finish()?.into_inner().into())
+}
+
+/// Appends a counter to the stem of `name` until it is not in `names`.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let (stem, ext) = match name.rfind('.') {
+        Some(pos) if pos > 0 => name.split_at(pos),
+        _ => (name, ""),
+    };
+    let mut unique = name.to_string();
+    let mut n = 1;
+    while !names.insert(unique.clone()) {
+        unique = format!("{}-{}{}", stem, n, ext);
+        n += 1;
+    }
+    unique
+}
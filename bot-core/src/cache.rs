@@ -0,0 +1,126 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use yozuk_sdk::prelude::*;
+
+/// Number of results kept at once; the least recently used one is dropped first.
+const MAX_ENTRIES: usize = 256;
+
+/// Skills whose outputs are random or depend on the current time.
+pub const DEFAULT_UNCACHED_SKILLS: &[&str] = &[
+    "chitchat", "dice", "kdf", "lipsum", "nanoid", "password", "time", "username", "uuid",
+];
+
+/// Reuses the outputs of identical commands run shortly after each other.
+///
+/// Results are keyed by the command arguments, the content of the input
+/// streams and the locale, timezone and location of the user. Only successful
+/// runs are stored.
+#[derive(Debug)]
+pub struct ResultCache {
+    ttl: Duration,
+    uncached: HashSet<String>,
+    entries: Mutex<HashMap<u64, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    outputs: Vec<Output>,
+    created: Instant,
+    used: Instant,
+}
+
+impl ResultCache {
+    /// `uncached` lists skill names like "uuid" whose results are never reused.
+    pub fn new<I, S>(ttl: Duration, uncached: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            ttl,
+            uncached: uncached.into_iter().map(Into::into).collect(),
+            entries: Default::default(),
+        }
+    }
+
+    /// Number of stored results, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn accepts(&self, commands: &[CommandArgs]) -> bool {
+        commands.iter().all(|command| match command.args.first() {
+            Some(name) => !self
+                .uncached
+                .contains(name.trim_start_matches("yozuk-skill-")),
+            None => true,
+        })
+    }
+
+    /// Computes the key of a request, buffering the streams to hash their content.
+    pub(crate) fn key(
+        &self,
+        commands: &[CommandArgs],
+        streams: &mut [InputStream],
+        user: &UserContext,
+    ) -> io::Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        commands.hash(&mut hasher);
+        for stream in streams.iter_mut() {
+            let mut data = vec![];
+            stream.read_to_end(&mut data)?;
+            stream.media_type().to_string().hash(&mut hasher);
+            data.hash(&mut hasher);
+            let mut buffered = InputStream::new(Cursor::new(data), stream.media_type().clone());
+            buffered.read_header()?;
+            *stream = buffered;
+        }
+        user.locale.hash(&mut hasher);
+        user.timezone.hash(&mut hasher);
+        user.location
+            .map(|(lat, lon)| (lat.to_bits(), lon.to_bits()))
+            .hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<Vec<Output>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get_mut(&key)
+            .filter(|entry| entry.created.elapsed() < self.ttl)?;
+        entry.used = Instant::now();
+        Some(entry.outputs.clone())
+    }
+
+    pub(crate) fn insert(&self, key: u64, outputs: Vec<Output>) {
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.retain(|_, entry| entry.created.elapsed() < ttl);
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            key,
+            Entry {
+                outputs,
+                created: now,
+                used: now,
+            },
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::i18n::Lang;
+use std::fmt;
+use tracing::Level;
+
+/// What kind of failure a reply reports, which decides how it is worded and
+/// how loudly it is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// No command understood the message.
+    NoMatch,
+    /// An input exceeded a size limit.
+    InputTooLarge,
+    /// The commands ran longer than `Limits::command_timeout`.
+    Timeout,
+    /// A command rejected its input.
+    CommandError,
+    /// A command panicked, which is a bug rather than a problem with the message.
+    InternalError,
+}
+
+impl ErrorCategory {
+    pub const ALL: [Self; 5] = [
+        Self::NoMatch,
+        Self::InputTooLarge,
+        Self::Timeout,
+        Self::CommandError,
+        Self::InternalError,
+    ];
+
+    /// Name used in logs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NoMatch => "no_match",
+            Self::InputTooLarge => "input_too_large",
+            Self::Timeout => "timeout",
+            Self::CommandError => "command_error",
+            Self::InternalError => "internal_error",
+        }
+    }
+
+    /// Whether the sender can avoid the failure by changing the message.
+    pub fn is_user_error(self) -> bool {
+        matches!(
+            self,
+            Self::NoMatch | Self::InputTooLarge | Self::CommandError
+        )
+    }
+
+    /// Unmatched and oversized messages are routine. Failed commands are
+    /// errors, so that their details reach operators who don't show them in
+    /// replies.
+    pub fn level(self) -> Level {
+        match self {
+            Self::NoMatch | Self::InputTooLarge => Level::INFO,
+            Self::Timeout => Level::WARN,
+            Self::CommandError | Self::InternalError => Level::ERROR,
+        }
+    }
+
+    /// Tells the sender what went wrong.
+    pub fn message(self, lang: Lang) -> String {
+        let key = match self {
+            Self::NoMatch => "unrecognized",
+            Self::InputTooLarge => "input-too-large",
+            Self::Timeout => "timed-out",
+            Self::CommandError => "command-failed",
+            Self::InternalError => "internal-error",
+        };
+        lang.tr(key, &[])
+    }
+
+    /// Logs `details` about a failure of `command` at the level of the category.
+    pub fn log(self, command: &str, details: &str) {
+        let category = self.name();
+        match self.level() {
+            Level::ERROR => tracing::error!(category, command, "{}", details),
+            Level::WARN => tracing::warn!(category, command, "{}", details),
+            _ => tracing::info!(category, command, "{}", details),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
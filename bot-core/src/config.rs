@@ -0,0 +1,174 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::value::{Table, Value};
+
+/// Help text describing how settings are resolved.
+pub const CONFIG_HELP: &str = "Settings are resolved in this order: command-line flags, \
+environment variables, the --config file, built-in defaults. \
+Secrets in the config file can be read from another file with a `_file` suffix \
+(e.g. password_file = \"/run/secrets/password\").";
+
+/// A TOML config file that fills in settings missing from the command line.
+///
+/// Each lookup consumes its key, so the keys left over in [`ConfigFile::finish`]
+/// are reported as unknown. Invalid and missing values are collected and
+/// reported together.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    path: Option<PathBuf>,
+    table: Table,
+    errors: Vec<String>,
+    missing: Vec<&'static str>,
+}
+
+impl ConfigFile {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let table =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Self {
+            path: Some(path.into()),
+            table,
+            ..Default::default()
+        })
+    }
+
+    /// Returns the argument if given, otherwise the value of `key`.
+    pub fn value<T>(&mut self, key: &'static str, arg: Option<T>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.table.remove(key) {
+            Some(value) if arg.is_none() => self.parse(key, &value),
+            _ => arg,
+        }
+    }
+
+    /// Like [`ConfigFile::value`], but records the key as missing if neither is set.
+    pub fn required<T>(&mut self, key: &'static str, arg: Option<T>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let errors = self.errors.len();
+        let value = self.value(key, arg);
+        if value.is_none() && self.errors.len() == errors {
+            self.missing.push(key);
+        }
+        value
+    }
+
+    /// Returns the arguments if any, otherwise the value or array of values of `key`.
+    pub fn list<T>(&mut self, key: &'static str, args: Vec<T>) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = match self.table.remove(key) {
+            Some(value) if args.is_empty() => value,
+            _ => return args,
+        };
+        match value {
+            Value::Array(values) => values
+                .iter()
+                .filter_map(|value| self.parse(key, value))
+                .collect(),
+            value => self.parse(key, &value).into_iter().collect(),
+        }
+    }
+
+    /// Like [`ConfigFile::required`], but the value may also be read from
+    /// the file named by `<key>_file`.
+    pub fn secret(&mut self, key: &'static str, arg: Option<String>) -> Option<String> {
+        let file_key = format!("{}_file", key);
+        let errors = self.errors.len();
+        let secret = match (self.table.remove(key), self.table.remove(&file_key)) {
+            _ if arg.is_some() => arg,
+            (Some(_), Some(_)) => {
+                self.errors
+                    .push(format!("{}: cannot be set together with {}", key, file_key));
+                return None;
+            }
+            (Some(value), None) => self.parse(key, &value),
+            (None, Some(value)) => self.parse::<PathBuf>(key, &value).and_then(|path| {
+                match fs::read_to_string(&path) {
+                    Ok(secret) => Some(secret.trim_end_matches(['\r', '\n']).to_string()),
+                    Err(err) => {
+                        self.errors
+                            .push(format!("{}: {}: {}", key, path.display(), err));
+                        None
+                    }
+                }
+            }),
+            (None, None) => None,
+        };
+        if secret.is_none() && self.errors.len() == errors {
+            self.missing.push(key);
+        }
+        secret
+    }
+
+    /// Consumes a key that has no effect with the other settings, warning that
+    /// it is ignored rather than unknown.
+    pub fn ignore(&mut self, key: &str, reason: &str) {
+        if let (Some(_), Some(path)) = (self.table.remove(key), &self.path) {
+            eprintln!(
+                "warning: ignoring `{}` in {} because {}",
+                key,
+                path.display(),
+                reason
+            );
+        }
+    }
+
+    /// Warns about unknown keys and fails if any value was invalid or missing.
+    pub fn finish(self) -> Result<()> {
+        if let Some(path) = &self.path {
+            for key in self.table.keys() {
+                eprintln!("warning: unknown key `{}` in {}", key, path.display());
+            }
+        }
+
+        let mut errors = self.errors;
+        if !self.missing.is_empty() {
+            errors.push(format!(
+                "missing required values: {}",
+                self.missing.join(", ")
+            ));
+        }
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  {}", errors.join("\n  "));
+        }
+        Ok(())
+    }
+
+    fn parse<T>(&mut self, key: &str, value: &Value) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let result = match value {
+            Value::String(s) => s.parse().map_err(|err| anyhow!("{}", err)),
+            Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => {
+                value.to_string().parse().map_err(|err| anyhow!("{}", err))
+            }
+            _ => Err(anyhow!("expected a string or a number")),
+        };
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.errors.push(format!("{}: {}", key, err));
+                None
+            }
+        }
+    }
+}
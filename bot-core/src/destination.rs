@@ -0,0 +1,70 @@
+use anyhow::{bail, Error};
+use std::str::FromStr;
+
+/// Where the answer to a request is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplyDestination {
+    /// The chat or channel the request came from.
+    #[default]
+    Same,
+    /// A private chat with the sender.
+    Dm,
+    /// A thread started from the request, where the platform supports it.
+    Thread,
+}
+
+impl FromStr for ReplyDestination {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same" => Ok(Self::Same),
+            "dm" => Ok(Self::Dm),
+            "thread" => Ok(Self::Thread),
+            _ => bail!(
+                "unsupported destination {:?} (expected same, dm or thread)",
+                s
+            ),
+        }
+    }
+}
+
+/// Where to send the answer to one message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// The chat the message came from.
+    InPlace,
+    /// A private chat with the sender.
+    Dm,
+    /// A new thread with the given name.
+    Thread(String),
+}
+
+/// Name of threads started from empty messages.
+const DEFAULT_THREAD_NAME: &str = "Yozuk";
+
+impl ReplyDestination {
+    /// Routes the answer to `text`, sent in a private chat if `private`.
+    ///
+    /// Private chats are answered in place, as are threads on platforms
+    /// without `threads`. Thread names are at most `max_name_length`
+    /// characters of the message.
+    pub fn route(self, private: bool, threads: bool, text: &str, max_name_length: usize) -> Route {
+        match self {
+            Self::Dm if !private => Route::Dm,
+            Self::Thread if !private && threads => {
+                let name = text
+                    .trim()
+                    .chars()
+                    .take(max_name_length)
+                    .collect::<String>();
+                if name.is_empty() {
+                    Route::Thread(DEFAULT_THREAD_NAME.into())
+                } else {
+                    Route::Thread(name)
+                }
+            }
+            _ => Route::InPlace,
+        }
+    }
+}
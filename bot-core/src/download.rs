@@ -0,0 +1,83 @@
+use crate::i18n::Lang;
+use crate::memory::Attachment;
+use anyhow::{bail, Error};
+use futures::stream::{self, StreamExt};
+use std::fmt::Display;
+use std::future::Future;
+use std::str::FromStr;
+
+/// What to do when some attachments of a message can't be downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DownloadFailure {
+    /// Ask the user to send the message again.
+    #[default]
+    Abort,
+    /// Run the command with the remaining attachments.
+    Skip,
+}
+
+impl FromStr for DownloadFailure {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "skip" => Ok(Self::Skip),
+            _ => bail!("expected \"abort\" or \"skip\", got {:?}", s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Downloaded {
+    /// Run the command, after sending the notice about skipped attachments if any.
+    Proceed {
+        attachments: Vec<Attachment>,
+        notice: Option<String>,
+    },
+    /// Reply with the message instead of running the command.
+    Abort(String),
+}
+
+/// Collects the results of downloading the named attachments of a message.
+pub fn collect_downloads<I, E>(results: I, policy: DownloadFailure, lang: Lang) -> Downloaded
+where
+    I: IntoIterator<Item = (String, Result<Attachment, E>)>,
+    E: Display,
+{
+    let mut attachments = vec![];
+    let mut failed = vec![];
+    for (name, result) in results {
+        match result {
+            Ok(attachment) => attachments.push(attachment),
+            Err(err) => {
+                tracing::warn!(attachment = %name, "download failed: {}", err);
+                failed.push(name);
+            }
+        }
+    }
+    match (failed.is_empty(), policy) {
+        (true, _) => Downloaded::Proceed {
+            attachments,
+            notice: None,
+        },
+        (false, DownloadFailure::Abort) => Downloaded::Abort(lang.tr("download-failed", &[])),
+        (false, DownloadFailure::Skip) => Downloaded::Proceed {
+            attachments,
+            notice: Some(lang.tr("attachments-skipped", &[("names", &failed.join(", "))])),
+        },
+    }
+}
+
+/// Awaits `downloads` with at most `limit` of them in flight, returning the
+/// results in their original order.
+pub async fn download_all<I>(downloads: I, limit: usize) -> Vec<<I::Item as Future>::Output>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    stream::iter(downloads)
+        .buffered(limit.max(1))
+        .collect()
+        .await
+}
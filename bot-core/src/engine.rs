@@ -0,0 +1,126 @@
+use anyhow::{anyhow, bail, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use yozuk::Yozuk;
+use yozuk_sdk::prelude::*;
+
+/// Options applied to `Yozuk::builder()` and to every request.
+///
+/// Models and skills are compiled into the `yozuk` crate, so there is no data
+/// directory to point at; skills are selected with its cargo features.
+/// The suggestion seed is random and cannot be set.
+#[derive(Debug, Clone, Default)]
+pub struct EngineOptions {
+    /// Timezone for users who haven't set their own.
+    pub timezone: Option<Timezone>,
+
+    /// Location for users, as latitude and longitude.
+    pub location: Option<Location>,
+}
+
+impl EngineOptions {
+    pub fn build(&self) -> Yozuk {
+        let mut user = UserContext::default();
+        self.apply(&mut user);
+        Yozuk::builder().set_user_context(user).build()
+    }
+
+    /// Fills in the values the frontend doesn't know about the user.
+    pub fn apply(&self, user: &mut UserContext) {
+        if user.timezone.is_none() {
+            user.timezone = self.timezone.as_ref().map(ToString::to_string);
+        }
+        if user.location.is_none() {
+            user.location = self.location.map(|loc| (loc.latitude, loc.longitude));
+        }
+    }
+}
+
+/// Query run by [`selftest`], with a result that doesn't depend on the
+/// environment.
+pub const SELFTEST_QUERY: &str = "\"Hello World!\" to md5";
+const SELFTEST_EXPECTED: &str = "ed076287532e86365e841e92bfc50d8c";
+
+/// Runs a known command end-to-end and checks its output, to catch a broken
+/// build before any user does.
+pub fn selftest(zuk: &Yozuk) -> Result<()> {
+    let tokens = Tokenizer::new().tokenize(SELFTEST_QUERY);
+    let commands = zuk.get_commands(&tokens, &[]);
+    if commands.is_empty() {
+        bail!("Self-test failed: no command matched {:?}", SELFTEST_QUERY);
+    }
+    let outputs = zuk
+        .run_commands(commands, &mut [], None)
+        .map_err(|outputs| anyhow!("Self-test failed: the command failed: {:?}", outputs))?;
+    let found = outputs
+        .iter()
+        .flat_map(|output| &output.blocks)
+        .any(|block| match block {
+            Block::Data(data) => data.data.as_ref() == SELFTEST_EXPECTED.as_bytes(),
+            _ => false,
+        });
+    if !found {
+        bail!(
+            "Self-test failed: expected {:?} in the output of {:?}, got {:?}",
+            SELFTEST_EXPECTED,
+            SELFTEST_QUERY,
+            outputs
+        );
+    }
+    tracing::info!("self-test passed");
+    Ok(())
+}
+
+/// A timezone name like `UTC` or `America/Argentina/Buenos_Aires`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timezone(String);
+
+impl FromStr for Timezone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = s.len() <= 64
+            && s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '+' | '-'));
+        if !valid {
+            bail!(
+                "invalid timezone {:?} (expected a name like Europe/Berlin)",
+                s
+            );
+        }
+        Ok(Self(s.into()))
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Coordinates written as `<latitude>,<longitude>` in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl FromStr for Location {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (latitude, longitude) = s
+            .split_once(',')
+            .ok_or_else(|| anyhow!("expected <latitude>,<longitude>, got {:?}", s))?;
+        let latitude: f64 = latitude.trim().parse()?;
+        let longitude: f64 = longitude.trim().parse()?;
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            bail!("coordinates out of range: {:?}", s);
+        }
+        Ok(Self {
+            latitude,
+            longitude,
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    /// The sender hasn't been told how to get access yet.
+    Instruct,
+    /// The sender has already been told.
+    Ignore,
+}
+
+/// Restricts the bot to verified contacts and an allow-list of addresses.
+///
+/// Addresses are compared case-insensitively. Each rejected sender is only
+/// instructed once per process.
+#[derive(Debug, Default)]
+pub struct ContactGate {
+    require_verified: bool,
+    allowed: HashSet<String>,
+    instructed: Mutex<HashSet<String>>,
+}
+
+impl ContactGate {
+    pub fn new<I, S>(require_verified: bool, allowed: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            require_verified,
+            allowed: allowed
+                .into_iter()
+                .map(|addr| addr.as_ref().to_lowercase())
+                .collect(),
+            instructed: Default::default(),
+        }
+    }
+
+    /// Whether anyone may be turned away, so that callers can skip looking
+    /// up the verification status.
+    pub fn is_open(&self) -> bool {
+        !self.require_verified && self.allowed.is_empty()
+    }
+
+    pub fn check(&self, addr: &str, verified: bool) -> Admission {
+        let addr = addr.to_lowercase();
+        if self.is_open() || self.allowed.contains(&addr) || (self.require_verified && verified) {
+            // Losing access later should bring the instructions back.
+            self.instructed.lock().unwrap().remove(&addr);
+            return Admission::Allowed;
+        }
+        if self.instructed.lock().unwrap().insert(addr) {
+            Admission::Instruct
+        } else {
+            Admission::Ignore
+        }
+    }
+}
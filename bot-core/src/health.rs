@@ -0,0 +1,111 @@
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Liveness and readiness of a frontend for container orchestration.
+///
+/// The frontend reports when its platform connection is established and
+/// whenever it sees an event from the platform. It is considered ready only
+/// while connected and the last event is more recent than `stale_after`.
+pub struct Health {
+    started: Instant,
+    stale_after: Duration,
+    connected: AtomicBool,
+    /// Unix time of the last platform event in milliseconds, 0 if none.
+    last_event: AtomicU64,
+}
+
+impl Health {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            stale_after,
+            connected: AtomicBool::new(false),
+            last_event: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+        if connected {
+            self.event();
+        }
+    }
+
+    /// Records that the platform connection is alive.
+    pub fn event(&self) {
+        self.last_event.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        let last_event = self.last_event.load(Ordering::Relaxed);
+        self.connected.load(Ordering::Relaxed)
+            && last_event > 0
+            && unix_millis().saturating_sub(last_event) < self.stale_after.as_millis() as u64
+    }
+
+    /// Serves /live and /ready on `addr` in the background.
+    pub fn serve(self: &Arc<Self>, addr: SocketAddr) -> Result<()> {
+        let health = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let health = health.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let health = health.clone();
+                    async move { Ok::<_, Infallible>(health.respond(req)) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                tracing::error!("health server error: {}", err);
+            }
+        });
+        Ok(())
+    }
+
+    fn respond(&self, req: Request<Body>) -> Response<Body> {
+        let ok = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/live") => true,
+            (&Method::GET, "/ready") => self.is_ready(),
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+        let last_event = match self.last_event.load(Ordering::Relaxed) {
+            0 => "null".to_string(),
+            time => (time / 1000).to_string(),
+        };
+        let body = format!(
+            r#"{{"status":"{}","uptime_secs":{},"last_event":{}}}"#,
+            if ok { "ok" } else { "unavailable" },
+            self.started.elapsed().as_secs(),
+            last_event
+        );
+        Response::builder()
+            .status(if ok {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            })
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .unwrap()
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,36 @@
+use crate::i18n::{Lang, DOCS_URL};
+use yozuk::{Yozuk, SKILLS};
+
+const TRIGGERS: &[&str] = &["help", "?", "/help"];
+const EXAMPLES: usize = 5;
+
+/// Whether `text` asks for [`help_text`].
+pub fn is_help(text: &str) -> bool {
+    let text = text.trim();
+    TRIGGERS
+        .iter()
+        .any(|trigger| text.eq_ignore_ascii_case(trigger))
+}
+
+/// Lists the skills compiled into this build along with a few example commands.
+pub fn help_text(zuk: &Yozuk, lang: Lang) -> String {
+    let skills = SKILLS
+        .iter()
+        .map(|skill| skill.key.trim_start_matches("yozuk-skill-"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut text = lang.tr("help-skills", &[("skills", &skills)]);
+    let examples = zuk.random_suggestions(EXAMPLES);
+    if !examples.is_empty() {
+        text.push_str(&format!("\n\n{}", lang.tr("help-examples", &[])));
+        for example in examples {
+            text.push_str(&format!("\n- {}", example));
+        }
+    }
+    text.push_str(&format!(
+        "\n\n{}",
+        lang.tr("help-docs", &[("url", DOCS_URL)])
+    ));
+    text
+}
@@ -0,0 +1,484 @@
+use anyhow::{anyhow, bail, Error};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const DOCS_URL: &str = "https://docs.yozuk.com/";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+    Ja,
+}
+
+impl Lang {
+    /// Picks the language of a locale such as `de-DE` or `ja_JP`.
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let lang = locale.split(['-', '_']).next().unwrap_or_default();
+        lang.parse().ok()
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::De => "de",
+            Self::Ja => "ja",
+        }
+    }
+
+    /// Looks up a canned string, falling back to English and then to the key itself.
+    ///
+    /// `{name}` placeholders are replaced with the matching arguments.
+    pub fn tr(&self, key: &str, args: &[(&str, &str)]) -> String {
+        translate(self.table(), key, args)
+    }
+
+    fn table(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::En => EN,
+            Self::De => DE,
+            Self::Ja => JA,
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "en" => Ok(Self::En),
+            "de" => Ok(Self::De),
+            "ja" => Ok(Self::Ja),
+            _ => bail!("unsupported language {:?} (expected en, de or ja)", s),
+        }
+    }
+}
+
+/// A locale pinned to a channel or contact, written as `<id>=<locale>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocaleOverride {
+    pub id: String,
+    pub lang: Lang,
+}
+
+impl FromStr for LocaleOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, locale) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <id>=<locale>, got {:?}", s))?;
+        let lang = Lang::from_locale(locale.trim())
+            .ok_or_else(|| anyhow!("unsupported language {:?} (expected en, de or ja)", locale))?;
+        Ok(Self {
+            id: id.trim().into(),
+            lang,
+        })
+    }
+}
+
+/// Languages pinned to channels or contacts by the operator.
+#[derive(Debug, Clone, Default)]
+pub struct LocaleOverrides(HashMap<String, Lang>);
+
+impl FromIterator<LocaleOverride> for LocaleOverrides {
+    fn from_iter<I: IntoIterator<Item = LocaleOverride>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|o| (o.id, o.lang)).collect())
+    }
+}
+
+impl LocaleOverrides {
+    /// Picks the language of a request: the locale hint of the sender if it
+    /// is supported, then the override of the first of `ids` that has one,
+    /// then `default`.
+    pub fn resolve<'a, I>(&self, hint: Option<&str>, ids: I, default: Lang) -> Lang
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        hint.and_then(Lang::from_locale)
+            .or_else(|| ids.into_iter().find_map(|id| self.0.get(id).copied()))
+            .unwrap_or(default)
+    }
+}
+
+/// Looks up `key` in `table` the way [`Lang::tr`] does, falling back to
+/// English and then to the key itself.
+pub fn translate(table: &[(&str, &str)], key: &str, args: &[(&str, &str)]) -> String {
+    let mut text = lookup(table, key)
+        .or_else(|| lookup(EN, key))
+        .unwrap_or(key)
+        .to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+fn lookup<'a>(table: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    table
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, text)| *text)
+}
+
+const EN: &[(&str, &str)] = &[
+    ("unrecognized", "Sorry, I can't understand your request."),
+    ("did-you-mean", "Did you mean"),
+    ("hint", "Hint"),
+    (
+        "docs-hint",
+        "Please refer [Documentation]({url}) for available commands.",
+    ),
+    ("no-output", "The command completed but produced no displayable output."),
+    ("too-large", "Too large file input ({max} max.)"),
+    (
+        "rate-limited",
+        "You're sending requests too quickly. Please try again in {seconds} seconds.",
+    ),
+    (
+        "decrypt-failure",
+        "Sorry, I can't decrypt your message. Please send the message again.",
+    ),
+    (
+        "upload-unavailable",
+        "Sorry, I couldn't deliver the file {name} ({size} bytes, {type}) because file upload is not available.",
+    ),
+    ("help-skills", "Available skills: {skills}"),
+    ("help-examples", "Try:"),
+    ("help-docs", "Documentation: {url}"),
+    ("forgotten", "OK, I've forgotten our conversation."),
+    ("dry-run", "Dry run: nothing was executed. These commands would run:"),
+    ("language-set", "Language set to {lang}."),
+    (
+        "unsupported-language",
+        "Unsupported language {lang} (available: en, de, ja).",
+    ),
+    ("timezone-set", "Timezone set to {timezone}."),
+    (
+        "invalid-timezone",
+        "Invalid timezone {timezone}. Use a name like Europe/Berlin.",
+    ),
+    ("timed-out", "This command took too long and was cancelled."),
+    (
+        "internal-error",
+        "Sorry, something went wrong while running this command.",
+    ),
+    ("binary-omitted", "(binary output, {size} bytes, {type}) — omitted"),
+    (
+        "download-failed",
+        "I couldn't download one of your attachments, please retry.",
+    ),
+    (
+        "attachments-skipped",
+        "Skipped attachments that couldn't be downloaded: {names}",
+    ),
+    (
+        "files-omitted",
+        "({count} files omitted: I need the Attach Files permission here)",
+    ),
+    (
+        "requested-in",
+        "You asked for this in {channel}, where I can't send messages.",
+    ),
+    ("mode-set", "Output mode set to {mode}."),
+    ("unknown-mode", "Unknown mode \"{mode}\". Valid modes: {modes}"),
+    (
+        "verification-required",
+        "I only answer verified contacts. To verify me, scan or paste this invite with \"Scan QR code\" in Delta Chat:\n{invite}",
+    ),
+    (
+        "retention-set",
+        "My replies in this channel will be deleted after {retention}.",
+    ),
+    ("retention-off", "My replies in this channel will be kept."),
+    (
+        "invalid-retention",
+        "Invalid retention \"{retention}\". Use e.g. 30m, 24h, 7d or off.",
+    ),
+    (
+        "settings-unavailable",
+        "Settings can't be stored because I have no data directory.",
+    ),
+    (
+        "config-forbidden",
+        "Only members who can manage the server may change my settings.",
+    ),
+    (
+        "file-truncated",
+        "(`{name}` cut off, {size} bytes in total: I need the Attach Files permission here)",
+    ),
+    (
+        "requested-in-slow-mode",
+        "You asked for this in {channel}, where slow mode keeps me from sending long replies.",
+    ),
+    (
+        "file-too-large",
+        "(`{name}` omitted: at {size} bytes it is too large to upload here)",
+    ),
+    ("blocks-truncated", "…(truncated, {count} more)"),
+    ("working", "Working on it…"),
+    (
+        "stats-forbidden",
+        "You are not authorized to see my statistics.",
+    ),
+    (
+        "archive-file-count",
+        "Please send an archive that contains exactly one file.",
+    ),
+    (
+        "archive-too-large",
+        "The file in this archive is larger than {size} bytes, so I didn't extract it.",
+    ),
+    ("archive-invalid", "I couldn't read this archive."),
+    (
+        "input-too-large",
+        "Your input is too large for me to process.",
+    ),
+    ("command-failed", "The command couldn't process your input."),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("unrecognized", "Entschuldigung, ich verstehe deine Anfrage nicht."),
+    ("did-you-mean", "Meintest du"),
+    ("hint", "Hinweis"),
+    (
+        "docs-hint",
+        "Die verfügbaren Befehle findest du in der [Dokumentation]({url}).",
+    ),
+    (
+        "no-output",
+        "Der Befehl wurde ausgeführt, hat aber keine darstellbare Ausgabe erzeugt.",
+    ),
+    ("too-large", "Die Eingabedatei ist zu groß (max. {max})."),
+    (
+        "rate-limited",
+        "Du sendest zu viele Anfragen. Bitte versuche es in {seconds} Sekunden erneut.",
+    ),
+    (
+        "decrypt-failure",
+        "Entschuldigung, ich kann deine Nachricht nicht entschlüsseln. Bitte sende sie erneut.",
+    ),
+    (
+        "upload-unavailable",
+        "Entschuldigung, die Datei {name} ({size} Bytes, {type}) konnte nicht zugestellt werden, da kein Datei-Upload verfügbar ist.",
+    ),
+    ("help-skills", "Verfügbare Skills: {skills}"),
+    ("help-examples", "Probiere:"),
+    ("help-docs", "Dokumentation: {url}"),
+    ("forgotten", "OK, ich habe unser Gespräch vergessen."),
+    (
+        "dry-run",
+        "Testlauf: Es wurde nichts ausgeführt. Diese Befehle würden laufen:",
+    ),
+    ("language-set", "Sprache auf {lang} gesetzt."),
+    (
+        "unsupported-language",
+        "Nicht unterstützte Sprache {lang} (verfügbar: en, de, ja).",
+    ),
+    ("timezone-set", "Zeitzone auf {timezone} gesetzt."),
+    (
+        "invalid-timezone",
+        "Ungültige Zeitzone {timezone}. Verwende einen Namen wie Europe/Berlin.",
+    ),
+    (
+        "timed-out",
+        "Dieser Befehl hat zu lange gedauert und wurde abgebrochen.",
+    ),
+    (
+        "internal-error",
+        "Entschuldigung, beim Ausführen dieses Befehls ist ein Fehler aufgetreten.",
+    ),
+    (
+        "binary-omitted",
+        "(Binärausgabe, {size} Bytes, {type}) — ausgelassen",
+    ),
+    (
+        "download-failed",
+        "Ich konnte einen deiner Anhänge nicht herunterladen, bitte versuche es erneut.",
+    ),
+    (
+        "attachments-skipped",
+        "Nicht herunterladbare Anhänge wurden übersprungen: {names}",
+    ),
+    (
+        "files-omitted",
+        "({count} Dateien ausgelassen: Ich brauche hier die Berechtigung „Dateien anhängen“)",
+    ),
+    (
+        "requested-in",
+        "Du hast das in {channel} angefragt, wo ich keine Nachrichten senden kann.",
+    ),
+    ("mode-set", "Ausgabemodus auf {mode} gesetzt."),
+    ("unknown-mode", "Unbekannter Modus „{mode}“. Gültige Modi: {modes}"),
+    (
+        "verification-required",
+        "Ich antworte nur verifizierten Kontakten. Um mich zu verifizieren, scanne diese Einladung oder füge sie mit „QR-Code scannen“ in Delta Chat ein:\n{invite}",
+    ),
+    (
+        "retention-set",
+        "Meine Antworten in diesem Kanal werden nach {retention} gelöscht.",
+    ),
+    (
+        "retention-off",
+        "Meine Antworten in diesem Kanal werden behalten.",
+    ),
+    (
+        "invalid-retention",
+        "Ungültige Aufbewahrungsdauer „{retention}“. Verwende z. B. 30m, 24h, 7d oder off.",
+    ),
+    (
+        "settings-unavailable",
+        "Einstellungen können nicht gespeichert werden, da ich kein Datenverzeichnis habe.",
+    ),
+    (
+        "config-forbidden",
+        "Nur Mitglieder, die den Server verwalten dürfen, können meine Einstellungen ändern.",
+    ),
+    (
+        "file-truncated",
+        "(`{name}` gekürzt, insgesamt {size} Bytes: Ich brauche hier die Berechtigung „Dateien anhängen“)",
+    ),
+    (
+        "requested-in-slow-mode",
+        "Du hast das in {channel} angefragt, wo mich der Slowmode keine langen Antworten senden lässt.",
+    ),
+    (
+        "file-too-large",
+        "(`{name}` ausgelassen: Mit {size} Bytes ist die Datei zu groß, um sie hier hochzuladen)",
+    ),
+    ("blocks-truncated", "…(gekürzt, {count} weitere)"),
+    ("working", "Ich arbeite daran…"),
+    (
+        "stats-forbidden",
+        "Du bist nicht berechtigt, meine Statistiken zu sehen.",
+    ),
+    (
+        "archive-file-count",
+        "Bitte sende ein Archiv, das genau eine Datei enthält.",
+    ),
+    (
+        "archive-too-large",
+        "Die Datei in diesem Archiv ist größer als {size} Bytes, daher habe ich sie nicht entpackt.",
+    ),
+    ("archive-invalid", "Ich konnte dieses Archiv nicht lesen."),
+    (
+        "input-too-large",
+        "Deine Eingabe ist zu groß, um sie zu verarbeiten.",
+    ),
+    (
+        "command-failed",
+        "Der Befehl konnte deine Eingabe nicht verarbeiten.",
+    ),
+];
+
+const JA: &[(&str, &str)] = &[
+    ("unrecognized", "すみません、リクエストを理解できませんでした。"),
+    ("did-you-mean", "もしかして"),
+    ("hint", "ヒント"),
+    (
+        "docs-hint",
+        "利用できるコマンドは[ドキュメント]({url})を参照してください。",
+    ),
+    ("no-output", "コマンドは完了しましたが、表示できる出力はありません。"),
+    ("too-large", "入力ファイルが大きすぎます（最大 {max}）"),
+    (
+        "rate-limited",
+        "リクエストが多すぎます。{seconds} 秒後にもう一度お試しください。",
+    ),
+    (
+        "decrypt-failure",
+        "すみません、メッセージを復号できませんでした。もう一度送信してください。",
+    ),
+    (
+        "upload-unavailable",
+        "ファイルアップロードが利用できないため、ファイル {name}（{size} バイト、{type}）を送信できませんでした。",
+    ),
+    ("help-skills", "利用できるスキル: {skills}"),
+    ("help-examples", "例:"),
+    ("help-docs", "ドキュメント: {url}"),
+    ("forgotten", "会話の内容を忘れました。"),
+    ("dry-run", "ドライラン: 何も実行していません。次のコマンドが実行されます:"),
+    ("language-set", "言語を {lang} に設定しました。"),
+    (
+        "unsupported-language",
+        "{lang} はサポートされていない言語です（利用可能: en, de, ja）。",
+    ),
+    ("timezone-set", "タイムゾーンを {timezone} に設定しました。"),
+    (
+        "invalid-timezone",
+        "{timezone} は無効なタイムゾーンです。Asia/Tokyo のような名前を指定してください。",
+    ),
+    ("timed-out", "コマンドの実行に時間がかかりすぎたため中止しました。"),
+    (
+        "internal-error",
+        "すみません、コマンドの実行中にエラーが発生しました。",
+    ),
+    ("binary-omitted", "（バイナリ出力、{size} バイト、{type}）— 省略"),
+    (
+        "download-failed",
+        "添付ファイルの一部をダウンロードできませんでした。もう一度お試しください。",
+    ),
+    (
+        "attachments-skipped",
+        "ダウンロードできなかった添付ファイルをスキップしました: {names}",
+    ),
+    (
+        "files-omitted",
+        "（ファイル {count} 件を省略しました: このチャンネルでは「ファイルを添付」権限が必要です）",
+    ),
+    (
+        "requested-in",
+        "{channel} でのリクエストですが、そこではメッセージを送信できません。",
+    ),
+    ("mode-set", "出力モードを {mode} に設定しました。"),
+    ("unknown-mode", "不明なモード「{mode}」です。有効なモード: {modes}"),
+    (
+        "verification-required",
+        "認証済みの連絡先にのみ応答します。Delta Chat の「QR コードをスキャン」でこの招待をスキャンまたは貼り付けて認証してください:\n{invite}",
+    ),
+    (
+        "retention-set",
+        "このチャンネルでの私の返信は {retention} 後に削除されます。",
+    ),
+    ("retention-off", "このチャンネルでの私の返信は保持されます。"),
+    (
+        "invalid-retention",
+        "保持期間「{retention}」は無効です。30m、24h、7d、off のように指定してください。",
+    ),
+    (
+        "settings-unavailable",
+        "データディレクトリがないため、設定を保存できません。",
+    ),
+    (
+        "config-forbidden",
+        "設定を変更できるのはサーバー管理権限を持つメンバーだけです。",
+    ),
+    (
+        "file-truncated",
+        "（`{name}` を途中で切りました。全体で {size} バイトです: このチャンネルでは「ファイルを添付」権限が必要です）",
+    ),
+    (
+        "requested-in-slow-mode",
+        "{channel} でのリクエストですが、そこではスローモードのため長い返信を送信できません。",
+    ),
+    (
+        "file-too-large",
+        "（`{name}` を省略しました: {size} バイトあり、ここにアップロードするには大きすぎます）",
+    ),
+    ("blocks-truncated", "…（省略、ほか {count} 件）"),
+    ("working", "処理中です…"),
+    ("stats-forbidden", "統計を表示する権限がありません。"),
+    (
+        "archive-file-count",
+        "ファイルを1つだけ含むアーカイブを送信してください。",
+    ),
+    (
+        "archive-too-large",
+        "このアーカイブ内のファイルは {size} バイトを超えているため、展開しませんでした。",
+    ),
+    ("archive-invalid", "このアーカイブを読み込めませんでした。"),
+    ("input-too-large", "入力が大きすぎて処理できません。"),
+    ("command-failed", "コマンドは入力を処理できませんでした。"),
+];
@@ -0,0 +1,61 @@
+mod access;
+mod address;
+mod analytics;
+mod archive;
+mod bundle;
+mod cache;
+mod category;
+mod config;
+mod destination;
+mod download;
+mod engine;
+mod gate;
+mod health;
+mod help;
+mod i18n;
+mod logging;
+mod mail;
+mod memory;
+mod metrics;
+mod paginate;
+mod plan;
+mod preprocess;
+mod progress;
+mod rate_limit;
+mod reaction;
+mod redact;
+mod retention;
+mod retry;
+mod settings;
+mod transport;
+
+pub use access::*;
+pub use address::*;
+pub use analytics::*;
+pub use archive::*;
+pub use cache::*;
+pub use category::*;
+pub use config::*;
+pub use destination::*;
+pub use download::*;
+pub use engine::*;
+pub use gate::*;
+pub use health::*;
+pub use help::*;
+pub use i18n::*;
+pub use logging::*;
+pub use mail::*;
+pub use memory::*;
+pub use metrics::*;
+pub use paginate::*;
+pub use plan::*;
+pub use preprocess::*;
+pub use progress::*;
+pub use rate_limit::*;
+pub use reaction::*;
+pub use redact::*;
+pub use retention::*;
+pub use retry::*;
+pub use settings::*;
+pub use transport::*;
+pub use yozuk_prefs::{Preferences, UserPrefs};
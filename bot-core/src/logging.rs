@@ -0,0 +1,48 @@
+use anyhow::{anyhow, bail, Error, Result};
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_FILTER: &str = "info";
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines for a terminal.
+    #[default]
+    Pretty,
+    /// One JSON object per line for log aggregation.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => bail!("unsupported log format {:?} (expected pretty or json)", s),
+        }
+    }
+}
+
+/// Installs the global logger, filtered by `RUST_LOG` (default: `info`).
+///
+/// Records of the `log` crate are forwarded as well. In JSON mode, the fields
+/// of the enclosing spans are included in each line.
+pub fn init_logging(format: LogFormat) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .try_init(),
+    }
+    .map_err(|err| anyhow!(err))
+}
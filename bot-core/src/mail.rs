@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Error};
+use regex::Regex;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// Credentials of a mail account, written as
+/// `mail=<address>,password_env=<variable>` so that the password itself
+/// stays out of the command line and the config file.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MailAccount {
+    pub mail: String,
+    pub password: String,
+}
+
+impl fmt::Debug for MailAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MailAccount")
+            .field("mail", &self.mail)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for MailAccount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mail = None;
+        let mut password_env = None;
+        for field in s.split(',') {
+            match field.trim().split_once('=') {
+                Some(("mail", value)) => mail = Some(value.trim()),
+                Some(("password_env", value)) => password_env = Some(value.trim()),
+                _ => return Err(anyhow!("unexpected {:?} in {:?}", field, s)),
+            }
+        }
+        let (mail, password_env) = mail.zip(password_env).ok_or_else(|| {
+            anyhow!(
+                "expected mail=<address>,password_env=<variable>, got {:?}",
+                s
+            )
+        })?;
+        let password = env::var(password_env)
+            .map_err(|_| anyhow!("{} is not set for {}", password_env, mail))?;
+        Ok(Self {
+            mail: mail.into(),
+            password,
+        })
+    }
+}
+
+/// Extracts the command from the HTML part of an email, dropping quoted
+/// replies and the signature.
+pub fn html_to_text(html: &str) -> String {
+    let hidden =
+        Regex::new(r"(?is)<(?:head|style|script)\b.*?</(?:head|style|script)\s*>").unwrap();
+    let quotes = Regex::new(r"(?is)<blockquote\b.*?</blockquote\s*>").unwrap();
+    let whitespace = Regex::new(r"\s+").unwrap();
+    let breaks = Regex::new(r"(?i)<(?:br|/?p|/?div|/?li|/?tr|/?h[1-6])\b[^>]*>").unwrap();
+    let tags = Regex::new(r"(?s)<[^>]*>").unwrap();
+
+    let text = hidden.replace_all(html, "");
+    let text = quotes.replace_all(&text, "");
+    // Line breaks in the source are just whitespace; only tags break lines.
+    let text = whitespace.replace_all(&text, " ");
+    let text = breaks.replace_all(&text, "\n");
+    let text = tags.replace_all(&text, "");
+    let text = decode_entities(&text);
+    let lines = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+    strip_reply(&lines.join("\n"))
+}
+
+/// Removes quoted lines and everything after a signature delimiter.
+pub fn strip_reply(text: &str) -> String {
+    text.lines()
+        .take_while(|line| line.trim_end() != "--")
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Removes the framing mail clients put around forwarded messages: the
+/// "Forwarded message" marker and the header block following it.
+pub fn strip_forward_header(text: &str) -> String {
+    let marker = Regex::new(
+        r"(?i)^\s*(?:-+\s*(?:forwarded|original) message\s*-+|begin forwarded message:)\s*$",
+    )
+    .unwrap();
+    let header = Regex::new(r"(?i)^\s*(?:from|to|cc|date|sent|subject|reply-to):(?:\s|$)").unwrap();
+    let mut lines = vec![];
+    let mut in_header = false;
+    let mut fields = false;
+    for line in text.lines() {
+        if marker.is_match(line) {
+            in_header = true;
+            fields = false;
+        } else if in_header && header.is_match(line) {
+            fields = true;
+        } else if in_header && line.trim().is_empty() {
+            // A blank line ends the header block once it has any fields.
+            in_header = !fields;
+        } else {
+            in_header = false;
+            lines.push(line);
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+fn decode_entities(text: &str) -> String {
+    let entity = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    entity
+        .replace_all(text, |caps: &regex::Captures| {
+            let name = &caps[1];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => name
+                        .strip_prefix('#')
+                        .and_then(|dec| dec.parse().ok())
+                        .and_then(char::from_u32),
+                },
+            };
+            c.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
@@ -0,0 +1,254 @@
+use crate::plan::{
+    plan_apology, plan_commands, raw_request, user_lang, Limits, PlanItem, ResponsePlan,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use yozuk::Yozuk;
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+
+/// Number of chats remembered at once; the least recently used one is dropped first.
+const MAX_CHATS: usize = 1000;
+
+/// Attachment bytes kept for all chats together by default.
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Longest command text in bytes that is remembered, so that follow-ups
+/// can't make it grow without bound.
+const MAX_TEXT_LENGTH: usize = 2000;
+
+const RESET_WORDS: &[&str] = &["forget", "reset"];
+
+/// An input file kept in memory so that it can be read more than once.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub data: Bytes,
+    pub media_type: MediaTypeBuf,
+
+    /// File name given by the sender, if the platform has one.
+    pub name: Option<String>,
+
+    /// File read on every use instead of `data`.
+    path: Option<PathBuf>,
+}
+
+impl Attachment {
+    pub fn new<T: Into<Bytes>>(data: T, media_type: MediaTypeBuf) -> Self {
+        Self {
+            data: data.into(),
+            media_type,
+            name: None,
+            path: None,
+        }
+    }
+
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Creates an attachment that is streamed from `path` rather than loaded
+    /// into memory.
+    ///
+    /// The file is opened by [`Attachment::stream`] and stays readable through
+    /// the returned stream, so it only has to exist until then.
+    pub fn from_file<P: Into<PathBuf>>(path: P, media_type: MediaTypeBuf) -> Self {
+        Self {
+            data: Bytes::new(),
+            media_type,
+            name: None,
+            path: Some(path.into()),
+        }
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn stream(&self) -> InputStream {
+        let media_type = self.media_type.clone();
+        match &self.path {
+            Some(path) => match File::open(path) {
+                Ok(file) => InputStream::new(BufReader::new(file), media_type),
+                Err(err) => InputStream::new(Unreadable(Some(err.kind())), media_type),
+            },
+            None => InputStream::new(Cursor::new(self.data.clone()), media_type),
+        }
+    }
+}
+
+/// Fails the first read and then appears empty, since some skills retry
+/// reads that fail.
+struct Unreadable(Option<io::ErrorKind>);
+
+impl Read for Unreadable {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.take() {
+            Some(kind) => Err(kind.into()),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Remembers the last command and attachments of each chat for a short time,
+/// so that follow-up messages like "and in miles?" can be understood.
+///
+/// Everything is kept in memory only.
+pub struct ConversationMemory<K> {
+    ttl: Duration,
+    max_bytes: usize,
+    chats: Mutex<HashMap<K, Entry>>,
+}
+
+struct Entry {
+    text: String,
+    attachments: Vec<Attachment>,
+    size: usize,
+    updated: Instant,
+}
+
+impl<K: Hash + Eq + Clone> ConversationMemory<K> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_bytes: MAX_BYTES,
+            chats: Default::default(),
+        }
+    }
+
+    /// Keeps at most `max` bytes of attachments, dropping the least recently
+    /// used chats first. Chats whose attachments alone exceed it are not
+    /// remembered at all.
+    pub fn with_max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = max;
+        self
+    }
+
+    /// Number of remembered chats, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        self.chats.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn forget(&self, key: &K) {
+        self.chats.lock().unwrap().remove(key);
+    }
+
+    /// Like [`crate::plan_response`], but retries an unrecognized message once
+    /// with the previous command of the chat prepended.
+    pub fn plan(
+        &self,
+        key: K,
+        zuk: &Yozuk,
+        text: &str,
+        attachments: Vec<Attachment>,
+        user: &UserContext,
+        limits: &Limits,
+    ) -> ResponsePlan {
+        if RESET_WORDS
+            .iter()
+            .any(|word| text.trim().eq_ignore_ascii_case(word))
+        {
+            self.forget(&key);
+            return ResponsePlan {
+                items: vec![PlanItem::Text(user_lang(user).tr("forgotten", &[]))],
+                ..Default::default()
+            };
+        }
+
+        let raw = raw_request(text, limits);
+        let (text, limits) = match &raw {
+            Some((text, limits)) => (text.as_str(), limits),
+            None => (text, limits),
+        };
+
+        let streams = open_streams(&attachments);
+        let tokens = Tokenizer::new().tokenize(text);
+        let commands = zuk.get_commands(&tokens, &streams);
+        if !commands.is_empty() {
+            self.remember(key, text.into(), attachments);
+            return plan_commands(zuk, commands, streams, user, limits);
+        }
+
+        if let Some((prev_text, prev_attachments)) = self.recall(&key) {
+            let text = format!("{} {}", prev_text, text);
+            let attachments = if attachments.is_empty() {
+                prev_attachments
+            } else {
+                attachments
+            };
+            let streams = open_streams(&attachments);
+            let commands = zuk.get_commands(&Tokenizer::new().tokenize(&text), &streams);
+            if !commands.is_empty() {
+                self.remember(key, text, attachments);
+                return plan_commands(zuk, commands, streams, user, limits);
+            }
+        }
+
+        plan_apology(zuk, &tokens, &streams, limits)
+    }
+
+    fn recall(&self, key: &K) -> Option<(String, Vec<Attachment>)> {
+        let chats = self.chats.lock().unwrap();
+        chats
+            .get(key)
+            .filter(|entry| entry.updated.elapsed() < self.ttl)
+            .map(|entry| (entry.text.clone(), entry.attachments.clone()))
+    }
+
+    fn remember(&self, key: K, text: String, attachments: Vec<Attachment>) {
+        let mut chats = self.chats.lock().unwrap();
+        let ttl = self.ttl;
+        chats.remove(&key);
+        chats.retain(|_, entry| entry.updated.elapsed() < ttl);
+
+        // Streamed attachments are read from their files again.
+        let size = attachments
+            .iter()
+            .map(|attachment| attachment.data.len())
+            .sum::<usize>();
+        if text.len() > MAX_TEXT_LENGTH || size > self.max_bytes {
+            return;
+        }
+        while chats.len() >= MAX_CHATS
+            || chats.values().map(|entry| entry.size).sum::<usize>() + size > self.max_bytes
+        {
+            let oldest = chats
+                .iter()
+                .min_by_key(|(_, entry)| entry.updated)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => chats.remove(&oldest),
+                None => break,
+            };
+        }
+        chats.insert(
+            key,
+            Entry {
+                text,
+                attachments,
+                size,
+                updated: Instant::now(),
+            },
+        );
+    }
+}
+
+fn open_streams(attachments: &[Attachment]) -> Vec<InputStream> {
+    attachments
+        .iter()
+        .map(|attachment| {
+            let mut stream = attachment.stream();
+            let _ = stream.read_header();
+            stream
+        })
+        .collect()
+}
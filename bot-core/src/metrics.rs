@@ -0,0 +1,311 @@
+use crate::plan::{PlanStatus, ResponsePlan};
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Commands beyond this many distinct names are labeled as "other".
+const MAX_COMMAND_LABELS: usize = 50;
+
+/// Prometheus metrics shared by the frontends.
+pub struct Metrics {
+    platform: &'static str,
+    started: Instant,
+    registry: Registry,
+    commands: Mutex<HashSet<String>>,
+    messages_received: IntCounterVec,
+    commands_executed: IntCounterVec,
+    command_errors: IntCounterVec,
+    unrecognized_commands: IntCounterVec,
+    send_failures: IntCounterVec,
+    cache_lookups: IntCounterVec,
+    command_duration: HistogramVec,
+    attachment_bytes: HistogramVec,
+    in_flight: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new(platform: &'static str) -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let histogram = |opts: HistogramOpts, labels: &[&str]| {
+            let histogram = HistogramVec::new(opts, labels).unwrap();
+            registry.register(Box::new(histogram.clone())).unwrap();
+            histogram
+        };
+        let in_flight = IntGaugeVec::new(
+            Opts::new("yozuk_in_flight_handlers", "Messages being handled"),
+            &["platform"],
+        )
+        .unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+
+        let metrics = Self {
+            platform,
+            started: Instant::now(),
+            commands: Default::default(),
+            messages_received: counter(
+                "yozuk_messages_received_total",
+                "Messages addressed to the bot",
+                &["platform"],
+            ),
+            commands_executed: counter(
+                "yozuk_commands_executed_total",
+                "Commands executed",
+                &["platform", "command"],
+            ),
+            command_errors: counter(
+                "yozuk_command_errors_total",
+                "Commands that returned an error",
+                &["platform", "command"],
+            ),
+            unrecognized_commands: counter(
+                "yozuk_unrecognized_commands_total",
+                "Messages that matched no command",
+                &["platform"],
+            ),
+            send_failures: counter(
+                "yozuk_send_failures_total",
+                "Responses that could not be delivered",
+                &["platform"],
+            ),
+            cache_lookups: counter(
+                "yozuk_result_cache_lookups_total",
+                "Result cache lookups by result (hit or miss)",
+                &["platform", "result"],
+            ),
+            command_duration: histogram(
+                HistogramOpts::new(
+                    "yozuk_command_duration_seconds",
+                    "Command execution duration",
+                ),
+                &["platform", "command"],
+            ),
+            attachment_bytes: histogram(
+                HistogramOpts::new("yozuk_attachment_bytes", "Size of received attachments")
+                    .buckets(exponential_buckets(1024.0, 4.0, 8).unwrap()),
+                &["platform"],
+            ),
+            in_flight,
+            registry,
+        };
+
+        // Export the series without a command label from the start.
+        metrics.messages_received.with_label_values(&[platform]);
+        metrics.unrecognized_commands.with_label_values(&[platform]);
+        metrics.send_failures.with_label_values(&[platform]);
+        metrics.attachment_bytes.with_label_values(&[platform]);
+        metrics.in_flight.with_label_values(&[platform]);
+        metrics
+    }
+
+    pub fn message_received(&self) {
+        self.messages_received
+            .with_label_values(&[self.platform])
+            .inc();
+    }
+
+    pub fn attachment(&self, bytes: usize) {
+        self.attachment_bytes
+            .with_label_values(&[self.platform])
+            .observe(bytes as f64);
+    }
+
+    /// Counts a failed delivery and passes the error through.
+    pub fn send_failure<E>(&self, err: E) -> E {
+        self.send_failures.with_label_values(&[self.platform]).inc();
+        err
+    }
+
+    /// Counts the handler as in flight until the guard is dropped.
+    pub fn in_flight(&self) -> InFlight {
+        let gauge = self.in_flight.with_label_values(&[self.platform]);
+        gauge.inc();
+        InFlight(gauge)
+    }
+
+    pub fn record(&self, plan: &ResponsePlan) {
+        if let Some(hit) = plan.cache_hit {
+            let result = if hit { "hit" } else { "miss" };
+            self.cache_lookups
+                .with_label_values(&[self.platform, result])
+                .inc();
+        }
+        let command = match &plan.command {
+            Some(command) => self.command_label(command),
+            None => {
+                self.unrecognized_commands
+                    .with_label_values(&[self.platform])
+                    .inc();
+                return;
+            }
+        };
+        let labels = [self.platform, command.as_str()];
+        self.commands_executed.with_label_values(&labels).inc();
+        self.command_duration
+            .with_label_values(&labels)
+            .observe(plan.duration.as_secs_f64());
+        if plan.status == PlanStatus::Failure {
+            self.command_errors.with_label_values(&labels).inc();
+        }
+    }
+
+    /// Reads the counters back, e.g. to show them in a chat.
+    pub fn stats(&self) -> Stats {
+        let (count, sum, buckets) = self.command_durations();
+        Stats {
+            uptime: self.started.elapsed(),
+            messages: self
+                .messages_received
+                .with_label_values(&[self.platform])
+                .get(),
+            commands: total(&self.commands_executed),
+            command_errors: total(&self.command_errors),
+            unrecognized: self
+                .unrecognized_commands
+                .with_label_values(&[self.platform])
+                .get(),
+            mean_latency: (count > 0).then(|| Duration::from_secs_f64(sum / count as f64)),
+            p95_latency: percentile(&buckets, count, 0.95),
+        }
+    }
+
+    /// Sums the duration histograms of all commands into the sample count,
+    /// the sum and the cumulative buckets.
+    fn command_durations(&self) -> (u64, f64, Vec<(f64, u64)>) {
+        let mut count = 0;
+        let mut sum = 0.0;
+        let mut buckets: Vec<(f64, u64)> = vec![];
+        for family in self.command_duration.collect() {
+            for metric in family.get_metric() {
+                let histogram = metric.get_histogram();
+                count += histogram.get_sample_count();
+                sum += histogram.get_sample_sum();
+                for (index, bucket) in histogram.get_bucket().iter().enumerate() {
+                    match buckets.get_mut(index) {
+                        Some((_, cumulative)) => *cumulative += bucket.get_cumulative_count(),
+                        None => {
+                            buckets.push((bucket.get_upper_bound(), bucket.get_cumulative_count()))
+                        }
+                    }
+                }
+            }
+        }
+        (count, sum, buckets)
+    }
+
+    fn command_label(&self, command: &str) -> String {
+        let mut commands = self.commands.lock().unwrap();
+        if commands.contains(command) {
+            command.into()
+        } else if commands.len() < MAX_COMMAND_LABELS {
+            commands.insert(command.into());
+            command.into()
+        } else {
+            "other".into()
+        }
+    }
+
+    /// Serves /metrics and /healthz on `addr` in the background.
+    pub fn serve(self: &Arc<Self>, addr: SocketAddr) -> Result<()> {
+        let metrics = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let metrics = metrics.clone();
+                    async move { Ok::<_, Infallible>(metrics.respond(req)) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                tracing::error!("metrics server error: {}", err);
+            }
+        });
+        Ok(())
+    }
+
+    fn respond(&self, req: Request<Body>) -> Response<Body> {
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => {
+                let encoder = TextEncoder::new();
+                let mut body = Vec::new();
+                match encoder.encode(&self.registry.gather(), &mut body) {
+                    Ok(()) => Response::builder()
+                        .header(header::CONTENT_TYPE, encoder.format_type())
+                        .body(body.into())
+                        .unwrap(),
+                    Err(err) => Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(err.to_string().into())
+                        .unwrap(),
+                }
+            }
+            (&Method::GET, "/healthz") => Response::new("ok".into()),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        }
+    }
+}
+
+/// A snapshot of the [`Metrics`] of a frontend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub uptime: Duration,
+    pub messages: u64,
+    pub commands: u64,
+    pub command_errors: u64,
+    pub unrecognized: u64,
+    /// Mean duration of the commands, if any has run.
+    pub mean_latency: Option<Duration>,
+    /// Upper bound of the histogram bucket that holds the 95th percentile.
+    pub p95_latency: Option<Duration>,
+}
+
+fn total(counter: &IntCounterVec) -> u64 {
+    counter
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+/// Finds the first bucket that holds the `q` quantile, or the largest one if
+/// the quantile lies beyond it.
+fn percentile(buckets: &[(f64, u64)], count: u64, q: f64) -> Option<Duration> {
+    if count == 0 {
+        return None;
+    }
+    let rank = (q * count as f64).ceil() as u64;
+    let (bound, _) = buckets
+        .iter()
+        .find(|(_, cumulative)| *cumulative >= rank)
+        .or_else(|| buckets.last())?;
+    Some(Duration::from_secs_f64(*bound))
+}
+
+pub struct InFlight(prometheus::IntGauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
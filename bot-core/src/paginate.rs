@@ -0,0 +1,250 @@
+const FENCE: &str = "```";
+
+/// Size constraints of a single message on a platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageLimits {
+    /// Maximum number of characters per page.
+    pub max_chars: Option<usize>,
+
+    /// Maximum size of a page in bytes.
+    pub max_bytes: Option<usize>,
+
+    /// Move parts and lines that don't fit to the next page rather than
+    /// filling every page up to the limit.
+    pub prefer_line_breaks: bool,
+
+    /// Close a code fence at the end of a page and reopen it on the next one.
+    pub fence_aware: bool,
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        Self {
+            max_chars: None,
+            max_bytes: None,
+            prefer_line_breaks: true,
+            fence_aware: true,
+        }
+    }
+}
+
+impl PageLimits {
+    fn fits(&self, chars: usize, bytes: usize) -> bool {
+        self.max_chars.is_none_or(|max| chars <= max)
+            && self.max_bytes.is_none_or(|max| bytes <= max)
+    }
+}
+
+/// Joins `parts` with line breaks and splits the result into pages that
+/// satisfy `limits`, numbering them with "(n/m)" if there are several.
+///
+/// Words are kept together where possible, and never split inside a
+/// UTF-8 sequence. Always returns at least one page, which may be empty.
+pub fn paginate<I, S>(parts: I, limits: &PageLimits) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let parts = parts
+        .into_iter()
+        .map(|part| part.as_ref().to_string())
+        .collect::<Vec<_>>();
+    let mut pages = Pager::new(limits, 0).run(&parts);
+
+    // The markers take up space too, depending on the number of pages.
+    let mut reserve = 0;
+    while pages.len() > 1 {
+        let needed = marker(pages.len(), pages.len()).len() + 1;
+        if needed <= reserve {
+            break;
+        }
+        reserve = needed;
+        pages = Pager::new(limits, reserve).run(&parts);
+    }
+
+    let total = pages.len();
+    if total > 1 {
+        for (index, page) in pages.iter_mut().enumerate() {
+            page.push('\n');
+            page.push_str(&marker(index + 1, total));
+        }
+    }
+    pages
+}
+
+fn marker(page: usize, total: usize) -> String {
+    format!("({}/{})", page, total)
+}
+
+struct Pager<'a> {
+    limits: &'a PageLimits,
+    reserve: usize,
+    pages: Vec<String>,
+    page: String,
+
+    /// Length of the reopened fence at the start of the page.
+    start: usize,
+
+    /// Opening line of the code fence the page currently ends in.
+    fence: Option<String>,
+}
+
+impl<'a> Pager<'a> {
+    fn new(limits: &'a PageLimits, reserve: usize) -> Self {
+        Self {
+            limits,
+            reserve,
+            pages: vec![],
+            page: String::new(),
+            start: 0,
+            fence: None,
+        }
+    }
+
+    fn run(mut self, parts: &[String]) -> Vec<String> {
+        for part in parts {
+            self.add_part(part);
+        }
+        self.pages.push(self.page);
+        self.pages
+    }
+
+    fn add_part(&mut self, part: &str) {
+        if self.fits(part) {
+            return self.push(part);
+        }
+        if self.limits.prefer_line_breaks && !self.is_empty() {
+            self.flush();
+            if self.fits(part) {
+                return self.push(part);
+            }
+        }
+        for line in part.split('\n') {
+            self.add_line(line);
+        }
+    }
+
+    fn add_line(&mut self, line: &str) {
+        let mut rest = line;
+        loop {
+            if self.fits(rest) {
+                return self.push(rest);
+            }
+            if (self.limits.prefer_line_breaks || self.is_fence(rest)) && !self.is_empty() {
+                self.flush();
+                continue;
+            }
+            // Splitting a fence would break the formatting of the rest.
+            if self.is_fence(rest) {
+                return self.push(rest);
+            }
+            let end = match self.split_point(rest) {
+                0 if !self.is_empty() => {
+                    self.flush();
+                    continue;
+                }
+                // Nothing fits even on an empty page; make progress anyway.
+                0 => rest.chars().next().map_or(rest.len(), char::len_utf8),
+                end => end,
+            };
+            self.push(&rest[..end]);
+            self.flush();
+            rest = rest[end..]
+                .strip_prefix(char::is_whitespace)
+                .unwrap_or(&rest[end..]);
+        }
+    }
+
+    /// Whether the page is empty apart from a reopened fence.
+    fn is_empty(&self) -> bool {
+        self.page.len() == self.start
+    }
+
+    fn separator(&self) -> &'static str {
+        if self.page.is_empty() {
+            ""
+        } else {
+            "\n"
+        }
+    }
+
+    /// Whether `text` can be added without exceeding the limits, including
+    /// the line needed to close a fence left open.
+    fn fits(&self, text: &str) -> bool {
+        let mut fence = self.fence.clone();
+        for line in text.split('\n') {
+            self.toggle(&mut fence, line);
+        }
+        let closing = if fence.is_some() { FENCE.len() + 1 } else { 0 };
+        let sep = self.separator().len();
+        let chars = self.page.chars().count() + sep + text.chars().count() + closing;
+        let bytes = self.page.len() + sep + text.len() + closing;
+        self.limits.fits(chars + self.reserve, bytes + self.reserve)
+    }
+
+    /// Returns the length of the longest prefix of `line` that fits,
+    /// preferring to end it before a word.
+    fn split_point(&self, line: &str) -> usize {
+        let closing = if self.fence.is_some() {
+            FENCE.len() + 1
+        } else {
+            0
+        };
+        let sep = self.separator().len();
+        let mut chars = self.page.chars().count() + sep + closing + self.reserve;
+        let mut bytes = self.page.len() + sep + closing + self.reserve;
+        let mut end = 0;
+        for c in line.chars() {
+            chars += 1;
+            bytes += c.len_utf8();
+            if !self.limits.fits(chars, bytes) {
+                break;
+            }
+            end += c.len_utf8();
+        }
+        if end < line.len() {
+            if let Some(pos) = line[..end]
+                .rfind(char::is_whitespace)
+                .filter(|&pos| pos > 0)
+            {
+                return pos;
+            }
+        }
+        end
+    }
+
+    fn push(&mut self, text: &str) {
+        self.page.push_str(self.separator());
+        self.page.push_str(text);
+        let mut fence = self.fence.take();
+        for line in text.split('\n') {
+            self.toggle(&mut fence, line);
+        }
+        self.fence = fence;
+    }
+
+    fn is_fence(&self, line: &str) -> bool {
+        self.limits.fence_aware && line.trim_start().starts_with(FENCE)
+    }
+
+    fn toggle(&self, fence: &mut Option<String>, line: &str) {
+        if self.is_fence(line) {
+            *fence = match fence {
+                Some(_) => None,
+                None => Some(line.trim().to_string()),
+            };
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.fence.is_some() {
+            self.page.push('\n');
+            self.page.push_str(FENCE);
+        }
+        self.pages.push(std::mem::take(&mut self.page));
+        if let Some(fence) = &self.fence {
+            self.page.push_str(fence);
+        }
+        self.start = self.page.len();
+    }
+}
@@ -0,0 +1,834 @@
+use crate::analytics::{AnalyticsSink, CommandEvent, NoopAnalytics};
+use crate::bundle::bundle_files;
+use crate::cache::ResultCache;
+use crate::category::ErrorCategory;
+use crate::i18n::Lang;
+use crate::redact::Redactor;
+use anyhow::{bail, Error};
+use std::any::Any;
+use std::str;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::task;
+use yozuk::Yozuk;
+use yozuk_helper_filetype::get_file_extension;
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+
+/// Platform constraints taken into account while planning a response.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Decides whether UTF-8 data is sent as a code block or as a file.
+    pub text_policy: TextPolicy,
+
+    /// Texts and code blocks longer than this are split into several items.
+    pub max_text_length: Option<usize>,
+
+    /// Number of suggestions offered when no command matches.
+    pub suggestions: usize,
+
+    /// Files are bundled into a single ZIP archive when there are more than this many.
+    pub bundle_threshold: Option<usize>,
+
+    /// Maximum size of a file the platform accepts; larger files are
+    /// replaced with a note.
+    pub max_file_size: Option<usize>,
+
+    /// Applied to the outputs of failed commands, which may echo the input.
+    pub redactor: Redactor,
+
+    /// Reply with the matched commands instead of running them.
+    pub dry_run: bool,
+
+    /// Reuse the outputs of identical commands.
+    pub cache: Option<Arc<ResultCache>>,
+
+    /// Time after which [`spawn_plan`] gives up waiting for the commands.
+    pub command_timeout: Duration,
+
+    /// Append the matched skills and the processing time to responses.
+    pub verbose: bool,
+
+    /// Never send files; binary data is summarized or inlined instead.
+    pub text_only: bool,
+
+    /// Binary data up to this size is rendered as text instead of a file.
+    pub inline_binary: Option<InlineBinary>,
+
+    /// Notified of every executed command.
+    pub analytics: Arc<dyn AnalyticsSink>,
+
+    /// Word that makes the bot send data blocks as files, without fencing
+    /// or splitting. It is removed from the message before tokenizing.
+    pub raw_flag: Option<String>,
+
+    /// Leave out the comments of outputs that contain data.
+    pub quiet: bool,
+
+    /// Precede each output with a [`PlanItem::Section`] if there are several.
+    pub sections: bool,
+
+    /// When the message being answered was sent, if the platform says so.
+    pub sent_at: Option<SystemTime>,
+
+    /// Items beyond this many are left out with a note. Front-ends that join
+    /// the items into pages leave this unset, as paging already merges them.
+    pub max_blocks: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            text_policy: TextPolicy::AlwaysInline,
+            max_text_length: None,
+            suggestions: 3,
+            bundle_threshold: None,
+            max_file_size: None,
+            redactor: Redactor::default(),
+            dry_run: false,
+            cache: None,
+            command_timeout: Duration::from_secs(30),
+            verbose: false,
+            text_only: false,
+            inline_binary: None,
+            analytics: Arc::new(NoopAnalytics),
+            raw_flag: Some("--raw".into()),
+            quiet: false,
+            sections: false,
+            max_blocks: None,
+            sent_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextPolicy {
+    AlwaysFile,
+    AlwaysInline,
+    Threshold(usize),
+}
+
+impl TextPolicy {
+    pub fn is_inline(&self, len: usize) -> bool {
+        match *self {
+            Self::AlwaysFile => false,
+            Self::AlwaysInline => true,
+            Self::Threshold(max) => len <= max,
+        }
+    }
+}
+
+impl FromStr for TextPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Self::AlwaysFile),
+            "inline" => Ok(Self::AlwaysInline),
+            _ => match s.parse() {
+                Ok(max) => Ok(Self::Threshold(max)),
+                Err(_) => bail!("expected \"file\", \"inline\" or a length, got {:?}", s),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    Hex,
+    Base64,
+}
+
+impl BinaryEncoding {
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Self::Hex => data.iter().map(|b| format!("{:02x}", b)).collect(),
+            Self::Base64 => base64::encode(data),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Hex => "hex",
+            Self::Base64 => "base64",
+        }
+    }
+}
+
+/// Encoding and maximum size of binary data rendered as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InlineBinary {
+    pub encoding: BinaryEncoding,
+    pub max_size: usize,
+}
+
+impl FromStr for InlineBinary {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (encoding, max_size) = s.split_once(':').unwrap_or((s, ""));
+        let encoding = match encoding {
+            "hex" => BinaryEncoding::Hex,
+            "base64" => BinaryEncoding::Base64,
+            _ => bail!("expected \"hex:<size>\" or \"base64:<size>\", got {:?}", s),
+        };
+        match max_size.parse() {
+            Ok(max_size) => Ok(Self { encoding, max_size }),
+            Err(_) => bail!("expected a size after {:?}, got {:?}", encoding.name(), s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponsePlan {
+    pub items: Vec<PlanItem>,
+
+    /// Name of the first command run, if any command matched.
+    pub command: Option<String>,
+    pub status: PlanStatus,
+    pub duration: Duration,
+
+    /// Whether the outputs came from the result cache, if it was consulted.
+    pub cache_hit: Option<bool>,
+
+    /// What went wrong if the commands failed, for operators. Only error
+    /// texts are included, redacted like the replies.
+    pub error: Option<String>,
+
+    /// Kind of failure the reply reports, if any.
+    pub category: Option<ErrorCategory>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlanStatus {
+    #[default]
+    Success,
+    Failure,
+    Unrecognized,
+}
+
+/// A platform-agnostic piece of a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanItem {
+    Text(String),
+    CodeBlock {
+        lang: Option<String>,
+        text: String,
+    },
+    File {
+        name: String,
+        media_type: MediaTypeBuf,
+        data: Bytes,
+    },
+    Apology {
+        suggestions: Vec<String>,
+    },
+    /// Introduces a failed reply; rendered with [`ErrorCategory::message`].
+    Error {
+        category: ErrorCategory,
+    },
+    /// Starts the items of another output; only planned if `Limits::sections` is set.
+    Section {
+        title: String,
+    },
+}
+
+pub fn plan_response(
+    zuk: &Yozuk,
+    text: &str,
+    mut streams: Vec<InputStream>,
+    user: &UserContext,
+    limits: &Limits,
+) -> ResponsePlan {
+    for stream in &mut streams {
+        let _ = stream.read_header();
+    }
+
+    let raw = raw_request(text, limits);
+    let (text, limits) = match &raw {
+        Some((text, limits)) => (text.as_str(), limits),
+        None => (text, limits),
+    };
+
+    let tokens = Tokenizer::new().tokenize(text);
+    let commands = zuk.get_commands(&tokens, &streams);
+    if commands.is_empty() {
+        return plan_apology(zuk, &tokens, &streams, limits);
+    }
+    plan_commands(zuk, commands, streams, user, limits)
+}
+
+/// Strips `limits.raw_flag` from `text` and returns the limits for a raw
+/// reply, or `None` if the flag isn't given.
+pub(crate) fn raw_request(text: &str, limits: &Limits) -> Option<(String, Limits)> {
+    let text = strip_flag(text, limits.raw_flag.as_deref()?)?;
+    let limits = Limits {
+        text_policy: TextPolicy::AlwaysFile,
+        inline_binary: None,
+        ..limits.clone()
+    };
+    Some((text, limits))
+}
+
+/// Removes every occurrence of the word `flag` from `text`, or returns `None`
+/// if there is none.
+pub fn strip_flag(text: &str, flag: &str) -> Option<String> {
+    let mut found = false;
+    let lines = text
+        .split('\n')
+        .map(|line| {
+            let words = line.split(' ').collect::<Vec<_>>();
+            let kept = words
+                .iter()
+                .copied()
+                .filter(|&word| word != flag)
+                .collect::<Vec<_>>();
+            found |= kept.len() < words.len();
+            kept.join(" ")
+        })
+        .collect::<Vec<_>>();
+    found.then(|| lines.join("\n").trim().to_string())
+}
+
+/// Runs `plan` on the blocking thread pool so that a slow or panicking skill
+/// can't stall the handler.
+///
+/// A task that exceeds `limits.command_timeout` keeps running in the
+/// background, but the user is told that the command was cancelled.
+pub async fn spawn_plan<F>(limits: &Limits, text: &str, plan: F) -> ResponsePlan
+where
+    F: FnOnce() -> ResponsePlan + Send + 'static,
+{
+    let start = Instant::now();
+    let failure =
+        match tokio::time::timeout(limits.command_timeout, task::spawn_blocking(plan)).await {
+            Ok(Ok(plan)) => return plan,
+            Ok(Err(err)) => match err.try_into_panic() {
+                Ok(panic) => panicked(limits, panic),
+                Err(err) => panicked(limits, Box::new(err.to_string())),
+            },
+            Err(_) => timed_out(limits),
+        };
+    plan_cancelled(limits, text, failure, start)
+}
+
+/// Runs `plan` on a thread of its own like [`spawn_plan`], for callers
+/// without an async runtime.
+pub fn run_plan<F>(limits: &Limits, text: &str, plan: F) -> ResponsePlan
+where
+    F: FnOnce() -> ResponsePlan + Send + 'static,
+{
+    let start = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let _ = sender.send(plan());
+    });
+    let failure = match receiver.recv_timeout(limits.command_timeout) {
+        Ok(plan) => return plan,
+        // The sender is only dropped without sending if `plan` panicked.
+        Err(RecvTimeoutError::Disconnected) => match handle.join() {
+            Err(panic) => panicked(limits, panic),
+            Ok(()) => panicked(limits, Box::new("")),
+        },
+        Err(RecvTimeoutError::Timeout) => timed_out(limits),
+    };
+    plan_cancelled(limits, text, failure, start)
+}
+
+fn panicked(limits: &Limits, panic: Box<dyn Any + Send>) -> (ErrorCategory, String) {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    let message = limits.redactor.redact(&message).into_owned();
+    (
+        ErrorCategory::InternalError,
+        format!("panicked: {}", message),
+    )
+}
+
+fn timed_out(limits: &Limits) -> (ErrorCategory, String) {
+    let timeout = limits.command_timeout.as_secs();
+    (
+        ErrorCategory::Timeout,
+        format!("timed out after {} s", timeout),
+    )
+}
+
+fn plan_cancelled(
+    limits: &Limits,
+    text: &str,
+    (category, error): (ErrorCategory, String),
+    start: Instant,
+) -> ResponsePlan {
+    let command = limits
+        .redactor
+        .redact(text.split_whitespace().next().unwrap_or_default())
+        .into_owned();
+    category.log(&command, &error);
+    ResponsePlan {
+        items: vec![PlanItem::Error { category }],
+        status: PlanStatus::Failure,
+        duration: start.elapsed(),
+        error: Some(error),
+        category: Some(category),
+        ..Default::default()
+    }
+}
+
+/// Formats a size limit in the largest binary unit that divides it, like "10MiB".
+pub fn format_size(bytes: usize) -> String {
+    [(1024 * 1024, "MiB"), (1024, "KiB")]
+        .into_iter()
+        .find(|&(unit, _)| bytes > 0 && bytes / unit * unit == bytes)
+        .map(|(unit, name)| format!("{}{}", bytes / unit, name))
+        .unwrap_or_else(|| format!("{}B", bytes))
+}
+
+/// Plans a reply to a message that was rejected before any command ran,
+/// explained by `details`, and logs `error` at the level of `category`.
+pub fn plan_failure(category: ErrorCategory, details: String, error: String) -> ResponsePlan {
+    category.log("", &error);
+    ResponsePlan {
+        items: vec![PlanItem::Error { category }, PlanItem::Text(details)],
+        status: PlanStatus::Failure,
+        error: Some(error),
+        category: Some(category),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn plan_apology(
+    zuk: &Yozuk,
+    tokens: &[Token],
+    streams: &[InputStream],
+    limits: &Limits,
+) -> ResponsePlan {
+    let category = ErrorCategory::NoMatch;
+    category.log("", "no command matched");
+    ResponsePlan {
+        items: vec![PlanItem::Apology {
+            suggestions: zuk.suggestions(tokens, streams, limits.suggestions),
+        }],
+        status: PlanStatus::Unrecognized,
+        category: Some(category),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn plan_commands(
+    zuk: &Yozuk,
+    commands: Vec<CommandArgs>,
+    mut streams: Vec<InputStream>,
+    user: &UserContext,
+    limits: &Limits,
+) -> ResponsePlan {
+    let command = commands[0].args.first().cloned();
+    if limits.dry_run {
+        return ResponsePlan {
+            items: vec![PlanItem::Text(describe_commands(
+                &commands,
+                user_lang(user),
+            ))],
+            command,
+            ..Default::default()
+        };
+    }
+
+    let skills = commands
+        .iter()
+        .filter_map(|command| command.args.first())
+        .map(|name| name.trim_start_matches("yozuk-skill-").to_string())
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    let cache = limits
+        .cache
+        .as_deref()
+        .filter(|cache| cache.accepts(&commands))
+        .and_then(|cache| Some((cache, cache.key(&commands, &mut streams, user).ok()?)));
+    let cached = cache.and_then(|(cache, key)| cache.get(key));
+    let cache_hit = cache.map(|_| cached.is_some());
+    let (outputs, status) = match cached {
+        Some(outputs) => (outputs, PlanStatus::Success),
+        None => match zuk.run_commands(commands, &mut streams, Some(user)) {
+            Ok(outputs) => {
+                if let Some((cache, key)) = cache {
+                    cache.insert(key, outputs.clone());
+                }
+                (outputs, PlanStatus::Success)
+            }
+            Err(outputs) => (outputs, PlanStatus::Failure),
+        },
+    };
+    let duration = start.elapsed();
+    tracing::info!(
+        command = command.as_deref().unwrap_or_default(),
+        duration_ms = duration.as_millis() as u64,
+        status = ?status,
+        cache_hit = ?cache_hit,
+        "command executed"
+    );
+    for command in &skills {
+        limits.analytics.record(&CommandEvent {
+            command: command.clone(),
+            lang: user_lang(user),
+            success: status == PlanStatus::Success,
+            sent_at: limits.sent_at,
+        });
+    }
+    let error = (status == PlanStatus::Failure).then(|| {
+        limits
+            .redactor
+            .redact(&error_details(&outputs))
+            .into_owned()
+    });
+    let category = (status == PlanStatus::Failure).then_some(ErrorCategory::CommandError);
+    if let (Some(category), Some(error)) = (category, &error) {
+        category.log(command.as_deref().unwrap_or_default(), error);
+    }
+    let mut plan = plan_outputs(outputs, limits, user_lang(user));
+    if let Some(category) = category {
+        for item in &mut plan.items {
+            if let PlanItem::Text(text) | PlanItem::CodeBlock { text, .. } = item {
+                *text = limits.redactor.redact(text).into_owned();
+            }
+        }
+        plan.items.insert(0, PlanItem::Error { category });
+    }
+    if limits.verbose {
+        plan.items
+            .push(PlanItem::Text(debug_footer(&skills, duration, cache_hit)));
+    }
+    ResponsePlan {
+        command,
+        status,
+        duration,
+        cache_hit,
+        error,
+        category,
+        ..plan
+    }
+}
+
+/// Collects the comments of failed outputs, leaving out their data, which
+/// may contain the input.
+fn error_details(outputs: &[Output]) -> String {
+    outputs
+        .iter()
+        .map(|output| {
+            let comments = output
+                .blocks
+                .iter()
+                .filter_map(|block| match block {
+                    Block::Comment(comment) => Some(comment.text.trim()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            match output.title.trim() {
+                "" => comments,
+                title => format!("{}: {}", title, comments),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Describes how a response was produced without repeating any arguments,
+/// which may contain user input.
+fn debug_footer(skills: &[String], duration: Duration, cache_hit: Option<bool>) -> String {
+    let mut footer = format!("debug: {} ({} ms", skills.join(", "), duration.as_millis());
+    if cache_hit == Some(true) {
+        footer.push_str(", cached");
+    }
+    footer.push(')');
+    footer
+}
+
+fn describe_commands(commands: &[CommandArgs], lang: Lang) -> String {
+    let mut text = lang.tr("dry-run", &[]);
+    for command in commands {
+        let args = command
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| match i {
+                0 => arg.trim_start_matches("yozuk-skill-"),
+                _ => arg.as_str(),
+            })
+            .collect::<Vec<_>>();
+        text.push_str(&format!("\n- {}", args.join(" ")));
+    }
+    text
+}
+
+pub(crate) fn user_lang(user: &UserContext) -> Lang {
+    user.locale
+        .as_deref()
+        .and_then(Lang::from_locale)
+        .unwrap_or_default()
+}
+
+pub fn plan_outputs(outputs: Vec<Output>, limits: &Limits, lang: Lang) -> ResponsePlan {
+    let name = archive_name(&outputs);
+    let sections = limits.sections && outputs.len() > 1;
+    let mut items = vec![];
+    for output in outputs {
+        if sections {
+            items.push(PlanItem::Section {
+                title: output.title.trim().to_string(),
+            });
+        }
+        let quiet = limits.quiet
+            && output
+                .blocks
+                .iter()
+                .any(|block| matches!(block, Block::Data(data) if !data.data.is_empty()));
+        for block in output.blocks {
+            if quiet && matches!(block, Block::Comment(_)) {
+                continue;
+            }
+            plan_block(&mut items, block, limits, lang);
+        }
+    }
+    bundle_files(&mut items, &name, limits);
+    if let Some(max) = limits.max_blocks {
+        truncate_blocks(&mut items, max, lang);
+    }
+    if items.is_empty() {
+        items.push(PlanItem::Text(lang.tr("no-output", &[])));
+    }
+    ResponsePlan {
+        items,
+        ..Default::default()
+    }
+}
+
+/// Keeps the first `max` items other than sections and notes how many were
+/// dropped.
+fn truncate_blocks(items: &mut Vec<PlanItem>, max: usize, lang: Lang) {
+    let mut blocks = 0;
+    let end = items.iter().position(|item| {
+        if !matches!(item, PlanItem::Section { .. }) {
+            blocks += 1;
+        }
+        blocks > max
+    });
+    let end = match end {
+        Some(end) => end,
+        None => return,
+    };
+    let more = items[end..]
+        .iter()
+        .filter(|item| !matches!(item, PlanItem::Section { .. }))
+        .count();
+    items.truncate(end);
+    while matches!(items.last(), Some(PlanItem::Section { .. })) {
+        items.pop();
+    }
+    items.push(PlanItem::Text(
+        lang.tr("blocks-truncated", &[("count", &more.to_string())]),
+    ));
+}
+
+fn plan_block(items: &mut Vec<PlanItem>, block: Block, limits: &Limits, lang: Lang) {
+    match block {
+        Block::Comment(comment) if !comment.text.is_empty() => {
+            items.extend(
+                split_text(&comment.text, limits.max_text_length)
+                    .into_iter()
+                    .map(PlanItem::Text),
+            );
+        }
+        Block::Data(data) if !data.data.is_empty() => {
+            let inline = limits
+                .inline_binary
+                .filter(|inline| data.data.len() <= inline.max_size);
+            match (str::from_utf8(&data.data), inline) {
+                (Ok(text), _) if limits.text_only || limits.text_policy.is_inline(text.len()) => {
+                    let lang = code_lang(&data.media_type);
+                    items.extend(split_text(text, limits.max_text_length).into_iter().map(
+                        |text| PlanItem::CodeBlock {
+                            lang: lang.map(Into::into),
+                            text,
+                        },
+                    ));
+                }
+                (Err(_), Some(inline)) => {
+                    items.push(PlanItem::Text(format!(
+                        "({}, {} bytes)",
+                        inline.encoding.name(),
+                        data.data.len()
+                    )));
+                    items.extend(
+                        split_text(&inline.encoding.encode(&data.data), limits.max_text_length)
+                            .into_iter()
+                            .map(|text| PlanItem::CodeBlock { lang: None, text }),
+                    );
+                }
+                _ if limits.text_only => {
+                    items.push(PlanItem::Text(lang.tr(
+                        "binary-omitted",
+                        &[
+                            ("size", &data.data.len().to_string()),
+                            ("type", data.media_type.as_ref()),
+                        ],
+                    )));
+                }
+                _ if matches!(limits.max_file_size, Some(max) if data.data.len() > max) => {
+                    items.push(PlanItem::Text(lang.tr(
+                        "file-too-large",
+                        &[
+                            ("name", &file_name(&data)),
+                            ("size", &data.data.len().to_string()),
+                        ],
+                    )));
+                }
+                _ => {
+                    items.push(PlanItem::File {
+                        name: file_name(&data),
+                        media_type: data.media_type,
+                        data: data.data,
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Renders planned files as text for chats where the bot may not upload them.
+///
+/// Text beyond `max_size` bytes is cut off and binary data is inlined as far
+/// as [`Limits::inline_binary`] allows; the remaining files are counted in a
+/// note at the end.
+pub fn inline_files(
+    items: Vec<PlanItem>,
+    limits: &Limits,
+    max_size: usize,
+    lang: Lang,
+) -> Vec<PlanItem> {
+    let limits = Limits {
+        text_only: true,
+        ..limits.clone()
+    };
+    let mut inlined = vec![];
+    let mut omitted = 0;
+    for item in items {
+        let (name, media_type, data) = match item {
+            PlanItem::File {
+                name,
+                media_type,
+                data,
+            } => (name, media_type, data),
+            item => {
+                inlined.push(item);
+                continue;
+            }
+        };
+        let inline_binary =
+            matches!(limits.inline_binary, Some(inline) if data.len() <= inline.max_size);
+        let kept = match str::from_utf8(&data) {
+            Ok(text) if text.len() > max_size => split_text(text, Some(max_size))[0].len(),
+            Ok(_) => data.len(),
+            Err(_) if inline_binary => data.len(),
+            Err(_) => {
+                omitted += 1;
+                continue;
+            }
+        };
+        let block = block::Data::new()
+            .set_data(data.slice(..kept))
+            .set_media_type(media_type);
+        plan_block(&mut inlined, Block::Data(block), &limits, lang);
+        if kept < data.len() {
+            inlined.push(PlanItem::Text(lang.tr(
+                "file-truncated",
+                &[("name", &name), ("size", &data.len().to_string())],
+            )));
+        }
+    }
+    if omitted > 0 {
+        inlined.push(PlanItem::Text(
+            lang.tr("files-omitted", &[("count", &omitted.to_string())]),
+        ));
+    }
+    inlined
+}
+
+pub fn file_name(data: &block::Data) -> String {
+    if data.file_name.is_empty() {
+        format!("data.{}", get_file_extension(&data.media_type))
+    } else {
+        data.file_name.clone()
+    }
+}
+
+/// Names the archive after the command if there is only one.
+fn archive_name(outputs: &[Output]) -> String {
+    let name = match outputs {
+        [output] => output
+            .title
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect::<String>(),
+        _ => String::new(),
+    };
+    if name.is_empty() {
+        "outputs".into()
+    } else {
+        name
+    }
+}
+
+fn code_lang(media_type: &MediaTypeBuf) -> Option<&'static str> {
+    let essence = media_type.essence();
+    if essence == media_type!(APPLICATION / JSON) {
+        Some("json")
+    } else if essence == media_type!(APPLICATION / XML) || essence == media_type!(TEXT / XML) {
+        Some("xml")
+    } else if essence == media_type!(TEXT / HTML) {
+        Some("html")
+    } else {
+        None
+    }
+}
+
+/// Splits text into chunks of at most `max` bytes, preferring line breaks.
+pub fn split_text(text: &str, max: Option<usize>) -> Vec<String> {
+    let max = match max {
+        Some(max) if max > 0 && text.len() > max => max,
+        _ => return vec![text.to_string()],
+    };
+
+    let mut chunks = vec![];
+    let mut rest = text;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let end = match rest[..end].rfind('\n') {
+            Some(pos) if pos > 0 => pos,
+            _ if end > 0 => end,
+            _ => rest.chars().next().map_or(rest.len(), char::len_utf8),
+        };
+        chunks.push(rest[..end].to_string());
+        rest = rest[end..].strip_prefix('\n').unwrap_or(&rest[end..]);
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
@@ -0,0 +1,228 @@
+use regex::Regex;
+
+/// Leading and trailing words that carry no meaning for the tokenizer.
+const FILLER_WORDS: &[&str] = &["please", "pls", "plz"];
+
+/// Invisible characters that are dropped from the input.
+///
+/// Joiners are kept because emoji sequences and some scripts depend on them.
+const ZERO_WIDTH: &[char] = &['\u{200B}', '\u{2060}', '\u{FEFF}'];
+
+/// A single cleanup applied by [`Preprocessor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Removes platform mentions matching the pattern set by the frontend.
+    StripMentions,
+    /// Unwraps a message consisting of a single fenced code block.
+    UnwrapCodeFence,
+    /// Replaces typographic quotes and dashes with their ASCII counterparts.
+    NormalizePunctuation,
+    /// Drops zero-width characters and collapses runs of spaces.
+    CollapseWhitespace,
+    /// Removes leading punctuation-only words and filler words like "please".
+    ///
+    /// Punctuation on its own, like a bare "?", is kept.
+    TrimFiller,
+}
+
+/// Cleans up user input before it is tokenized.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    steps: Vec<Step>,
+    mentions: Option<Regex>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new(vec![
+            Step::UnwrapCodeFence,
+            Step::NormalizePunctuation,
+            Step::CollapseWhitespace,
+            Step::TrimFiller,
+        ])
+    }
+}
+
+/// Input after preprocessing, with a record of what was removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub text: String,
+    pub removed: Vec<(Step, String)>,
+}
+
+impl Preprocessor {
+    /// Creates a preprocessor applying `steps` in order.
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self {
+            steps,
+            mentions: None,
+        }
+    }
+
+    /// Strips mentions matching `pattern` before any other step.
+    pub fn with_mentions(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.mentions = Some(Regex::new(pattern)?);
+        if !self.steps.contains(&Step::StripMentions) {
+            self.steps.insert(0, Step::StripMentions);
+        }
+        Ok(self)
+    }
+
+    pub fn process(&self, text: &str) -> Preprocessed {
+        let mut result = Preprocessed {
+            text: text.into(),
+            removed: vec![],
+        };
+        for &step in &self.steps {
+            let removed = match step {
+                Step::StripMentions => match &self.mentions {
+                    Some(mentions) => strip_mentions(&mut result.text, mentions),
+                    None => vec![],
+                },
+                Step::UnwrapCodeFence => unwrap_code_fence(&mut result.text),
+                Step::NormalizePunctuation => normalize_punctuation(&mut result.text),
+                Step::TrimFiller => trim_filler(&mut result.text),
+                Step::CollapseWhitespace => collapse_whitespace(&mut result.text),
+            };
+            result
+                .removed
+                .extend(removed.into_iter().map(|text| (step, text)));
+        }
+        result
+    }
+}
+
+/// Joins the URLs and descriptions of link previews, as the input of a
+/// message that only consists of links.
+pub fn preview_text<'a, I>(previews: I) -> String
+where
+    I: IntoIterator<Item = (Option<&'a str>, Option<&'a str>)>,
+{
+    previews
+        .into_iter()
+        .flat_map(|(url, description)| [url, description])
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn strip_mentions(text: &mut String, mentions: &Regex) -> Vec<String> {
+    let removed = mentions
+        .find_iter(text)
+        .map(|m| m.as_str().to_string())
+        .collect::<Vec<_>>();
+    if !removed.is_empty() {
+        *text = mentions.replace_all(text, "").into_owned();
+    }
+    removed
+}
+
+fn unwrap_code_fence(text: &mut String) -> Vec<String> {
+    let trimmed = text.trim();
+    let inner = match trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    {
+        Some(inner) if !inner.contains("```") => inner,
+        _ => return vec![],
+    };
+    // The info string names the language of a fence spanning several lines.
+    let (info, body) = match inner.split_once('\n') {
+        Some((info, body)) if !info.contains(char::is_whitespace) => (info, body),
+        _ => ("", inner),
+    };
+    let removed = vec![format!("```{}", info), "```".into()];
+    *text = body.trim_end_matches('\n').to_string();
+    removed
+}
+
+fn normalize_punctuation(text: &mut String) -> Vec<String> {
+    let mut removed = vec![];
+    *text = text
+        .chars()
+        .map(|c| {
+            let replacement = match c {
+                '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' => '"',
+                '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => '\'',
+                '\u{2013}' | '\u{2014}' | '\u{2212}' => '-',
+                _ => return c,
+            };
+            removed.push(c.to_string());
+            replacement
+        })
+        .collect();
+    removed
+}
+
+fn trim_filler(text: &mut String) -> Vec<String> {
+    let mut removed = vec![];
+    let mut words = text.split(' ').collect::<Vec<_>>();
+    while let Some(word) = words.first().copied() {
+        if word.is_empty() {
+            words.remove(0);
+        } else if is_filler_word(word) || is_punctuation(word) && has_more_text(&words[1..]) {
+            removed.push(words.remove(0).to_string());
+        } else {
+            break;
+        }
+    }
+    while let Some(word) = words.last().copied() {
+        if word.is_empty() {
+            words.pop();
+        } else if is_filler_word(word) {
+            removed.extend(words.pop().map(Into::into));
+        } else {
+            break;
+        }
+    }
+    if !removed.is_empty() {
+        *text = words.join(" ");
+    }
+    removed
+}
+
+fn is_punctuation(word: &str) -> bool {
+    word.chars().all(|c| c.is_ascii_punctuation())
+}
+
+fn has_more_text(words: &[&str]) -> bool {
+    words
+        .iter()
+        .any(|word| !word.is_empty() && !is_punctuation(word))
+}
+
+fn is_filler_word(word: &str) -> bool {
+    let word = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    FILLER_WORDS
+        .iter()
+        .any(|filler| filler.eq_ignore_ascii_case(word))
+}
+
+fn collapse_whitespace(text: &mut String) -> Vec<String> {
+    let mut removed = vec![];
+    let lines = text
+        .lines()
+        .map(|line| {
+            let line = line
+                .chars()
+                .filter(|c| {
+                    let zero_width = ZERO_WIDTH.contains(c);
+                    if zero_width {
+                        removed.push(c.escape_unicode().to_string());
+                    }
+                    !zero_width
+                })
+                .collect::<String>();
+            // Indentation is kept since it may be significant in pasted data.
+            let body = line.trim_start();
+            let indent = &line[..line.len() - body.len()];
+            format!(
+                "{}{}",
+                indent,
+                body.split_whitespace().collect::<Vec<_>>().join(" ")
+            )
+        })
+        .collect::<Vec<_>>();
+    *text = lines.join("\n").trim().to_string();
+    removed
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// A placeholder that is sent if a command takes longer than a delay, for
+/// platforms without a typing indicator.
+pub struct Progress<T> {
+    cancel: oneshot::Sender<()>,
+    task: JoinHandle<Option<T>>,
+}
+
+impl<T: Send + 'static> Progress<T> {
+    /// Calls `send` after `delay` unless [`Progress::finish`] is called first.
+    pub fn start<F, Fut>(delay: Duration, send: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T>> + Send,
+    {
+        let (cancel, cancelled) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            if time::timeout(delay, cancelled).await.is_ok() {
+                return None;
+            }
+            match send().await {
+                Ok(sent) => Some(sent),
+                Err(err) => {
+                    tracing::warn!("failed to send a progress message: {}", err);
+                    None
+                }
+            }
+        });
+        Self { cancel, task }
+    }
+
+    /// Stops the timer and returns the placeholder if it has been sent, so
+    /// that it can be removed. Waits for a send that is under way.
+    pub async fn finish(self) -> Option<T> {
+        let _ = self.cancel.send(());
+        self.task.await.ok().flatten()
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Allows `capacity` requests per `period`, written as `<capacity>/<seconds>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (capacity, period) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("expected <capacity>/<seconds>, got {:?}", s))?;
+        let capacity = capacity.trim().parse()?;
+        let period = Duration::from_secs(period.trim().parse()?);
+        if capacity == 0 || period.is_zero() {
+            return Err(anyhow!("capacity and period must be positive"));
+        }
+        Ok(Self { capacity, period })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// Token bucket rate limiter keyed by sender.
+///
+/// Buckets that have been refilled completely are indistinguishable from new
+/// ones, so they are evicted to keep memory bounded.
+pub struct RateLimiter<K> {
+    limit: RateLimit,
+    state: Mutex<State<K>>,
+}
+
+struct State<K> {
+    buckets: HashMap<K, Bucket>,
+    last_eviction: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                last_eviction: Instant::now(),
+            }),
+        }
+    }
+
+    /// Number of senders being tracked.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn check(&self, key: K) -> Decision {
+        let now = Instant::now();
+        let capacity = self.limit.capacity as f64;
+        let rate = capacity / self.limit.period.as_secs_f64();
+        let mut state = self.state.lock().unwrap();
+
+        if now.saturating_duration_since(state.last_eviction) >= self.limit.period {
+            let period = self.limit.period;
+            state
+                .buckets
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < period);
+            state.last_eviction = now;
+        }
+
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allowed
+        } else {
+            Decision::Limited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rate),
+            }
+        }
+    }
+}
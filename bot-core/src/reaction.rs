@@ -0,0 +1,38 @@
+use crate::plan::PlanStatus;
+
+/// Progress of a request, as shown by a reaction to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reaction {
+    Pending,
+    Done,
+    Failed,
+}
+
+impl Reaction {
+    /// Reaction showing the outcome of a request; unrecognized requests and
+    /// those that failed before planning count as failed.
+    pub fn outcome(status: Option<PlanStatus>) -> Self {
+        match status {
+            Some(PlanStatus::Success) => Self::Done,
+            _ => Self::Failed,
+        }
+    }
+
+    /// Decides how to go from the reaction `current` to `next`.
+    pub fn update(current: Option<Self>, next: Self) -> ReactionUpdate {
+        if current == Some(next) {
+            return ReactionUpdate::default();
+        }
+        ReactionUpdate {
+            remove: current,
+            add: Some(next),
+        }
+    }
+}
+
+/// Reactions to remove from a request and then to add to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReactionUpdate {
+    pub remove: Option<Reaction>,
+    pub add: Option<Reaction>,
+}
@@ -0,0 +1,113 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+const MASK: &str = "[REDACTED]";
+
+/// Patterns for well-known credential formats. A first capture group is kept
+/// as is, so key-value pairs retain their key.
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"(?i)\b((?:password|passwd|pwd|secret|token|api[_-]?key|access[_-]?key)\s*[:=]\s*)[^\s,;]+",
+    r"(?i)\b(bearer\s+)[\w\-.~+/]+=*",
+    r"\bAKIA[0-9A-Z]{16}\b",
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    r"\beyJ[\w-]+\.eyJ[\w-]+\.[\w-]+",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+/// Words at least this long are checked for randomness.
+const MIN_ENTROPY_LEN: usize = 20;
+
+/// Shannon entropy in bits per character above which a word looks like a key.
+///
+/// Random base64 strings exceed this, while hex digests and UUIDs do not.
+const MIN_ENTROPY: f64 = 4.0;
+
+/// Masks secrets in text that is about to be logged or echoed back.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self {
+            patterns: DEFAULT_PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl Redactor {
+    /// Creates a redactor using the default patterns plus `patterns`.
+    pub fn new<I, S>(patterns: I) -> Result<Self, regex::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut redactor = Self::default();
+        for pattern in patterns {
+            redactor.patterns.push(Regex::new(pattern.as_ref())?);
+        }
+        Ok(redactor)
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if let Cow::Owned(replaced) = pattern.replace_all(&text, |caps: &regex::Captures| {
+                format!("{}{}", caps.get(1).map_or("", |m| m.as_str()), MASK)
+            }) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        if text.split(is_separator).any(is_high_entropy) {
+            text = Cow::Owned(
+                text.split_inclusive(is_separator)
+                    .map(|word| {
+                        let (word, sep) = match word.char_indices().last() {
+                            Some((pos, c)) if is_separator(c) => word.split_at(pos),
+                            _ => (word, ""),
+                        };
+                        if is_high_entropy(word) {
+                            format!("{}{}", MASK, sep)
+                        } else {
+                            format!("{}{}", word, sep)
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        text
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '"' | '\'' | '`' | ',' | ';' | ':' | '=' | '(' | ')')
+}
+
+fn is_high_entropy(word: &str) -> bool {
+    let len = word.chars().count();
+    if len < MIN_ENTROPY_LEN
+        || !word.chars().any(|c| c.is_ascii_digit())
+        || !word.chars().any(|c| c.is_ascii_alphabetic())
+    {
+        return false;
+    }
+
+    let mut counts = HashMap::new();
+    for c in word.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    let entropy = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len as f64;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+    entropy >= MIN_ENTROPY
+}
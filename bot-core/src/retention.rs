@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use yozuk_prefs::Preferences;
+
+const RETENTION_KEY: &str = "retention";
+const SCANNED_KEY: &str = "cleanup_scanned";
+
+/// Discord only bulk-deletes messages younger than 14 days; the margin covers
+/// the time between planning and deleting.
+pub const MAX_BULK_DELETE_AGE: Duration = Duration::from_secs(13 * 24 * 60 * 60);
+
+/// Number of messages a single bulk delete accepts.
+pub const MAX_BULK_DELETE: usize = 100;
+
+/// How long the bot's own replies are kept in a chat, written as a number with
+/// a unit: `30m`, `24h` or `7d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention(pub Duration);
+
+impl Retention {
+    /// Returns the retention configured for a chat, if any.
+    pub fn load(store: &Preferences, platform: &str, chat_id: &str) -> Result<Option<Self>> {
+        store
+            .chat_value(platform, chat_id, RETENTION_KEY)?
+            .map(|value| value.parse())
+            .transpose()
+    }
+
+    /// Returns all chats with a retention.
+    pub fn all(store: &Preferences, platform: &str) -> Result<Vec<(String, Self)>> {
+        store
+            .chats_with(platform, RETENTION_KEY)?
+            .into_iter()
+            .map(|(chat_id, value)| Ok((chat_id, value.parse()?)))
+            .collect()
+    }
+}
+
+/// Stores the retention of a chat, or turns the cleanup off with `None`.
+pub fn set_retention(
+    store: &Preferences,
+    platform: &str,
+    chat_id: &str,
+    retention: Option<Retention>,
+) -> Result<()> {
+    match retention {
+        Some(retention) => {
+            store.set_chat_value(platform, chat_id, RETENTION_KEY, &retention.to_string())
+        }
+        None => store.remove_chat_value(platform, chat_id, RETENTION_KEY),
+    }
+}
+
+/// Returns the ID of the newest message the cleanup has looked at in a chat.
+/// Older messages have been deleted or kept already.
+pub fn cleanup_scanned(store: &Preferences, platform: &str, chat_id: &str) -> Result<Option<u64>> {
+    store
+        .chat_value(platform, chat_id, SCANNED_KEY)?
+        .map(|value| Ok(value.parse()?))
+        .transpose()
+}
+
+/// Stores where the next cleanup of a chat starts.
+pub fn set_cleanup_scanned(
+    store: &Preferences,
+    platform: &str,
+    chat_id: &str,
+    id: u64,
+) -> Result<()> {
+    store.set_chat_value(platform, chat_id, SCANNED_KEY, &id.to_string())
+}
+
+/// Returns the ID after which the cleanup continues, given the IDs of a page
+/// of messages fetched after the last scanned one, or `None` once the scan
+/// has caught up with `cutoff`, the first ID too young to delete.
+pub fn next_cleanup_page(page: &[u64], page_size: usize, cutoff: u64) -> Option<u64> {
+    match page.iter().max() {
+        Some(&newest) if page.len() == page_size && newest < cutoff => Some(newest),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Retention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        match (secs % 86400, secs % 3600) {
+            (0, _) => write!(f, "{}d", secs / 86400),
+            (_, 0) => write!(f, "{}h", secs / 3600),
+            _ => write!(f, "{}m", secs / 60),
+        }
+    }
+}
+
+impl FromStr for Retention {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let unit = match s.chars().last() {
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            _ => return Err(anyhow!("expected a number with m, h or d, got {:?}", s)),
+        };
+        let count: u64 = s[..s.len() - 1].parse()?;
+        if count == 0 {
+            return Err(anyhow!("retention must be positive"));
+        }
+        Ok(Self(Duration::from_secs(count.saturating_mul(unit))))
+    }
+}
+
+/// A message the bot has sent, as seen by the cleanup.
+#[derive(Debug, Clone)]
+pub struct OwnMessage<T> {
+    pub id: T,
+    pub age: Duration,
+    pub pinned: bool,
+}
+
+/// The deletions that remove the expired messages of a chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cleanup<T> {
+    /// Batches of recent messages, each deleted with one request.
+    pub bulk: Vec<Vec<T>>,
+    /// Messages that have to be deleted one by one.
+    pub single: Vec<T>,
+}
+
+impl<T> Cleanup<T> {
+    pub fn len(&self) -> usize {
+        self.bulk.iter().map(Vec::len).sum::<usize>() + self.single.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Picks the messages older than `retention`, skipping pinned ones.
+///
+/// Messages too old for a bulk delete, and a batch that would hold a single
+/// message, are deleted individually.
+pub fn plan_cleanup<T, I>(messages: I, retention: Retention) -> Cleanup<T>
+where
+    I: IntoIterator<Item = OwnMessage<T>>,
+{
+    let mut recent = vec![];
+    let mut single = vec![];
+    for msg in messages {
+        if msg.pinned || msg.age <= retention.0 {
+            continue;
+        }
+        if msg.age < MAX_BULK_DELETE_AGE {
+            recent.push(msg.id);
+        } else {
+            single.push(msg.id);
+        }
+    }
+
+    let mut bulk = vec![];
+    let mut recent = recent.into_iter().peekable();
+    while recent.peek().is_some() {
+        let batch = recent.by_ref().take(MAX_BULK_DELETE).collect::<Vec<_>>();
+        if batch.len() == 1 {
+            single.extend(batch);
+        } else {
+            bulk.push(batch);
+        }
+    }
+    Cleanup { bulk, single }
+}
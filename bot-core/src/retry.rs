@@ -0,0 +1,75 @@
+use anyhow::{anyhow, Error, Result};
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how patiently [`retry`] repeats a failing call.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Limit for each attempt; an attempt that takes longer counts as failed.
+    pub timeout: Option<Duration>,
+    /// Tells transient errors from permanent ones, which are returned at once.
+    pub is_retryable: fn(&Error) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(30)),
+            is_retryable: |_| true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_classifier(self, is_retryable: fn(&Error) -> bool) -> Self {
+        Self {
+            is_retryable,
+            ..self
+        }
+    }
+
+    /// Exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        max.mul_f64(rand::thread_rng().gen())
+    }
+}
+
+/// Calls `f` until it succeeds, fails permanently or runs out of attempts.
+pub async fn retry<F, Fut, T, E>(policy: &RetryPolicy, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let result = match policy.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, f()).await {
+                Ok(result) => result.map_err(Into::into),
+                Err(_) => Err(anyhow!("timed out after {:?}", timeout)),
+            },
+            None => f().await.map_err(Into::into),
+        };
+        match result {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < policy.max_attempts && (policy.is_retryable)(&err) => {
+                let backoff = policy.backoff(attempt);
+                tracing::warn!(attempt, error = %err, "retrying in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
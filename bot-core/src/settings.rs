@@ -0,0 +1,122 @@
+use crate::engine::Timezone;
+use crate::i18n::Lang;
+use anyhow::{bail, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use yozuk_prefs::Preferences;
+
+/// Handles "set language …" and "set timezone …" messages before Yozuk sees them.
+///
+/// Returns `None` if the text is not such a command, otherwise the reply.
+pub fn set_preference(
+    store: &Preferences,
+    platform: &str,
+    user_id: &str,
+    text: &str,
+    lang: Lang,
+) -> Option<Result<String>> {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let (key, value) = match words.as_slice() {
+        [set, key, value] if set.eq_ignore_ascii_case("set") => (key.to_ascii_lowercase(), *value),
+        _ => return None,
+    };
+
+    let reply = match key.as_str() {
+        "language" | "lang" | "locale" => match Lang::from_locale(value) {
+            Some(new_lang) => store
+                .update(platform, user_id, |prefs| {
+                    prefs.locale = Some(new_lang.code().into())
+                })
+                .map(|_| new_lang.tr("language-set", &[("lang", new_lang.code())])),
+            None => Ok(lang.tr("unsupported-language", &[("lang", value)])),
+        },
+        "timezone" | "tz" => {
+            if let Ok(timezone) = value.parse::<Timezone>() {
+                store
+                    .update(platform, user_id, |prefs| {
+                        prefs.timezone = Some(timezone.to_string())
+                    })
+                    .map(|_| lang.tr("timezone-set", &[("timezone", value)]))
+            } else {
+                Ok(lang.tr("invalid-timezone", &[("timezone", value)]))
+            }
+        }
+        _ => return None,
+    };
+    Some(reply)
+}
+
+const MODE_KEY: &str = "mode";
+
+/// How much of the command outputs is sent to a chat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChatMode {
+    /// Everything the commands return.
+    #[default]
+    Verbose,
+    /// Only the data of outputs that have any, without their comments.
+    Quiet,
+}
+
+impl ChatMode {
+    pub const ALL: &'static [Self] = &[Self::Quiet, Self::Verbose];
+
+    /// Returns the mode stored for a chat.
+    pub fn load(store: &Preferences, platform: &str, chat_id: &str) -> Result<Self> {
+        match store.chat_value(platform, chat_id, MODE_KEY)? {
+            Some(mode) => mode.parse(),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+impl fmt::Display for ChatMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Verbose => write!(f, "verbose"),
+            Self::Quiet => write!(f, "quiet"),
+        }
+    }
+}
+
+impl FromStr for ChatMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "verbose" => Ok(Self::Verbose),
+            "quiet" => Ok(Self::Quiet),
+            _ => bail!("expected \"verbose\" or \"quiet\", got {:?}", s),
+        }
+    }
+}
+
+/// Handles "mode …" messages, which change the [`ChatMode`] of a chat.
+///
+/// Returns `None` if the text is not such a command, otherwise the reply.
+pub fn set_mode(
+    store: &Preferences,
+    platform: &str,
+    chat_id: &str,
+    text: &str,
+    lang: Lang,
+) -> Option<Result<String>> {
+    let value = match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [mode, value] if mode.eq_ignore_ascii_case("mode") => *value,
+        _ => return None,
+    };
+    let reply = match value.parse::<ChatMode>() {
+        Ok(mode) => store
+            .set_chat_value(platform, chat_id, MODE_KEY, &mode.to_string())
+            .map(|_| lang.tr("mode-set", &[("mode", &mode.to_string())])),
+        Err(_) => {
+            let modes = ChatMode::ALL
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok(lang.tr("unknown-mode", &[("mode", value), ("modes", &modes)]))
+        }
+    };
+    Some(reply)
+}
@@ -0,0 +1,359 @@
+use crate::archive::{unpack_archives, ArchiveLimits};
+use crate::engine::EngineOptions;
+use crate::help::{help_text, is_help};
+use crate::i18n::{Lang, LocaleOverrides};
+use crate::memory::{Attachment, ConversationMemory};
+use crate::metrics::Metrics;
+use crate::plan::{plan_failure, plan_response, spawn_plan, Limits, PlanStatus, ResponsePlan};
+use crate::preprocess::Preprocessor;
+use crate::rate_limit::{Decision, RateLimiter};
+use crate::redact::Redactor;
+use crate::settings::{set_mode, set_preference, ChatMode};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+use yozuk::Yozuk;
+use yozuk_prefs::Preferences;
+use yozuk_sdk::prelude::*;
+
+/// A message received by a [`BotTransport`], with what [`run_bot`] needs to
+/// answer it.
+pub struct IncomingMessage<R> {
+    pub text: String,
+
+    /// Used if nothing is left of `text` after preprocessing, e.g. the
+    /// contents of a link preview. Left unset for messages with attachments,
+    /// which run with an empty command instead.
+    pub alt_text: Option<String>,
+
+    pub username: Option<String>,
+
+    /// Identifies the sender for preferences, rate limits and locale overrides.
+    pub user_id: String,
+
+    /// Identifies the conversation for chat modes, memory and locale overrides.
+    pub chat_id: String,
+
+    /// Language used unless the user or the operator picked another one.
+    pub lang: Lang,
+
+    /// When the platform says the message was sent, which analytics report
+    /// instead of the time it is handled.
+    pub sent_at: Option<SystemTime>,
+
+    /// Carries the identifiers of the platform into the logs of the request.
+    pub span: Span,
+
+    pub reply: R,
+}
+
+/// Converts a timestamp in seconds since the Unix epoch, as platforms report
+/// them, for [`IncomingMessage::sent_at`].
+pub fn unix_time(secs: i64) -> Option<SystemTime> {
+    let secs = u64::try_from(secs).ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Awaits a message that a transport loads after being notified of it, e.g.
+/// from a database it may have been deleted from in the meantime.
+///
+/// A failure is logged and treated like a message that isn't addressed to
+/// the bot, so that one bad message can't keep the transport from the next.
+pub async fn load_or_skip<T, F>(load: F, redactor: &Redactor) -> Option<T>
+where
+    F: Future<Output = Result<Option<T>>>,
+{
+    match load.await {
+        Ok(msg) => msg,
+        Err(err) => {
+            tracing::error!("{}", redactor.redact(&err.to_string()));
+            None
+        }
+    }
+}
+
+/// Something [`run_bot`] asks a transport to deliver.
+#[derive(Debug, Clone)]
+pub enum RenderedMessage {
+    /// A short canned reply, e.g. to a preference change.
+    Notice(String),
+
+    /// The response to a command.
+    Response {
+        plan: ResponsePlan,
+        /// The preprocessed message, e.g. to name a thread after it.
+        input: String,
+    },
+}
+
+/// Connects [`run_bot`] to a chat platform.
+#[async_trait]
+pub trait BotTransport: Send + Sync + 'static {
+    /// Handle to the message being answered.
+    type Reply: Send + Sync + 'static;
+
+    /// Waits for the next message addressed to the bot, or returns `None`
+    /// once the transport has shut down.
+    async fn next(&self) -> Option<IncomingMessage<Self::Reply>>;
+
+    /// Fetches the attachments of a message that is going to be answered.
+    ///
+    /// Returns `None` if the transport has answered the message itself, e.g.
+    /// because an attachment is too large.
+    async fn attachments(
+        &self,
+        _reply: &Self::Reply,
+        _lang: Lang,
+    ) -> Result<Option<Vec<Attachment>>> {
+        Ok(Some(vec![]))
+    }
+
+    async fn send(&self, reply: &Self::Reply, lang: Lang, message: RenderedMessage) -> Result<()>;
+
+    /// Largest file the chat of `reply` accepts, if it differs from
+    /// [`Limits::max_file_size`].
+    async fn max_file_size(&self, _reply: &Self::Reply) -> Option<usize> {
+        None
+    }
+
+    /// Called before a command is handled.
+    async fn started(&self, _reply: &Self::Reply, _lang: Lang) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after a command has been handled, with `None` if that failed.
+    async fn finished(&self, _reply: &Self::Reply, _status: Option<PlanStatus>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Settings and state shared by the messages handled by [`run_bot`].
+pub struct BotConfig {
+    /// Key of the platform in the preference store.
+    pub platform: &'static str,
+    pub limits: Limits,
+    pub engine: EngineOptions,
+    pub preprocessor: Preprocessor,
+    pub metrics: Arc<Metrics>,
+    pub rate_limiter: Option<Arc<RateLimiter<String>>>,
+    pub memory: Option<Arc<ConversationMemory<(String, String)>>>,
+    pub prefs: Option<Arc<Preferences>>,
+    pub locales: LocaleOverrides,
+
+    /// Replace archive attachments with the file they contain.
+    pub archives: Option<ArchiveLimits>,
+
+    /// Answer "help" with the list of skills.
+    pub help: bool,
+
+    /// Let chats switch between quiet and verbose output with "mode …".
+    pub chat_modes: bool,
+}
+
+impl BotConfig {
+    pub fn new(platform: &'static str, limits: Limits) -> Self {
+        Self {
+            platform,
+            limits,
+            engine: Default::default(),
+            preprocessor: Default::default(),
+            metrics: Arc::new(Metrics::new(platform)),
+            rate_limiter: None,
+            memory: None,
+            prefs: None,
+            locales: Default::default(),
+            archives: None,
+            help: false,
+            chat_modes: false,
+        }
+    }
+}
+
+/// Answers the messages of `transport` until it shuts down, then waits for
+/// the pending answers.
+///
+/// Messages are handled concurrently. Errors are logged with the span of the
+/// message they belong to.
+pub async fn run_bot<T: BotTransport>(transport: Arc<T>, zuk: Arc<Yozuk>, config: BotConfig) {
+    let bot = Arc::new(Bot {
+        transport,
+        zuk,
+        config,
+    });
+    let mut tasks: Vec<JoinHandle<()>> = vec![];
+    while let Some(msg) = bot.transport.next().await {
+        tasks.retain(|task| !task.is_finished());
+        let bot = bot.clone();
+        tasks.push(tokio::spawn(async move {
+            let span = msg.span.clone();
+            if let Err(err) = bot.handle(msg).instrument(span.clone()).await {
+                let err = bot
+                    .config
+                    .limits
+                    .redactor
+                    .redact(&err.to_string())
+                    .into_owned();
+                span.in_scope(|| tracing::error!("{}", err));
+            }
+        }));
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+}
+
+struct Bot<T> {
+    transport: Arc<T>,
+    zuk: Arc<Yozuk>,
+    config: BotConfig,
+}
+
+impl<T: BotTransport> Bot<T> {
+    async fn handle(&self, msg: IncomingMessage<T::Reply>) -> Result<()> {
+        let config = &self.config;
+        let mut input = config.preprocessor.process(&msg.text);
+        if input.text.is_empty() {
+            if let Some(alt_text) = &msg.alt_text {
+                input = config.preprocessor.process(alt_text);
+            }
+        }
+        if !input.removed.is_empty() {
+            tracing::debug!(removed = ?input.removed, "preprocessed input");
+        }
+        let text = input.text;
+
+        config.metrics.message_received();
+        let _in_flight = config.metrics.in_flight();
+        let prefs = match &config.prefs {
+            Some(store) => store.get(config.platform, &msg.user_id)?,
+            None => Default::default(),
+        };
+        let lang = config.locales.resolve(
+            prefs.locale.as_deref(),
+            [msg.chat_id.as_str(), &msg.user_id],
+            msg.lang,
+        );
+        let reply = &msg.reply;
+
+        if let Some(limiter) = &config.rate_limiter {
+            if let Decision::Limited { retry_after } = limiter.check(msg.user_id.clone()) {
+                let seconds = retry_after.as_secs() + 1;
+                let text = lang.tr("rate-limited", &[("seconds", &seconds.to_string())]);
+                return self.notice(reply, lang, text).await;
+            }
+        }
+
+        let mut mode = ChatMode::default();
+        if let Some(store) = &config.prefs {
+            if let Some(text) = set_preference(store, config.platform, &msg.user_id, &text, lang) {
+                return self.notice(reply, lang, text?).await;
+            }
+            if config.chat_modes {
+                if let Some(text) = set_mode(store, config.platform, &msg.chat_id, &text, lang) {
+                    return self.notice(reply, lang, text?).await;
+                }
+                mode = ChatMode::load(store, config.platform, &msg.chat_id)?;
+            }
+        }
+
+        if config.help && is_help(&text) {
+            return self.notice(reply, lang, help_text(&self.zuk, lang)).await;
+        }
+
+        let mut user = UserContext {
+            username: msg.username,
+            locale: prefs.locale.or_else(|| Some(lang.code().into())),
+            timezone: prefs.timezone,
+            ..Default::default()
+        };
+        config.engine.apply(&mut user);
+
+        self.transport.started(reply, lang).await?;
+        let key = (msg.chat_id, msg.user_id);
+        let limits = Limits {
+            quiet: mode == ChatMode::Quiet,
+            sent_at: msg.sent_at,
+            ..config.limits.clone()
+        };
+        let result = self.respond(reply, key, text, user, lang, limits).await;
+        let finished = self
+            .transport
+            .finished(reply, result.as_ref().ok().copied())
+            .await;
+        // Report the original error rather than a failed acknowledgement.
+        result?;
+        finished
+    }
+
+    async fn respond(
+        &self,
+        reply: &T::Reply,
+        key: (String, String),
+        text: String,
+        user: UserContext,
+        lang: Lang,
+        limits: Limits,
+    ) -> Result<PlanStatus> {
+        let attachments = match self.transport.attachments(reply, lang).await? {
+            Some(attachments) => attachments,
+            None => return Ok(PlanStatus::Failure),
+        };
+
+        let config = &self.config;
+        let attachments = match &config.archives {
+            Some(archives) => match unpack_archives(attachments, archives) {
+                Ok(attachments) => attachments,
+                Err(err) => {
+                    let plan = plan_failure(
+                        err.category(),
+                        err.message(lang),
+                        format!("rejected an archive: {}", err),
+                    );
+                    self.transport
+                        .send(reply, lang, RenderedMessage::Response { plan, input: text })
+                        .await?;
+                    return Ok(PlanStatus::Failure);
+                }
+            },
+            None => attachments,
+        };
+        let max_file_size = self.transport.max_file_size(reply).await;
+        let limits = Limits {
+            max_file_size: max_file_size.or(limits.max_file_size),
+            // The cache would read streamed files into memory to hash them.
+            cache: limits
+                .cache
+                .clone()
+                .filter(|_| !attachments.iter().any(Attachment::is_streamed)),
+            ..limits
+        };
+        let input = text.clone();
+        let zuk = self.zuk.clone();
+        let memory = config.memory.clone();
+        let plan = spawn_plan(&config.limits, &text, move || match &memory {
+            Some(memory) => memory.plan(key, &zuk, &input, attachments, &user, &limits),
+            None => {
+                let streams = attachments.iter().map(Attachment::stream).collect();
+                plan_response(&zuk, &input, streams, &user, &limits)
+            }
+        })
+        .await;
+        config.metrics.record(&plan);
+
+        let status = plan.status;
+        self.transport
+            .send(reply, lang, RenderedMessage::Response { plan, input: text })
+            .await?;
+        Ok(status)
+    }
+
+    async fn notice(&self, reply: &T::Reply, lang: Lang, text: String) -> Result<()> {
+        self.transport
+            .send(reply, lang, RenderedMessage::Notice(text))
+            .await
+    }
+}
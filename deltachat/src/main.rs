@@ -1,54 +1,481 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
 use clap::Parser;
-use deltachat::chat::{self, ChatId};
+use deltachat::accounts::Accounts;
+use deltachat::chat::{self, Chat, ChatId};
 use deltachat::config;
-use deltachat::contact::Contact;
+use deltachat::constants::Chattype;
+use deltachat::contact::{Contact, VerifiedStatus};
 use deltachat::context::*;
-use deltachat::message::{Message, Viewtype};
-use deltachat::{Event, EventType, Events};
+use deltachat::message::{self, Message, MsgId, Viewtype};
+use deltachat::securejoin;
+use deltachat::{EventEmitter, EventType, Events};
+use std::collections::HashMap;
+use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
-use std::str;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tempfile::NamedTempFile;
-use yozuk::Yozuk;
+use tracing::{Instrument, Span};
+use yozuk_bot_core::{
+    html_to_text, init_logging, load_or_skip, paginate, retry, run_bot, selftest,
+    strip_forward_header, unix_time, Admission, AnalyticsSink, ArchiveLimits, Attachment,
+    BotConfig, BotTransport, ConfigFile, ContactGate, ConversationMemory, EngineOptions, Health,
+    IncomingMessage, InlineBinary, Lang, Limits, LocaleOverride, LocaleOverrides, Location,
+    LogAnalytics, LogFormat, MailAccount, Metrics, NoopAnalytics, PageLimits, PlanItem, PlanStatus,
+    Preferences, Progress, RateLimit, RateLimiter, Redactor, RenderedMessage, ReplyDestination,
+    ResultCache, RetryPolicy, Route, TextPolicy, Timezone, CONFIG_HELP, DEFAULT_UNCACHED_SKILLS,
+};
 use yozuk_sdk::prelude::*;
 
 #[derive(Parser)]
-#[clap(author, version, about)]
+#[clap(author, version, about, after_help = CONFIG_HELP)]
 pub struct Args {
+    /// Read settings from a TOML file
     #[clap(long)]
-    pub mail: String,
+    pub config: Option<PathBuf>,
+
+    #[clap(long)]
+    pub mail: Option<String>,
 
     #[clap(long, env("MAIL_PASSWORD"), hide_env_values = true)]
-    pub password: String,
+    pub password: Option<String>,
+
+    #[clap(long)]
+    pub dbfile: Option<PathBuf>,
+
+    /// Serve several accounts stored in this directory instead of --dbfile
+    #[clap(long, conflicts_with_all = &["dbfile", "mail"])]
+    pub dbdir: Option<PathBuf>,
+
+    /// Account to serve with --dbdir, as "mail=<address>,password_env=<variable>" (repeatable)
+    #[clap(long = "account")]
+    pub accounts: Vec<MailAccount>,
+
+    /// How text outputs are sent: "file", "inline" or the maximum inline length [default: inline]
+    #[clap(long)]
+    pub text_policy: Option<TextPolicy>,
+
+    /// Send files as a single ZIP archive when there are more than this many
+    #[clap(long)]
+    pub bundle_threshold: Option<usize>,
+
+    /// Serve Prometheus metrics on this address
+    #[clap(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Limit requests per sender, e.g. "5/60" for 5 requests per minute
+    #[clap(long)]
+    pub rate_limit: Option<RateLimit>,
+
+    /// Also redact text matching this regular expression from error replies and logs (repeatable)
+    #[clap(long = "redact")]
+    pub redact_patterns: Vec<String>,
+
+    /// Language of canned replies when the user's language is unknown: en, de or ja [default: en]
+    #[clap(long)]
+    pub lang: Option<Lang>,
+
+    /// Remember the last command of each chat for this many seconds to understand follow-ups
+    #[clap(long)]
+    pub memory_ttl: Option<u64>,
+
+    /// Reply with the commands that would run instead of running them
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Store per-user preferences such as "set timezone Europe/Berlin" in this directory
+    #[clap(long)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Timezone for users who haven't set their own, e.g. "Europe/Berlin"
+    #[clap(long)]
+    pub timezone: Option<Timezone>,
+
+    /// Location for users, as "<latitude>,<longitude>"
+    #[clap(long)]
+    pub location: Option<Location>,
+
+    /// Serve /live and /ready probes on this address
+    #[clap(long)]
+    pub health_addr: Option<SocketAddr>,
+
+    /// Report not ready when no event has been seen for this many seconds [default: 600]
+    #[clap(long)]
+    pub health_stale_after: Option<u64>,
+
+    /// Log format: "pretty" or "json" [default: pretty]
+    #[clap(long)]
+    pub log_format: Option<LogFormat>,
+
+    /// Where answers are sent: "same" chat or "dm" [default: same]
+    #[clap(long)]
+    pub reply_destination: Option<ReplyDestination>,
+
+    /// Reuse the results of identical commands for this many seconds (0 disables the cache) [default: 0]
+    #[clap(long)]
+    pub cache_ttl: Option<u64>,
+
+    /// Never reuse results of this skill, e.g. "uuid" (repeatable) [default: random and time-dependent skills]
+    #[clap(long = "uncached-skill")]
+    pub uncached_skills: Vec<String>,
+
+    /// Cancel commands that take longer than this many seconds [default: 30]
+    #[clap(long)]
+    pub command_timeout: Option<u64>,
+
+    /// Append the matched skills and the processing time to replies
+    #[clap(long)]
+    pub verbose: bool,
+
+    /// Never send files; binary outputs are summarized or inlined instead
+    #[clap(long)]
+    pub text_only: bool,
 
+    /// Send binary outputs up to a size as text instead of files, e.g. "base64:64" or "hex:32"
     #[clap(long)]
-    pub dbfile: PathBuf,
+    pub inline_binary: Option<InlineBinary>,
+
+    /// Log the name and outcome of every command, without any message content
+    #[clap(long)]
+    pub analytics: bool,
+
+    /// Only answer verified contacts and those given with --allow-contact
+    #[clap(long)]
+    pub require_verified: bool,
+
+    /// Answer this address even if it is not verified (repeatable)
+    #[clap(long = "allow-contact")]
+    pub allowed_contacts: Vec<String>,
+
+    /// Run a known command and exit with an error if its output is wrong, before connecting
+    #[clap(long)]
+    pub selftest: bool,
+
+    /// Stream attachments larger than this many bytes from disk instead of loading them into memory
+    #[clap(long)]
+    pub stream_large_files: Option<u64>,
+
+    /// Language for a channel or contact, as "<id>=<locale>" (repeatable); a user's own setting still wins
+    #[clap(long = "locale-override")]
+    pub locale_overrides: Vec<LocaleOverride>,
+
+    /// Send "Working on it…" for commands that take longer than this many seconds, and delete it with the answer (0 disables) [default: 3]
+    #[clap(long)]
+    pub progress_delay: Option<u64>,
+
+    /// Run commands on the single file inside a ZIP or gzip attachment instead of the archive
+    #[clap(long)]
+    pub extract_archives: bool,
+}
+
+/// Where the accounts of the bot are kept.
+pub enum Store {
+    /// A single account in a database file.
+    File {
+        dbfile: PathBuf,
+        account: MailAccount,
+    },
+    /// Several accounts in a directory managed by Delta Chat.
+    Dir {
+        dbdir: PathBuf,
+        accounts: Vec<MailAccount>,
+    },
+}
+
+pub struct Config {
+    pub store: Store,
+    pub text_policy: TextPolicy,
+    pub bundle_threshold: Option<usize>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub rate_limit: Option<RateLimit>,
+    pub redactor: Redactor,
+    pub lang: Lang,
+    pub memory_ttl: Option<Duration>,
+    pub dry_run: bool,
+    pub data_dir: Option<PathBuf>,
+    pub engine: EngineOptions,
+    pub health_addr: Option<SocketAddr>,
+    pub health_stale_after: Duration,
+    pub log_format: LogFormat,
+    pub reply_destination: ReplyDestination,
+    pub cache: Option<Arc<ResultCache>>,
+    pub command_timeout: Duration,
+    pub verbose: bool,
+    pub text_only: bool,
+    pub inline_binary: Option<InlineBinary>,
+    pub analytics: bool,
+    pub gate: ContactGate,
+    pub selftest: bool,
+    pub stream_large_files: Option<u64>,
+    pub locales: LocaleOverrides,
+    pub progress_delay: Option<Duration>,
+    pub extract_archives: bool,
+}
+
+impl Config {
+    pub fn new(args: Args) -> Result<Self> {
+        let mut file = ConfigFile::load(args.config.as_deref())?;
+        let dbdir = file.value("dbdir", args.dbdir);
+        let accounts = file.list("accounts", args.accounts);
+        let (mail, password, dbfile) = match dbdir {
+            // Each account brings its own address and password.
+            Some(_) => {
+                for key in ["mail", "password", "password_file", "dbfile"] {
+                    file.ignore(key, "dbdir is set");
+                }
+                (None, None, None)
+            }
+            None => (
+                file.required("mail", args.mail),
+                file.secret("password", args.password),
+                file.required("dbfile", args.dbfile),
+            ),
+        };
+        let text_policy = file.value("text_policy", args.text_policy);
+        let bundle_threshold = file.value("bundle_threshold", args.bundle_threshold);
+        let metrics_addr = file.value("metrics_addr", args.metrics_addr);
+        let rate_limit = file.value("rate_limit", args.rate_limit);
+        let redact_patterns = file.list("redact_patterns", args.redact_patterns);
+        let lang = file.value("lang", args.lang);
+        let memory_ttl = file.value("memory_ttl", args.memory_ttl);
+        let dry_run = file.value("dry_run", args.dry_run.then_some(true));
+        let data_dir = file.value("data_dir", args.data_dir);
+        let timezone = file.value("timezone", args.timezone);
+        let location = file.value("location", args.location);
+        let health_addr = file.value("health_addr", args.health_addr);
+        let health_stale_after = file.value("health_stale_after", args.health_stale_after);
+        let log_format = file.value("log_format", args.log_format);
+        let reply_destination = file.value("reply_destination", args.reply_destination);
+        let cache_ttl = file.value("cache_ttl", args.cache_ttl);
+        let uncached_skills = file.list("uncached_skills", args.uncached_skills);
+        let command_timeout = file.value("command_timeout", args.command_timeout);
+        let verbose = file.value("verbose", args.verbose.then_some(true));
+        let text_only = file.value("text_only", args.text_only.then_some(true));
+        let inline_binary = file.value("inline_binary", args.inline_binary);
+        let analytics = file.value("analytics", args.analytics.then_some(true));
+        let require_verified =
+            file.value("require_verified", args.require_verified.then_some(true));
+        let allowed_contacts = file.list("allowed_contacts", args.allowed_contacts);
+        let selftest = file.value("selftest", args.selftest.then_some(true));
+        let stream_large_files = file.value("stream_large_files", args.stream_large_files);
+        let locale_overrides = file.list("locale_overrides", args.locale_overrides);
+        let progress_delay = file.value("progress_delay", args.progress_delay);
+        let extract_archives =
+            file.value("extract_archives", args.extract_archives.then_some(true));
+        file.finish()?;
+        let redactor = Redactor::new(redact_patterns)?;
+
+        let store = match dbdir {
+            Some(_) if accounts.is_empty() => bail!("--dbdir needs at least one --account"),
+            Some(dbdir) => Store::Dir { dbdir, accounts },
+            None if !accounts.is_empty() => bail!("--account needs --dbdir"),
+            // `finish` has failed if any required value is missing.
+            None => Store::File {
+                dbfile: dbfile.unwrap(),
+                account: MailAccount {
+                    mail: mail.unwrap(),
+                    password: password.unwrap(),
+                },
+            },
+        };
+
+        Ok(Self {
+            store,
+            text_policy: text_policy.unwrap_or(TextPolicy::AlwaysInline),
+            bundle_threshold,
+            metrics_addr,
+            rate_limit,
+            redactor,
+            lang: lang.unwrap_or_default(),
+            memory_ttl: memory_ttl.map(Duration::from_secs),
+            dry_run: dry_run.unwrap_or_default(),
+            data_dir,
+            engine: EngineOptions { timezone, location },
+            health_addr,
+            health_stale_after: Duration::from_secs(health_stale_after.unwrap_or(600)),
+            log_format: log_format.unwrap_or_default(),
+            reply_destination: reply_destination.unwrap_or_default(),
+            cache: cache_ttl.filter(|&ttl| ttl > 0).map(|ttl| {
+                let ttl = Duration::from_secs(ttl);
+                Arc::new(if uncached_skills.is_empty() {
+                    ResultCache::new(ttl, DEFAULT_UNCACHED_SKILLS.iter().copied())
+                } else {
+                    ResultCache::new(ttl, uncached_skills)
+                })
+            }),
+            command_timeout: Duration::from_secs(command_timeout.unwrap_or(30)),
+            verbose: verbose.unwrap_or_default(),
+            text_only: text_only.unwrap_or_default(),
+            inline_binary,
+            analytics: analytics.unwrap_or_default(),
+            gate: ContactGate::new(require_verified.unwrap_or_default(), allowed_contacts),
+            selftest: selftest.unwrap_or_default(),
+            stream_large_files,
+            locales: locale_overrides.into_iter().collect(),
+            progress_delay: Some(progress_delay.unwrap_or(3))
+                .filter(|&delay| delay > 0)
+                .map(Duration::from_secs),
+            extract_archives: extract_archives.unwrap_or_default(),
+        })
+    }
 }
 
 const DECRYPT_FAILURE: &str = "[This message was encrypted for another setup.]";
+const MAX_TEXT_LENGTH: usize = 5000;
+
+/// Code blocks are sent as plain text, so there are no fences to keep intact.
+const PAGE_LIMITS: PageLimits = PageLimits {
+    max_chars: Some(MAX_TEXT_LENGTH),
+    max_bytes: None,
+    prefer_line_breaks: true,
+    fence_aware: false,
+};
+
+const PLATFORM: &str = "deltachat";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::try_parse()?;
-    pretty_env_logger::try_init_timed()?;
+    let config = Config::new(Args::try_parse()?)?;
+    init_logging(config.log_format)?;
 
-    let ctx = Context::new(&args.dbfile, 0, Events::new()).await?;
-    let server = Server::new(args, ctx)?;
+    let zuk = Arc::new(config.engine.build());
+    if config.selftest {
+        selftest(&zuk)?;
+    }
+    let bot = bot_config(&config)?;
+    let server = Arc::new(Server::open(config, bot.metrics.clone()).await?);
     server.start().await?;
+    run_bot(server, zuk, bot).await;
     Ok(())
 }
 
+fn bot_config(config: &Config) -> Result<BotConfig> {
+    let limits = Limits {
+        text_policy: config.text_policy,
+        bundle_threshold: config.bundle_threshold,
+        redactor: config.redactor.clone(),
+        dry_run: config.dry_run,
+        cache: config.cache.clone(),
+        analytics: analytics(config.analytics),
+        text_only: config.text_only,
+        inline_binary: config.inline_binary,
+        verbose: config.verbose,
+        command_timeout: config.command_timeout,
+        ..Default::default()
+    };
+    let bot = BotConfig {
+        engine: config.engine.clone(),
+        rate_limiter: config
+            .rate_limit
+            .map(|limit| Arc::new(RateLimiter::new(limit))),
+        memory: config
+            .memory_ttl
+            .map(|ttl| Arc::new(ConversationMemory::new(ttl))),
+        prefs: config
+            .data_dir
+            .as_deref()
+            .map(Preferences::open)
+            .transpose()?
+            .map(Arc::new),
+        locales: config.locales.clone(),
+        archives: config.extract_archives.then(ArchiveLimits::default),
+        help: true,
+        chat_modes: true,
+        ..BotConfig::new(PLATFORM, limits)
+    };
+    if let Some(addr) = config.metrics_addr {
+        bot.metrics.serve(addr)?;
+    }
+    Ok(bot)
+}
+
+/// An account the bot answers messages for.
+struct Account {
+    login: MailAccount,
+    ctx: Context,
+}
+
 struct Server {
-    args: Args,
+    config: Config,
+    /// Accounts by the ID in their events.
+    accounts: HashMap<u32, Account>,
+    /// Chats of different accounts share IDs, so their keys name the account.
+    scoped_chats: bool,
+    events: EventEmitter,
+    /// Owns the accounts of a `--dbdir`.
+    _manager: Option<Accounts>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    retry: RetryPolicy,
+}
+
+/// The account and chat to answer in and the attachment of the message being
+/// answered.
+struct DeltaReply {
     ctx: Context,
-    zuk: Yozuk,
+    chat_id: ChatId,
+    file: Option<PathBuf>,
+    mime: Option<String>,
+    progress: Mutex<Option<Progress<MsgId>>>,
 }
 
 impl Server {
-    fn new(args: Args, ctx: Context) -> Result<Self> {
-        let zuk = Yozuk::builder().build();
-        Ok(Self { args, ctx, zuk })
+    async fn open(config: Config, metrics: Arc<Metrics>) -> Result<Self> {
+        let health = Arc::new(Health::new(config.health_stale_after));
+        if let Some(addr) = config.health_addr {
+            health.serve(addr)?;
+        }
+
+        let mut accounts = HashMap::new();
+        let (events, manager) = match &config.store {
+            Store::File { dbfile, account } => {
+                let ctx = Context::new(dbfile, 0, Events::new()).await?;
+                let events = ctx.get_event_emitter();
+                let login = account.clone();
+                accounts.insert(0, Account { login, ctx });
+                (events, None)
+            }
+            Store::Dir {
+                dbdir,
+                accounts: specs,
+            } => {
+                let mut manager = Accounts::new(dbdir.clone()).await?;
+                let mut known = HashMap::new();
+                for id in manager.get_all() {
+                    if let Some(ctx) = manager.get_account(id) {
+                        if let Some(addr) = ctx.get_config(config::Config::Addr).await? {
+                            known.insert(addr.to_lowercase(), id);
+                        }
+                    }
+                }
+                for spec in specs {
+                    let id = match known.get(&spec.mail.to_lowercase()) {
+                        Some(id) => *id,
+                        None => manager.add_account().await?,
+                    };
+                    let ctx = manager
+                        .get_account(id)
+                        .ok_or_else(|| anyhow!("account {} is missing", id))?;
+                    let login = spec.clone();
+                    accounts.insert(id, Account { login, ctx });
+                }
+                (manager.get_event_emitter(), Some(manager))
+            }
+        };
+
+        Ok(Self {
+            scoped_chats: matches!(config.store, Store::Dir { .. }),
+            config,
+            accounts,
+            events,
+            _manager: manager,
+            metrics,
+            health,
+            retry: Default::default(),
+        })
     }
 
     async fn start(&self) -> Result<()> {
@@ -57,137 +484,334 @@ impl Server {
         avatar.flush()?;
         let avatar = avatar.into_temp_path();
 
-        log::info!("configuring");
-        self.ctx
-            .set_config(config::Config::Addr, Some(&self.args.mail))
-            .await?;
-        self.ctx
-            .set_config(config::Config::MailPw, Some(&self.args.password))
-            .await?;
-        self.ctx
-            .set_config(config::Config::Displayname, Some("Yozuk"))
-            .await?;
-        self.ctx
-            .set_config(config::Config::Selfavatar, avatar.to_str())
-            .await?;
-        self.ctx.configure().await?;
-        self.ctx.start_io().await;
-
-        let events = self.ctx.get_event_emitter();
-        while let Some(event) = events.recv().await {
-            self.handle_event(event).await?;
+        for Account { login, ctx } in self.accounts.values() {
+            tracing::info!(account = %login.mail, "configuring");
+            ctx.set_config(config::Config::Addr, Some(&login.mail))
+                .await?;
+            ctx.set_config(config::Config::MailPw, Some(&login.password))
+                .await?;
+            ctx.set_config(config::Config::Displayname, Some("Yozuk"))
+                .await?;
+            ctx.set_config(config::Config::Selfavatar, avatar.to_str())
+                .await?;
+            ctx.configure().await?;
+            ctx.start_io().await;
         }
-
+        self.health.set_connected(true);
         Ok(())
     }
 
-    async fn handle_event(&self, event: Event) -> Result<()> {
-        if let EventType::IncomingMsg { msg_id, .. } = event.typ {
-            deltachat::message::markseen_msgs(&self.ctx, vec![msg_id]).await?;
-            let msg = Message::load_from_db(&self.ctx, msg_id).await?;
-            let chat_id = ChatId::create_for_contact(&self.ctx, msg.get_from_id()).await?;
-            let contact = Contact::load_from_db(&self.ctx, msg.get_from_id()).await?;
-            let username = if contact.get_addr() == contact.get_display_name() {
-                None
-            } else {
-                Some(contact.get_display_name().to_string())
-            };
-            let user = UserContext {
-                username,
-                ..Default::default()
-            };
+    /// Loads an incoming message and decides whether it is a command.
+    async fn incoming(
+        &self,
+        account: &Account,
+        msg_id: MsgId,
+    ) -> Result<Option<IncomingMessage<DeltaReply>>> {
+        let ctx = &account.ctx;
+        if let Err(err) = deltachat::message::markseen_msgs(ctx, vec![msg_id]).await {
+            tracing::warn!("failed to mark message as seen: {}", err);
+        }
+        // The message may have been deleted since the event was emitted.
+        let msg = match Message::load_from_db(ctx, msg_id).await {
+            Ok(msg) => msg,
+            Err(err) => {
+                tracing::warn!("failed to load message: {}", err);
+                return Ok(None);
+            }
+        };
+        // Delta Chat has no threads, so those answers stay in place as well.
+        let chat = Chat::load_from_db(ctx, msg.get_chat_id()).await?;
+        let private = chat.get_type() == Chattype::Single;
+        let chat_id = match self.config.reply_destination.route(private, false, "", 0) {
+            Route::Dm => ChatId::create_for_contact(ctx, msg.get_from_id()).await?,
+            Route::InPlace | Route::Thread(_) => msg.get_chat_id(),
+        };
+        let contact = Contact::load_from_db(ctx, msg.get_from_id()).await?;
+        let username = if contact.get_addr() == contact.get_display_name() {
+            None
+        } else {
+            Some(contact.get_display_name().to_string())
+        };
+        let user_id = contact.get_addr().to_string();
+        let mut chat = msg.get_chat_id().to_u32().to_string();
+        if self.scoped_chats {
+            chat = format!("{}/{}", account.login.mail, chat);
+        }
+        // Users who haven't been answered yet can't have set a language.
+        let lang = self
+            .config
+            .locales
+            .resolve(None, [chat.as_str(), &user_id], self.config.lang);
 
-            if let Some(text) = msg.get_text() {
-                if text.ends_with(DECRYPT_FAILURE) {
-                    chat::send_text_msg(
-                        &self.ctx,
-                        chat_id,
-                        "Sorry, I can't decrypt your message. Please send the message again."
-                            .into(),
-                    )
-                    .await?;
-                } else if !msg.is_system_message() {
-                    let mut streams = vec![];
-                    if let Some(file) = msg.get_file(&self.ctx) {
-                        let data = deltachat::tools::open_file_std(&self.ctx, file)?;
-                        let media_type = msg
-                            .get_filemime()
-                            .and_then(|mime| MediaTypeBuf::from_string(mime).ok())
-                            .unwrap_or_else(|| media_type!(APPLICATION / OCTET_STREAM).into());
-                        streams.push(InputStream::new(data, media_type));
-                    }
-                    self.handle_command(chat_id, text, streams, user).await?;
+        // Attachments may come without any caption.
+        let file = msg.get_file(ctx);
+        let mut text = msg.get_text().unwrap_or_default();
+        // Mails from regular clients may only have an HTML part.
+        if text.trim().is_empty() && msg.has_html() {
+            if let Some(html) = msg_id.get_html(ctx).await? {
+                text = html_to_text(&html);
+            }
+        }
+        // Forwarded content is answered for the forwarder; a forwarded file
+        // without text runs the skills that only need the file.
+        if msg.is_forwarded() {
+            tracing::debug!("forwarded message");
+            text = strip_forward_header(&text);
+        }
+        if text.is_empty() && file.is_none() {
+            return Ok(None);
+        }
+        if text.ends_with(DECRYPT_FAILURE) {
+            self.send_text(ctx, chat_id, lang.tr("decrypt-failure", &[]))
+                .await?;
+            return Ok(None);
+        }
+        if msg.is_system_message() {
+            return Ok(None);
+        }
+        if !self.config.gate.is_open() {
+            let verified = contact.is_verified(ctx).await? != VerifiedStatus::Unverified;
+            match self.config.gate.check(contact.get_addr(), verified) {
+                Admission::Allowed => {}
+                Admission::Instruct => {
+                    let invite = securejoin::get_securejoin_qr(ctx, None).await?;
+                    let text = lang.tr("verification-required", &[("invite", &invite)]);
+                    self.send_text(ctx, chat_id, text).await?;
+                    return Ok(None);
                 }
+                Admission::Ignore => return Ok(None),
             }
         }
 
-        Ok(())
+        Ok(Some(IncomingMessage {
+            text,
+            alt_text: None,
+            username,
+            user_id,
+            chat_id: chat,
+            lang: self.config.lang,
+            sent_at: unix_time(msg.get_timestamp()),
+            span: Span::current(),
+            reply: DeltaReply {
+                ctx: ctx.clone(),
+                chat_id,
+                file,
+                mime: msg.get_filemime(),
+                progress: Default::default(),
+            },
+        }))
     }
 
-    async fn handle_command(
+    async fn render_item(
         &self,
+        ctx: &Context,
         chat_id: ChatId,
-        text: String,
-        mut streams: Vec<InputStream>,
-        user: UserContext,
+        item: PlanItem,
+        lang: Lang,
     ) -> Result<()> {
-        let tokenizer = Tokenizer::new();
-        let tokens = tokenizer.tokenize(&text);
-        let commands = self.zuk.get_commands(&tokens, &streams);
-        if commands.is_empty() {
-            chat::send_text_msg(
-                &self.ctx,
-                chat_id,
-                format!("Sorry, I can't understand your request."),
-            )
+        match item {
+            PlanItem::Text(text) => {
+                self.send_text(ctx, chat_id, text).await?;
+            }
+            PlanItem::CodeBlock { text, .. } => {
+                self.send_text(ctx, chat_id, text).await?;
+            }
+            PlanItem::File {
+                name,
+                media_type,
+                data,
+            } => {
+                let dir = tempfile::tempdir()?;
+                let name = Path::new(&name)
+                    .file_name()
+                    .map(|name| name.to_os_string())
+                    .unwrap_or_else(|| "data".into());
+                let path = dir.path().join(name);
+                fs::write(&path, &data)?;
+                let media_type = media_type.to_string();
+                self.send(ctx, chat_id, || {
+                    let mut msg = Message::new(Viewtype::File);
+                    msg.set_file(path.to_str().unwrap(), Some(&media_type));
+                    msg
+                })
+                .await?;
+            }
+            PlanItem::Apology { suggestions } => {
+                let mut text = lang.tr("unrecognized", &[]);
+                if !suggestions.is_empty() {
+                    text.push_str(&format!("\n\n{}:", lang.tr("did-you-mean", &[])));
+                    for suggestion in suggestions {
+                        text.push_str(&format!("\n- {}", suggestion));
+                    }
+                }
+                self.send_text(ctx, chat_id, text).await?;
+            }
+            PlanItem::Error { category } => {
+                self.send_text(ctx, chat_id, category.message(lang)).await?;
+            }
+            PlanItem::Section { .. } => {}
+        }
+        Ok(())
+    }
+
+    async fn send_text(&self, ctx: &Context, chat_id: ChatId, text: String) -> Result<()> {
+        for page in paginate([text], &PAGE_LIMITS) {
+            retry(&self.retry, || {
+                chat::send_text_msg(ctx, chat_id, page.clone())
+            })
             .await?;
-            return Ok(());
         }
+        Ok(())
+    }
 
-        let result = self.zuk.run_commands(commands, &mut streams, Some(&user));
-        let outputs = match result {
-            Ok(output) => output,
-            Err(errors) => errors,
+    /// Deletes the progress message of `reply`, if one has been sent.
+    async fn clear_progress(&self, reply: &DeltaReply) {
+        let progress = reply.progress.lock().unwrap().take();
+        let msg_id = match progress {
+            Some(progress) => progress.finish().await,
+            None => None,
         };
-
-        for output in outputs {
-            self.render_output(chat_id, output).await?;
+        if let Some(msg_id) = msg_id {
+            if let Err(err) = message::delete_msgs(&reply.ctx, &[msg_id]).await {
+                tracing::warn!("failed to delete the progress message: {}", err);
+            }
         }
+    }
 
+    /// Sends a message, building it anew for each attempt.
+    async fn send<F>(&self, ctx: &Context, chat_id: ChatId, build: F) -> Result<()>
+    where
+        F: Fn() -> Message,
+    {
+        let build = &build;
+        retry(&self.retry, || async move {
+            chat::send_msg(ctx, chat_id, &mut build()).await
+        })
+        .await?;
         Ok(())
     }
+}
+
+#[async_trait]
+impl BotTransport for Server {
+    type Reply = DeltaReply;
 
-    async fn render_output(&self, chat_id: ChatId, output: Output) -> Result<()> {
-        for block in output.blocks {
-            self.render_block(chat_id, block).await?;
+    async fn next(&self) -> Option<IncomingMessage<DeltaReply>> {
+        while let Some(event) = self.events.recv().await {
+            self.health.event();
+            let account = match self.accounts.get(&event.id) {
+                Some(account) => account,
+                None => continue,
+            };
+            let addr = &account.login.mail;
+            match event.typ {
+                EventType::IncomingMsg { chat_id, msg_id } => {
+                    let span = tracing::info_span!(
+                        "message",
+                        platform = PLATFORM,
+                        account = %addr,
+                        chat_id = %chat_id,
+                        msg_id = %msg_id,
+                    );
+                    // One bad message must not take down the event loop.
+                    let msg = self.incoming(account, msg_id);
+                    if let Some(msg) = load_or_skip(msg, &self.config.redactor)
+                        .instrument(span)
+                        .await
+                    {
+                        return Some(msg);
+                    }
+                }
+                EventType::Info(msg) => {
+                    tracing::debug!(target: "deltachat", account = %addr, "{}", msg)
+                }
+                EventType::Warning(msg) => {
+                    tracing::warn!(target: "deltachat", account = %addr, "{}", msg)
+                }
+                EventType::Error(msg) => {
+                    tracing::error!(target: "deltachat", account = %addr, "{}", msg)
+                }
+                _ => {}
+            }
         }
-        Ok(())
+        None
     }
 
-    async fn render_block(&self, chat_id: ChatId, block: Block) -> Result<()> {
-        match block {
-            Block::Comment(comment) => {
-                chat::send_text_msg(&self.ctx, chat_id, comment.text).await?;
-            }
-            Block::Data(data) => {
-                if let Ok(text) = str::from_utf8(&data.data) {
-                    let mut msg = Message::new(Viewtype::Text);
-                    msg.set_text(Some(text.to_string()));
-                    chat::send_msg(&self.ctx, chat_id, &mut msg).await?;
-                } else {
-                    let mut file = NamedTempFile::new()?;
-                    file.write_all(&data.data)?;
-                    file.flush()?;
-                    let mut msg = Message::new(Viewtype::File);
-                    let media_type = data.media_type.to_string();
-                    msg.set_file(file.path().to_str().unwrap(), Some(&media_type));
-                    chat::send_msg(&self.ctx, chat_id, &mut msg).await?;
-                }
+    async fn attachments(&self, reply: &DeltaReply, _: Lang) -> Result<Option<Vec<Attachment>>> {
+        let file = match &reply.file {
+            Some(file) => file.clone(),
+            None => return Ok(Some(vec![])),
+        };
+        let media_type = reply
+            .mime
+            .clone()
+            .and_then(|mime| MediaTypeBuf::from_string(mime).ok())
+            .unwrap_or_else(|| media_type!(APPLICATION / OCTET_STREAM).into());
+        let size = fs::metadata(&file).map(|meta| meta.len()).ok();
+        // Blobs keep the extension of the name they were sent with.
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let mut attachment = match (self.config.stream_large_files, size) {
+            // The blob stays in place as long as the message exists.
+            (Some(max), Some(size)) if size > max => {
+                self.metrics.attachment(size as usize);
+                Attachment::from_file(file, media_type)
             }
             _ => {
-                chat::send_text_msg(&self.ctx, chat_id, "[unimplemented]".to_string()).await?;
+                let data = deltachat::tools::read_file(&reply.ctx, file).await?;
+                self.metrics.attachment(data.len());
+                Attachment::new(data, media_type)
+            }
+        };
+        attachment.name = name;
+        Ok(Some(vec![attachment]))
+    }
+
+    async fn send(&self, reply: &DeltaReply, lang: Lang, message: RenderedMessage) -> Result<()> {
+        self.clear_progress(reply).await;
+        match message {
+            RenderedMessage::Notice(text) => self.send_text(&reply.ctx, reply.chat_id, text).await,
+            RenderedMessage::Response { plan, .. } => {
+                for item in plan.items {
+                    // An item that can't be delivered doesn't stop the remaining ones.
+                    if let Err(err) = self
+                        .render_item(&reply.ctx, reply.chat_id, item, lang)
+                        .await
+                    {
+                        let err = self.metrics.send_failure(err);
+                        tracing::error!("{}", self.config.redactor.redact(&err.to_string()));
+                    }
+                }
+                Ok(())
             }
         }
+    }
+
+    async fn started(&self, reply: &DeltaReply, lang: Lang) -> Result<()> {
+        if let Some(delay) = self.config.progress_delay {
+            let ctx = reply.ctx.clone();
+            let chat_id = reply.chat_id;
+            let text = lang.tr("working", &[]);
+            let progress = Progress::start(delay, move || async move {
+                chat::send_text_msg(&ctx, chat_id, text).await
+            });
+            *reply.progress.lock().unwrap() = Some(progress);
+        }
+        Ok(())
+    }
+
+    async fn finished(&self, reply: &DeltaReply, _: Option<PlanStatus>) -> Result<()> {
+        // Nothing has been sent if the command failed.
+        self.clear_progress(reply).await;
         Ok(())
     }
 }
+
+fn analytics(enabled: bool) -> Arc<dyn AnalyticsSink> {
+    if enabled {
+        Arc::new(LogAnalytics::new(PLATFORM))
+    } else {
+        Arc::new(NoopAnalytics)
+    }
+}
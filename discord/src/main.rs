@@ -1,173 +1,1320 @@
 use anyhow::Result;
 use clap::Parser;
-use futures::future::join_all;
-use lazy_regex::regex_replace_all;
 use mediatype::{media_type, MediaType};
 use serenity::async_trait;
+use serenity::client::bridge::gateway::event::ShardStageUpdateEvent;
+use serenity::client::bridge::gateway::{ShardId, ShardManager};
+use serenity::gateway::ConnectionStage;
 use serenity::http::client::Http;
-use serenity::model::channel::Message;
+use serenity::http::StatusCode;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::{Channel, ChannelType, Embed, Message};
 use serenity::model::gateway::Ready;
-use serenity::model::id::UserId;
+use serenity::model::guild::{Guild, PremiumTier};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::Permissions;
 use serenity::prelude::*;
-use std::io::Cursor;
-use std::str;
+use serenity::utils::Colour;
+use serenity::Error as SerenityError;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use yozuk::Yozuk;
-use yozuk_helper_filetype::get_file_extension;
-use yozuk_sdk::prelude::*;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use yozuk_bot_core::{
+    cleanup_scanned, collect_downloads, download_all, format_size, init_logging, inline_files,
+    next_cleanup_page, paginate, plan_cleanup, plan_failure, preview_text, retry, run_bot,
+    selftest, set_cleanup_scanned, set_retention, unix_time, Access, AccessCache, AnalyticsSink,
+    ArchiveLimits, Attachment, BotConfig, BotTransport, ChannelPermissions, Cleanup, ConfigFile,
+    ConversationMemory, Delivery, DownloadFailure, Downloaded, EngineOptions, ErrorCategory,
+    Health, IncomingMessage, InlineBinary, Lang, Limits, LocaleOverride, LocaleOverrides, Location,
+    LogAnalytics, LogFormat, Metrics, NoopAnalytics, OwnMessage, PageLimits, PermissionSource,
+    PlanItem, PlanStatus, Preferences, Preprocessor, RateLimit, RateLimiter, Reaction,
+    ReactionUpdate, Redactor, RenderedMessage, ReplyDestination, ResponsePlan, ResultCache,
+    Retention, RetryPolicy, Route, TextPolicy, Timezone, CONFIG_HELP, DEFAULT_UNCACHED_SKILLS,
+    DOCS_URL,
+};
 
 const MAX_FILE_SIZE: usize = 10485760;
+/// Upload limits by boost level of the guild; DMs get the base limit.
+const BASE_UPLOAD_SIZE: usize = 25 * 1024 * 1024;
+const TIER_2_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+const TIER_3_UPLOAD_SIZE: usize = 100 * 1024 * 1024;
 
+const MAX_MESSAGE_LENGTH: usize = 2000;
+const PAGE_LIMITS: PageLimits = PageLimits {
+    max_chars: Some(MAX_MESSAGE_LENGTH),
+    max_bytes: None,
+    prefer_line_breaks: true,
+    fence_aware: true,
+};
+
+const PLATFORM: &str = "discord";
+
+const PENDING: char = '⏳';
+const SUCCESS: char = '✅';
+const FAILURE: char = '❌';
+
+const MAX_THREAD_NAME_LENGTH: usize = 100;
+
+/// Error details included with `--debug-replies` are cut off after this many characters.
+const MAX_DETAILS_LENGTH: usize = 800;
+
+/// Separates the outputs of messages that matched several commands.
+const SECTION_RULE: &str = "────────────";
+
+/// Text files are cut off after this many bytes if they can't be attached.
+const MAX_INLINED_FILE_SIZE: usize = 2 * MAX_MESSAGE_LENGTH;
+
+/// How long the permissions of the bot in a channel are remembered.
+const ACCESS_TTL: Duration = Duration::from_secs(60);
+/// How long the boost level of a guild is remembered.
+const UPLOAD_LIMIT_TTL: Duration = Duration::from_secs(10 * 60);
+const SHARD_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+const CONFIG_COMMAND: &str = "yozuk-config";
+const STATS_COMMAND: &str = "yozuk-stats";
+
+/// Start of the timestamps in Discord IDs, in milliseconds since the Unix epoch.
+const DISCORD_EPOCH: u64 = 1420070400000;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Pause between the requests of a cleanup, which runs next to the replies.
+const CLEANUP_DELAY: Duration = Duration::from_secs(1);
+/// Pages of 100 messages scanned per channel and run; a scan that stops
+/// there is resumed by the next run.
+const CLEANUP_MAX_PAGES: usize = 10;
+
+/// Makes the shard manager available to event handlers.
+struct ShardManagerKey;
+
+impl TypeMapKey for ShardManagerKey {
+    type Value = Arc<Mutex<ShardManager>>;
+}
+
+/// Forwards gateway events; messages are answered through [`DiscordTransport`].
 struct Handler {
-    user_id: UserId,
-    yozuk: Arc<Yozuk>,
+    health: Arc<Health>,
+    lang: Lang,
+    guild_langs: Arc<Mutex<HashMap<GuildId, Lang>>>,
+    messages: mpsc::UnboundedSender<(Context, Message)>,
+    prefs: Option<Arc<Preferences>>,
+    metrics: Arc<Metrics>,
+    cache: Option<Arc<ResultCache>>,
+    rate_limiter: Option<Arc<RateLimiter<String>>>,
+    owner_id: Option<UserId>,
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
-        if let Err(err) = handle_message(self, ctx, msg).await {
-            println!("{err}");
+        self.health.event();
+        // The receiver only goes away when the bot is shutting down.
+        let _ = self.messages.send((ctx, msg));
+    }
+
+    async fn guild_create(&self, _: Context, guild: Guild) {
+        if let Some(lang) = Lang::from_locale(&guild.preferred_locale) {
+            self.guild_langs.lock().await.insert(guild.id, lang);
         }
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        tracing::info!(user = %ready.user.name, "connected");
+        self.health.set_connected(true);
+        // Settings can only be stored with a data directory.
+        if self.prefs.is_some() {
+            if let Err(err) = register_config_command(&ctx.http).await {
+                tracing::error!("failed to register /{}: {}", CONFIG_COMMAND, err);
+            }
+        }
+        if let Err(err) = register_stats_command(&ctx.http).await {
+            tracing::error!("failed to register /{}: {}", STATS_COMMAND, err);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            let result = match command.data.name.as_str() {
+                CONFIG_COMMAND => self.configure(&ctx, &command).await,
+                STATS_COMMAND => self.stats(&ctx, &command).await,
+                _ => Ok(()),
+            };
+            if let Err(err) = result {
+                tracing::error!(channel_id = %command.channel_id, "{}", err);
+            }
+        }
+    }
+
+    async fn shard_stage_update(&self, _: Context, event: ShardStageUpdateEvent) {
+        self.health
+            .set_connected(event.new == ConnectionStage::Connected);
     }
 }
 
-async fn handle_message(handler: &Handler, ctx: Context, msg: Message) -> Result<()> {
-    let echo = msg.author.id == handler.user_id;
-    let dm = msg.guild_id.is_none();
-    let mention = msg.mentions.iter().any(|user| user.id == handler.user_id);
-    if !echo && (dm || mention) {
-        let content = regex_replace_all!(
-            r#"<@\d+>"#i,
-            & msg.content,
-            |_| String::new(),
+impl Handler {
+    /// Answers `/yozuk-config`, which members who can manage the server use
+    /// to change the settings of a channel.
+    async fn configure(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<()> {
+        let lang = Lang::from_locale(&command.locale).unwrap_or(self.lang);
+        let permissions = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions);
+        let allowed = matches!(permissions, Some(permissions) if permissions.manage_guild());
+        let text = match (&self.prefs, command.data.options.first()) {
+            _ if !allowed => lang.tr("config-forbidden", &[]),
+            (None, _) => lang.tr("settings-unavailable", &[]),
+            (Some(store), Some(option)) if option.name == "retention" => {
+                let value = option
+                    .options
+                    .first()
+                    .and_then(|option| option.value.as_ref())
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default();
+                let channel = command.channel_id.to_string();
+                if value.eq_ignore_ascii_case("off") {
+                    set_retention(store, PLATFORM, &channel, None)?;
+                    lang.tr("retention-off", &[])
+                } else if let Ok(retention) = value.parse::<Retention>() {
+                    set_retention(store, PLATFORM, &channel, Some(retention))?;
+                    lang.tr("retention-set", &[("retention", &retention.to_string())])
+                } else {
+                    lang.tr("invalid-retention", &[("retention", value)])
+                }
+            }
+            _ => return Ok(()),
+        };
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| data.content(text).ephemeral(true))
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl Handler {
+    /// Answers `/yozuk-stats` with the counters of the bot, for members who
+    /// can manage the server and for the owner.
+    async fn stats(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+        let lang = Lang::from_locale(&command.locale).unwrap_or(self.lang);
+        let permissions = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions);
+        let owner = self.owner_id == Some(command.user.id);
+        if !owner && !matches!(permissions, Some(permissions) if permissions.manage_guild()) {
+            command
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| {
+                            data.content(lang.tr("stats-forbidden", &[]))
+                                .ephemeral(true)
+                        })
+                })
+                .await?;
+            return Ok(());
+        }
+
+        let stats = self.metrics.stats();
+        let shard_latency = shard_latency(ctx).await;
+        let size = |len: Option<usize>| len.map_or_else(|| "off".into(), |len| len.to_string());
+        let cache = size(self.cache.as_ref().map(|cache| cache.len()));
+        let cooldowns = size(self.rate_limiter.as_ref().map(|limiter| limiter.len()));
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| {
+                        data.ephemeral(true).embed(|embed| {
+                            embed
+                                .title("Yozuk")
+                                .field("Uptime", uptime(stats.uptime), true)
+                                .field("Messages", stats.messages, true)
+                                .field("Commands", stats.commands, true)
+                                .field("Errors", stats.command_errors, true)
+                                .field("Unrecognized", stats.unrecognized, true)
+                                .field("Mean latency", millis(stats.mean_latency), true)
+                                .field("p95 latency", millis(stats.p95_latency), true)
+                                .field("Cached results", cache, true)
+                                .field("Rate-limited senders", cooldowns, true)
+                                .field("Shard latency", millis(shard_latency), true)
+                        })
+                    })
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+async fn shard_latency(ctx: &Context) -> Option<Duration> {
+    let manager = ctx.data.read().await.get::<ShardManagerKey>()?.clone();
+    let manager = manager.lock().await;
+    let runners = manager.runners.lock().await;
+    runners.get(&ShardId(ctx.shard_id))?.latency
+}
+
+fn uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{}d {}h {}m",
+        secs / 86400,
+        secs / 3600 % 24,
+        secs / 60 % 60
+    )
+}
+
+fn millis(duration: Option<Duration>) -> String {
+    duration.map_or_else(
+        || "–".into(),
+        |duration| format!("{} ms", duration.as_millis()),
+    )
+}
+
+async fn register_config_command(http: &Http) -> Result<()> {
+    Command::create_global_application_command(http, |command| {
+        command
+            .name(CONFIG_COMMAND)
+            .description("Change the settings of Yozuk in this channel")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("retention")
+                    .description("Delete my replies in this channel after a while")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("duration")
+                            .description("e.g. 30m, 24h or 7d, or off to keep replies")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+    })
+    .await?;
+    Ok(())
+}
+
+/// The command is offered to everyone: default member permissions would hide
+/// it from the owner in servers they can't manage, so `stats` checks them.
+async fn register_stats_command(http: &Http) -> Result<()> {
+    Command::create_global_application_command(http, |command| {
+        command
+            .name(STATS_COMMAND)
+            .description("Show how Yozuk is doing")
+    })
+    .await?;
+    Ok(())
+}
+
+/// Deletes the replies of the bot that are older than the retention of their
+/// channel, in all channels that have one.
+async fn clean_up(http: Arc<Http>, store: Arc<Preferences>, user_id: UserId) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let channels = match Retention::all(&store, PLATFORM) {
+            Ok(channels) => channels,
+            Err(err) => {
+                tracing::error!("failed to load retention settings: {}", err);
+                continue;
+            }
+        };
+        for (channel, retention) in channels {
+            let channel = match channel.parse() {
+                Ok(id) => ChannelId(id),
+                Err(_) => continue,
+            };
+            match clean_up_channel(&http, &store, channel, user_id, retention).await {
+                Ok(removed) => {
+                    tracing::info!(channel_id = %channel, removed, "cleaned up old replies")
+                }
+                Err(err) => tracing::warn!(channel_id = %channel, "failed to clean up: {}", err),
+            }
+        }
+    }
+}
+
+/// Returns the number of deleted messages.
+///
+/// Messages are scanned oldest first, starting after the newest one scanned
+/// by the previous run, up to the ones sent before the retention.
+async fn clean_up_channel(
+    http: &Http,
+    store: &Preferences,
+    channel: ChannelId,
+    user_id: UserId,
+    retention: Retention,
+) -> Result<usize> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    // IDs grow with time, so messages older than the retention have lower IDs.
+    let cutoff = now.saturating_sub(retention.0).as_millis() as u64;
+    let cutoff = cutoff.saturating_sub(DISCORD_EPOCH) << 22;
+    let chat_id = channel.to_string();
+    let mut after = cleanup_scanned(store, PLATFORM, &chat_id)?.unwrap_or(0);
+    let mut own = vec![];
+    for _ in 0..CLEANUP_MAX_PAGES {
+        if after >= cutoff {
+            break;
+        }
+        let page = channel
+            .messages(http, |builder| builder.after(MessageId(after)).limit(100))
+            .await?;
+        tokio::time::sleep(CLEANUP_DELAY).await;
+        let ids = page.iter().map(|msg| msg.id.0).collect::<Vec<_>>();
+        after = next_cleanup_page(&ids, 100, cutoff).unwrap_or(cutoff);
+        own.extend(
+            page.into_iter()
+                .filter(|msg| msg.id.0 < cutoff && msg.author.id == user_id)
+                .map(|msg| OwnMessage {
+                    id: msg.id,
+                    age: now.saturating_sub(Duration::from_secs(
+                        msg.timestamp.unix_timestamp().max(0) as u64,
+                    )),
+                    pinned: msg.pinned,
+                }),
         );
+    }
+
+    let Cleanup { bulk, mut single } = plan_cleanup(own, retention);
+    let mut removed = 0;
+    for batch in bulk {
+        // Bulk deletes need the Manage Messages permission, single ones don't.
+        match channel.delete_messages(http, &batch).await {
+            Ok(()) => removed += batch.len(),
+            Err(err) => {
+                tracing::debug!(channel_id = %channel, "deleting one by one: {}", err);
+                single.extend(batch);
+            }
+        }
+        tokio::time::sleep(CLEANUP_DELAY).await;
+    }
+    for id in single {
+        match channel.delete_message(http, id).await {
+            Ok(()) => removed += 1,
+            Err(err) => tracing::debug!(channel_id = %channel, message_id = %id, "{}", err),
+        }
+        tokio::time::sleep(CLEANUP_DELAY).await;
+    }
+    set_cleanup_scanned(store, PLATFORM, &chat_id, after)?;
+    Ok(removed)
+}
+
+struct DiscordTransport {
+    user_id: UserId,
+    messages: Mutex<mpsc::UnboundedReceiver<(Context, Message)>>,
+    metrics: Arc<Metrics>,
+    redactor: Redactor,
+    lang: Lang,
+    guild_langs: Arc<Mutex<HashMap<GuildId, Lang>>>,
+    reactions: bool,
+    read_embeds: bool,
+    retry: RetryPolicy,
+    reply_destination: ReplyDestination,
+    download_failure: DownloadFailure,
+    download_concurrency: usize,
+    access: AccessCache<ChannelId>,
+    upload_limits: Mutex<HashMap<GuildId, (Instant, usize)>>,
+    max_upload_size: Option<usize>,
+    debug_replies: bool,
+    /// Used to inline files in channels that don't allow uploads.
+    limits: Limits,
+}
 
+struct DiscordReply {
+    ctx: Context,
+    msg: Message,
+}
+
+#[async_trait]
+impl BotTransport for DiscordTransport {
+    type Reply = DiscordReply;
+
+    async fn next(&self) -> Option<IncomingMessage<DiscordReply>> {
+        loop {
+            let (ctx, msg) = self.messages.lock().await.recv().await?;
+            let echo = msg.author.id == self.user_id;
+            let dm = msg.guild_id.is_none();
+            let mention = msg.mentions.iter().any(|user| user.id == self.user_id);
+            if echo || !(dm || mention) {
+                continue;
+            }
+            return Some(IncomingMessage {
+                text: msg.content.clone(),
+                // Link-only messages may arrive with the link unfurled into an embed.
+                // Attachments without a caption are commands of their own.
+                alt_text: (self.read_embeds && msg.attachments.is_empty())
+                    .then(|| embed_text(&msg.embeds)),
+                username: Some(msg.author.name.clone()),
+                user_id: msg.author.id.to_string(),
+                chat_id: msg.channel_id.to_string(),
+                lang: self.lang(&msg).await,
+                sent_at: unix_time(msg.timestamp.unix_timestamp()),
+                span: tracing::info_span!(
+                    "message",
+                    platform = PLATFORM,
+                    channel_id = %msg.channel_id,
+                    message_id = %msg.id,
+                ),
+                reply: DiscordReply { ctx, msg },
+            });
+        }
+    }
+
+    async fn attachments(
+        &self,
+        reply: &DiscordReply,
+        lang: Lang,
+    ) -> Result<Option<Vec<Attachment>>> {
+        let DiscordReply { ctx, msg } = reply;
         let filesize = msg.attachments.iter().fold(0, |acc, x| acc + x.size);
         if filesize as usize > MAX_FILE_SIZE {
-            msg.reply(&ctx.http, "Too large file input (10MiB max.)")
+            let plan = plan_failure(
+                ErrorCategory::InputTooLarge,
+                lang.tr("too-large", &[("max", &format_size(MAX_FILE_SIZE))]),
+                format!("attachments of {} bytes", filesize),
+            );
+            let input = msg.content.clone();
+            self.send(reply, lang, RenderedMessage::Response { plan, input })
                 .await?;
-            return Ok(());
+            return Ok(None);
         }
 
-        let attachments = join_all(msg.attachments.iter().map(|att| att.download())).await;
-        let mut attachments = attachments
-            .into_iter()
-            .zip(&msg.attachments)
-            .filter_map(|(data, att)| data.ok().map(|data| (data, att.content_type.as_ref())))
-            .map(|(data, content_type)| {
-                InputStream::new(
-                    Cursor::new(data),
-                    content_type
-                        .and_then(|ty| MediaType::parse(ty).ok())
-                        .unwrap_or(media_type!(APPLICATION / OCTET_STREAM)),
-                )
-            })
+        for att in &msg.attachments {
+            self.metrics.attachment(att.size as usize);
+        }
+
+        // The downloads only start when polled, so the limit still applies.
+        let downloads = msg
+            .attachments
+            .iter()
+            .map(|att| att.download())
             .collect::<Vec<_>>();
+        let downloads = download_all(downloads, self.download_concurrency).await;
+        let downloads = msg.attachments.iter().zip(downloads).map(|(att, data)| {
+            let media_type = att
+                .content_type
+                .as_deref()
+                .and_then(|ty| MediaType::parse(ty).ok())
+                .unwrap_or(media_type!(APPLICATION / OCTET_STREAM));
+            let attachment =
+                data.map(|data| Attachment::new(data, media_type.into()).with_name(&att.filename));
+            (att.filename.clone(), attachment)
+        });
+        match collect_downloads(downloads, self.download_failure, lang) {
+            Downloaded::Proceed {
+                attachments,
+                notice,
+            } => {
+                if let Some(notice) = notice {
+                    self.reply(ctx, msg, &notice).await?;
+                }
+                Ok(Some(attachments))
+            }
+            Downloaded::Abort(text) => {
+                self.reply(ctx, msg, &text).await?;
+                Ok(None)
+            }
+        }
+    }
 
-        let tokens = Tokenizer::new().tokenize(&content);
-        let commands = handler.yozuk.get_commands(&tokens, &attachments);
-        if commands.is_empty() {
-            msg.channel_id
-                .send_message(&ctx.http, |m| {
-                    m.content("Sorry, I can't understand your request.")
-                        .add_embed(|f| {
-                            f.field(
-                                "Hint",
-                                "Please refer [Documentation](https://docs.yozuk.com/) for available commands.",
-                                true,
-                            )
-                        })
-                        .reference_message(&msg)
-                })
-                .await?;
-            return Ok(());
+    async fn send(&self, reply: &DiscordReply, lang: Lang, message: RenderedMessage) -> Result<()> {
+        match message {
+            RenderedMessage::Notice(text) => self.reply(&reply.ctx, &reply.msg, &text).await,
+            RenderedMessage::Response { plan, input } => {
+                self.respond(&reply.ctx, &reply.msg, &input, lang, plan)
+                    .await
+            }
         }
+    }
 
-        let user = UserContext {
-            username: Some(msg.author.name.clone()),
-            ..Default::default()
+    async fn max_file_size(&self, reply: &DiscordReply) -> Option<usize> {
+        let limit = match reply.msg.guild_id {
+            Some(guild_id) if self.reply_destination != ReplyDestination::Dm => {
+                self.upload_limit(&reply.ctx, guild_id).await
+            }
+            _ => BASE_UPLOAD_SIZE,
         };
+        Some(self.max_upload_size.map_or(limit, |max| limit.min(max)))
+    }
+
+    async fn started(&self, reply: &DiscordReply, _: Lang) -> Result<()> {
+        if self.reactions {
+            let update = Reaction::update(None, Reaction::Pending);
+            react(&reply.ctx.http, &reply.msg, update)
+                .await
+                .map_err(|err| self.metrics.send_failure(err))?;
+        }
+        Ok(())
+    }
+
+    async fn finished(&self, reply: &DiscordReply, status: Option<PlanStatus>) -> Result<()> {
+        if self.reactions {
+            let update = Reaction::update(Some(Reaction::Pending), Reaction::outcome(status));
+            react(&reply.ctx.http, &reply.msg, update)
+                .await
+                .map_err(|err| self.metrics.send_failure(err))?;
+        }
+        Ok(())
+    }
+}
+
+impl DiscordTransport {
+    /// Uses the preferred locale of the guild, if supported.
+    async fn lang(&self, msg: &Message) -> Lang {
+        match msg.guild_id {
+            Some(guild_id) => self.guild_langs.lock().await.get(&guild_id).copied(),
+            None => None,
+        }
+        .unwrap_or(self.lang)
+    }
+
+    async fn reply(&self, ctx: &Context, msg: &Message, text: &str) -> Result<()> {
+        if !self.access(ctx, msg.channel_id).await.send {
+            tracing::info!(channel_id = %msg.channel_id, "skipping a notice without permission to send");
+            return Ok(());
+        }
+        retry(&self.retry, || msg.reply(&ctx.http, text))
+            .await
+            .map_err(|err| self.metrics.send_failure(err))?;
+        Ok(())
+    }
 
-        let result = handler
-            .yozuk
-            .run_commands(commands, &mut attachments, Some(&user));
-        let outputs = match result {
-            Ok(outputs) => outputs,
-            Err(outputs) => outputs,
+    async fn respond(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        input: &str,
+        lang: Lang,
+        plan: ResponsePlan,
+    ) -> Result<()> {
+        // The shared handler has logged the failure at error level, in the
+        // span of the message with its id.
+        let details = plan
+            .error
+            .as_deref()
+            .filter(|_| self.debug_replies)
+            .map(|error| format!("```text\n{}\n```", truncate(error, MAX_DETAILS_LENGTH)));
+
+        let mut channel = self.destination(ctx, msg, input).await;
+        let access = self.access(ctx, channel).await;
+        let items = if access.attach {
+            plan.items
+        } else {
+            inline_files(plan.items, &self.limits, MAX_INLINED_FILE_SIZE, lang)
         };
 
         let mut content = vec![];
         let mut files = vec![];
-
-        for output in outputs {
-            for block in output.blocks {
-                match block {
-                    Block::Comment(comment) => {
-                        content.push(comment.text);
+        let mut apology = None;
+        let mut failure = None;
+        let mut sectioned = false;
+        for item in items {
+            match item {
+                PlanItem::Text(text) => {
+                    content.push(text);
+                }
+                PlanItem::CodeBlock { lang, text } => {
+                    content.push(format!("```{}\n{}\n```", lang.unwrap_or_default(), text));
+                }
+                PlanItem::File { name, data, .. } => {
+                    // All files go into the first page, so sections refer to them by name.
+                    if sectioned {
+                        content.push(format!("📎 `{}`", name));
                     }
-                    Block::Data(data) => match str::from_utf8(&data.data) {
-                        Ok(text) if text.len() <= 1024 => {
-                            content.push(format!("```\n{}\n```", text));
+                    files.push((data, name));
+                }
+                PlanItem::Section { title } => {
+                    if sectioned {
+                        content.push(SECTION_RULE.into());
+                    }
+                    if !title.is_empty() {
+                        content.push(format!("**{}**", title));
+                    }
+                    sectioned = true;
+                }
+                PlanItem::Apology { suggestions } => {
+                    apology = Some(suggestions);
+                    break;
+                }
+                PlanItem::Error { category } => {
+                    failure = Some(category);
+                }
+            }
+        }
+        content.extend(details);
+
+        let mut pages = paginate(&content, &PAGE_LIMITS);
+        let mut page_delay = None;
+        let count = if apology.is_some() { 1 } else { pages.len() };
+        match access.delivery(count) {
+            Delivery::Chat {
+                page_delay: delay, ..
+            } => page_delay = delay,
+            Delivery::Private => match msg.author.create_dm_channel(&ctx.http).await {
+                Ok(dm) => {
+                    let requested = channel.mention().to_string();
+                    let key = if access.send {
+                        "requested-in-slow-mode"
+                    } else {
+                        "requested-in"
+                    };
+                    let note = lang.tr(key, &[("channel", &requested)]);
+                    pages = paginate(std::iter::once(&note).chain(&content), &PAGE_LIMITS);
+                    channel = dm.id;
+                }
+                Err(err) => {
+                    tracing::warn!(channel_id = %channel, "skipping a reply that can't be sent: {}", err);
+                    return Ok(());
+                }
+            },
+        }
+        let reference = (channel == msg.channel_id).then_some(msg);
+
+        if let Some(suggestions) = apology {
+            retry(&self.retry, || {
+                channel.send_message(&ctx.http, |m| {
+                    m.content(lang.tr("unrecognized", &[])).add_embed(|f| {
+                        if !suggestions.is_empty() {
+                            f.field(lang.tr("did-you-mean", &[]), suggestions.join("\n"), false);
                         }
-                        _ => {
-                            let filename = if data.file_name.is_empty() {
-                                format!("data.{}", get_file_extension(&data.media_type))
-                            } else {
-                                data.file_name.clone()
-                            };
-                            files.push((data.data.clone(), filename));
+                        f.field(
+                            lang.tr("hint", &[]),
+                            lang.tr("docs-hint", &[("url", DOCS_URL)]),
+                            true,
+                        )
+                    });
+                    if let Some(msg) = reference {
+                        m.reference_message(msg);
+                    }
+                    m
+                })
+            })
+            .await
+            .map_err(|err| self.metrics.send_failure(err))?;
+            return Ok(());
+        }
+
+        // A page that can't be delivered doesn't stop the remaining ones.
+        for (index, page) in pages.iter().enumerate() {
+            if let (Some(delay), true) = (page_delay, index > 0) {
+                tokio::time::sleep(delay).await;
+            }
+            let sent = retry(&self.retry, || {
+                channel.send_message(&ctx.http, |m| {
+                    m.content(page);
+                    if let (Some(category), 0) = (failure, index) {
+                        m.embed(|e| {
+                            e.description(category.message(lang))
+                                .colour(error_colour(category))
+                        });
+                    }
+                    if index == 0 {
+                        m.add_files(
+                            files
+                                .iter()
+                                .map(|(data, name)| (data.as_ref(), name.as_str())),
+                        );
+                        if let Some(msg) = reference {
+                            m.reference_message(msg);
                         }
-                    },
-                    _ => {}
-                }
+                    }
+                    m
+                })
+            })
+            .await;
+            if let Err(err) = sent {
+                let err = self.metrics.send_failure(err);
+                tracing::error!(page = index, "{}", self.redactor.redact(&err.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up what the bot may do in `channel`, reusing recent results.
+    async fn access(&self, ctx: &Context, channel: ChannelId) -> Access {
+        let source = DiscordPermissions {
+            ctx,
+            user_id: self.user_id,
+        };
+        self.access.get(&source, channel).await
+    }
+
+    /// Looks up the upload limit of a guild, reusing recent results.
+    async fn upload_limit(&self, ctx: &Context, guild_id: GuildId) -> usize {
+        if let Some((checked, limit)) = self.upload_limits.lock().await.get(&guild_id) {
+            if checked.elapsed() < UPLOAD_LIMIT_TTL {
+                return *limit;
+            }
+        }
+        match guild_id.to_partial_guild(&ctx.http).await {
+            Ok(guild) => {
+                let limit = upload_limit(guild.premium_tier);
+                self.upload_limits
+                    .lock()
+                    .await
+                    .insert(guild_id, (Instant::now(), limit));
+                limit
+            }
+            Err(err) => {
+                tracing::debug!(guild_id = %guild_id, "failed to look up the boost level: {}", err);
+                BASE_UPLOAD_SIZE
             }
         }
+    }
 
+    /// Picks the channel for the answer to `msg`, falling back to its own
+    /// channel if a thread or DM channel can't be created.
+    async fn destination(&self, ctx: &Context, msg: &Message, text: &str) -> ChannelId {
+        let private = msg.guild_id.is_none();
+        let created =
+            match self
+                .reply_destination
+                .route(private, true, text, MAX_THREAD_NAME_LENGTH)
+            {
+                Route::InPlace => return msg.channel_id,
+                Route::Dm => msg
+                    .author
+                    .create_dm_channel(&ctx.http)
+                    .await
+                    .map(|channel| channel.id),
+                Route::Thread(name) => msg
+                    .channel_id
+                    .create_public_thread(&ctx.http, msg.id, |thread| thread.name(name))
+                    .await
+                    .map(|thread| thread.id),
+            };
+        created.unwrap_or_else(|err| {
+            tracing::warn!("replying in place: {}", err);
+            msg.channel_id
+        })
+    }
+}
+
+/// Channel permissions of the bot, as seen by its member in the guild.
+struct DiscordPermissions<'a> {
+    ctx: &'a Context,
+    user_id: UserId,
+}
+
+#[async_trait]
+impl PermissionSource<ChannelId> for DiscordPermissions<'_> {
+    async fn access(&self, channel: &ChannelId) -> Result<Access> {
+        let ctx = self.ctx;
+        let channel = match channel.to_channel(ctx).await? {
+            Channel::Guild(channel) => channel,
+            _ => return Ok(Access::FULL),
+        };
+        // Threads take their permissions from the parent channel, but have a slow mode of their own.
+        let thread = matches!(
+            channel.kind,
+            ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+        );
+        let slow_mode = channel.rate_limit_per_user.map(Duration::from_secs);
+        let channel = match channel.parent_id {
+            Some(parent) if thread => match parent.to_channel(ctx).await? {
+                Channel::Guild(parent) => parent,
+                _ => channel,
+            },
+            _ => channel,
+        };
+        let guild = channel.guild_id.to_partial_guild(&ctx.http).await?;
+        let member = channel.guild_id.member(ctx, self.user_id).await?;
+        let permissions = guild.user_permissions_in(&channel, &member)?;
+        Ok(ChannelPermissions {
+            thread,
+            send_messages: permissions.send_messages(),
+            send_messages_in_threads: permissions.send_messages_in_threads(),
+            attach_files: permissions.attach_files(),
+            moderator: permissions.manage_messages() || permissions.manage_channels(),
+            slow_mode,
+        }
+        .access())
+    }
+}
+
+fn upload_limit(tier: PremiumTier) -> usize {
+    match tier {
+        PremiumTier::Tier2 => TIER_2_UPLOAD_SIZE,
+        PremiumTier::Tier3 => TIER_3_UPLOAD_SIZE,
+        _ => BASE_UPLOAD_SIZE,
+    }
+}
+
+/// Client errors other than rate limiting won't go away by retrying.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<SerenityError>() {
+        Some(SerenityError::Http(err)) => match err.status_code() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => true,
+        },
+        _ => true,
+    }
+}
+
+/// Problems with the message are shown in yellow, the bot's own ones in red.
+fn error_colour(category: ErrorCategory) -> Colour {
+    match category {
+        ErrorCategory::Timeout => Colour::ORANGE,
+        ErrorCategory::InternalError => Colour::RED,
+        _ => Colour::GOLD,
+    }
+}
+
+/// Joins the URLs and descriptions of link embeds.
+fn embed_text(embeds: &[Embed]) -> String {
+    preview_text(
+        embeds
+            .iter()
+            .map(|embed| (embed.url.as_deref(), embed.description.as_deref())),
+    )
+}
+
+/// Applies `update` to the reactions of the bot to `msg`.
+async fn react(http: &Http, msg: &Message, update: ReactionUpdate) -> Result<()> {
+    if let Some(reaction) = update.remove {
         msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.content(content.join("\n"))
-                    .add_files(
-                        files
-                            .iter()
-                            .map(|(data, name)| (data.as_ref(), name.as_str())),
-                    )
-                    .reference_message(&msg)
-            })
+            .delete_reaction(http, msg.id, None, emoji(reaction))
             .await?;
     }
+    if let Some(reaction) = update.add {
+        msg.react(http, emoji(reaction)).await?;
+    }
     Ok(())
 }
 
+fn emoji(reaction: Reaction) -> char {
+    match reaction {
+        Reaction::Pending => PENDING,
+        Reaction::Done => SUCCESS,
+        Reaction::Failed => FAILURE,
+    }
+}
+
+/// Heartbeat acknowledgements update the latency of a shard, so a changed
+/// latency shows that the gateway connection is still alive.
+async fn watch_shards(shard_manager: Arc<Mutex<ShardManager>>, health: Arc<Health>) {
+    let mut latencies = HashMap::new();
+    loop {
+        tokio::time::sleep(SHARD_POLL_INTERVAL).await;
+        let manager = shard_manager.lock().await;
+        let runners = manager.runners.lock().await;
+        let mut alive = false;
+        for (id, runner) in runners.iter() {
+            let changed = latencies.insert(*id, runner.latency) != Some(runner.latency);
+            alive |= runner.stage == ConnectionStage::Connected && changed;
+        }
+        if alive {
+            health.event();
+        }
+    }
+}
+
 #[derive(Parser)]
-#[clap(author, version, about)]
+#[clap(author, version, about, after_help = CONFIG_HELP)]
 pub struct Args {
+    /// Read settings from a TOML file
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
     #[clap(long, env("DISCORD_TOKEN"), hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// How text outputs are sent: "file", "inline" or the maximum inline length [default: 1024]
+    #[clap(long)]
+    pub text_policy: Option<TextPolicy>,
+
+    /// Send files as a single ZIP archive when there are more than this many
+    #[clap(long)]
+    pub bundle_threshold: Option<usize>,
+
+    /// Serve Prometheus metrics on this address
+    #[clap(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Limit requests per sender, e.g. "5/60" for 5 requests per minute
+    #[clap(long)]
+    pub rate_limit: Option<RateLimit>,
+
+    /// Also redact text matching this regular expression from error replies and logs (repeatable)
+    #[clap(long = "redact")]
+    pub redact_patterns: Vec<String>,
+
+    /// Language of canned replies when the user's language is unknown: en, de or ja [default: en]
+    #[clap(long)]
+    pub lang: Option<Lang>,
+
+    /// Remember the last command of each chat for this many seconds to understand follow-ups
+    #[clap(long)]
+    pub memory_ttl: Option<u64>,
+
+    /// Reply with the commands that would run instead of running them
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Store per-user preferences such as "set timezone Europe/Berlin" in this directory
+    #[clap(long)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Timezone for users who haven't set their own, e.g. "Europe/Berlin"
+    #[clap(long)]
+    pub timezone: Option<Timezone>,
+
+    /// Location for users, as "<latitude>,<longitude>"
+    #[clap(long)]
+    pub location: Option<Location>,
+
+    /// Serve /live and /ready probes on this address
+    #[clap(long)]
+    pub health_addr: Option<SocketAddr>,
+
+    /// Report not ready when no gateway event has been seen for this many seconds [default: 300]
+    #[clap(long)]
+    pub health_stale_after: Option<u64>,
+
+    /// React to requests with ⏳ while running and ✅ or ❌ when done
+    #[clap(long)]
+    pub reactions: bool,
+
+    /// Log format: "pretty" or "json" [default: pretty]
+    #[clap(long)]
+    pub log_format: Option<LogFormat>,
+
+    /// Use the URL and description of link embeds as input for messages without text
+    #[clap(long)]
+    pub read_embeds: bool,
+
+    /// Where answers are sent: "same" channel, "dm" or a new "thread" [default: same]
+    #[clap(long)]
+    pub reply_destination: Option<ReplyDestination>,
+
+    /// Reuse the results of identical commands for this many seconds (0 disables the cache) [default: 0]
+    #[clap(long)]
+    pub cache_ttl: Option<u64>,
+
+    /// Never reuse results of this skill, e.g. "uuid" (repeatable) [default: random and time-dependent skills]
+    #[clap(long = "uncached-skill")]
+    pub uncached_skills: Vec<String>,
+
+    /// Cancel commands that take longer than this many seconds [default: 30]
+    #[clap(long)]
+    pub command_timeout: Option<u64>,
+
+    /// Append the matched skills and the processing time to replies
+    #[clap(long)]
+    pub verbose: bool,
+
+    /// Never send files; binary outputs are summarized or inlined instead
+    #[clap(long)]
+    pub text_only: bool,
+
+    /// Send binary outputs up to a size as text instead of files, e.g. "base64:64" or "hex:32"
+    #[clap(long)]
+    pub inline_binary: Option<InlineBinary>,
+
+    /// Log the name and outcome of every command, without any message content
+    #[clap(long)]
+    pub analytics: bool,
+
+    /// When an attachment can't be downloaded: "abort" asks the user to retry, "skip" runs the command without it [default: abort]
+    #[clap(long)]
+    pub download_failure: Option<DownloadFailure>,
+
+    /// Download at most this many attachments of a message at once [default: 4]
+    #[clap(long)]
+    pub download_concurrency: Option<usize>,
+
+    /// Run a known command and exit with an error if its output is wrong, before connecting
+    #[clap(long)]
+    pub selftest: bool,
+
+    /// Language for a channel or contact, as "<id>=<locale>" (repeatable); a user's own setting still wins
+    #[clap(long = "locale-override")]
+    pub locale_overrides: Vec<LocaleOverride>,
+
+    /// Include the error details of failed commands in replies instead of only logging them
+    #[clap(long)]
+    pub debug_replies: bool,
+
+    /// Never upload files larger than this many bytes, even where Discord allows more
+    #[clap(long)]
+    pub max_upload_size: Option<usize>,
+
+    /// User ID that may use /yozuk-stats anywhere, besides members who can manage the server
+    #[clap(long)]
+    pub owner_id: Option<u64>,
+
+    /// Run commands on the single file inside a ZIP or gzip attachment instead of the archive
+    #[clap(long)]
+    pub extract_archives: bool,
+}
+
+pub struct Config {
     pub token: String,
+    pub text_policy: TextPolicy,
+    pub bundle_threshold: Option<usize>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub rate_limit: Option<RateLimit>,
+    pub redactor: Redactor,
+    pub lang: Lang,
+    pub memory_ttl: Option<Duration>,
+    pub dry_run: bool,
+    pub data_dir: Option<PathBuf>,
+    pub engine: EngineOptions,
+    pub health_addr: Option<SocketAddr>,
+    pub health_stale_after: Duration,
+    pub reactions: bool,
+    pub log_format: LogFormat,
+    pub read_embeds: bool,
+    pub reply_destination: ReplyDestination,
+    pub cache: Option<Arc<ResultCache>>,
+    pub command_timeout: Duration,
+    pub verbose: bool,
+    pub text_only: bool,
+    pub inline_binary: Option<InlineBinary>,
+    pub analytics: bool,
+    pub download_failure: DownloadFailure,
+    pub download_concurrency: usize,
+    pub selftest: bool,
+    pub locales: LocaleOverrides,
+    pub debug_replies: bool,
+    pub max_upload_size: Option<usize>,
+    pub owner_id: Option<UserId>,
+    pub extract_archives: bool,
+}
+
+impl Config {
+    pub fn new(args: Args) -> Result<Self> {
+        let mut file = ConfigFile::load(args.config.as_deref())?;
+        let token = file.secret("token", args.token);
+        let text_policy = file.value("text_policy", args.text_policy);
+        let bundle_threshold = file.value("bundle_threshold", args.bundle_threshold);
+        let metrics_addr = file.value("metrics_addr", args.metrics_addr);
+        let rate_limit = file.value("rate_limit", args.rate_limit);
+        let redact_patterns = file.list("redact_patterns", args.redact_patterns);
+        let lang = file.value("lang", args.lang);
+        let memory_ttl = file.value("memory_ttl", args.memory_ttl);
+        let dry_run = file.value("dry_run", args.dry_run.then_some(true));
+        let data_dir = file.value("data_dir", args.data_dir);
+        let timezone = file.value("timezone", args.timezone);
+        let location = file.value("location", args.location);
+        let health_addr = file.value("health_addr", args.health_addr);
+        let health_stale_after = file.value("health_stale_after", args.health_stale_after);
+        let reactions = file.value("reactions", args.reactions.then_some(true));
+        let log_format = file.value("log_format", args.log_format);
+        let read_embeds = file.value("read_embeds", args.read_embeds.then_some(true));
+        let reply_destination = file.value("reply_destination", args.reply_destination);
+        let cache_ttl = file.value("cache_ttl", args.cache_ttl);
+        let uncached_skills = file.list("uncached_skills", args.uncached_skills);
+        let command_timeout = file.value("command_timeout", args.command_timeout);
+        let verbose = file.value("verbose", args.verbose.then_some(true));
+        let text_only = file.value("text_only", args.text_only.then_some(true));
+        let inline_binary = file.value("inline_binary", args.inline_binary);
+        let analytics = file.value("analytics", args.analytics.then_some(true));
+        let download_failure = file.value("download_failure", args.download_failure);
+        let download_concurrency = file.value("download_concurrency", args.download_concurrency);
+        let selftest = file.value("selftest", args.selftest.then_some(true));
+        let locale_overrides = file.list("locale_overrides", args.locale_overrides);
+        let debug_replies = file.value("debug_replies", args.debug_replies.then_some(true));
+        let max_upload_size = file.value("max_upload_size", args.max_upload_size);
+        let owner_id = file.value("owner_id", args.owner_id);
+        let extract_archives =
+            file.value("extract_archives", args.extract_archives.then_some(true));
+        file.finish()?;
+        let redactor = Redactor::new(redact_patterns)?;
+
+        // `finish` has failed if any required value is missing.
+        Ok(Self {
+            token: token.unwrap(),
+            text_policy: text_policy.unwrap_or(TextPolicy::Threshold(1024)),
+            bundle_threshold,
+            metrics_addr,
+            rate_limit,
+            redactor,
+            lang: lang.unwrap_or_default(),
+            memory_ttl: memory_ttl.map(Duration::from_secs),
+            dry_run: dry_run.unwrap_or_default(),
+            data_dir,
+            engine: EngineOptions { timezone, location },
+            health_addr,
+            health_stale_after: Duration::from_secs(health_stale_after.unwrap_or(300)),
+            reactions: reactions.unwrap_or_default(),
+            log_format: log_format.unwrap_or_default(),
+            read_embeds: read_embeds.unwrap_or_default(),
+            reply_destination: reply_destination.unwrap_or_default(),
+            cache: cache_ttl.filter(|&ttl| ttl > 0).map(|ttl| {
+                let ttl = Duration::from_secs(ttl);
+                Arc::new(if uncached_skills.is_empty() {
+                    ResultCache::new(ttl, DEFAULT_UNCACHED_SKILLS.iter().copied())
+                } else {
+                    ResultCache::new(ttl, uncached_skills)
+                })
+            }),
+            command_timeout: Duration::from_secs(command_timeout.unwrap_or(30)),
+            verbose: verbose.unwrap_or_default(),
+            text_only: text_only.unwrap_or_default(),
+            inline_binary,
+            analytics: analytics.unwrap_or_default(),
+            download_failure: download_failure.unwrap_or_default(),
+            download_concurrency: download_concurrency.unwrap_or(4),
+            selftest: selftest.unwrap_or_default(),
+            locales: locale_overrides.into_iter().collect(),
+            debug_replies: debug_replies.unwrap_or_default(),
+            max_upload_size,
+            owner_id: owner_id.map(UserId),
+            extract_archives: extract_archives.unwrap_or_default(),
+        })
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::try_parse()?;
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES;
+    let config = Config::new(Args::try_parse()?)?;
+    init_logging(config.log_format)?;
+    let yozuk = Arc::new(config.engine.build());
+    if config.selftest {
+        selftest(&yozuk)?;
+    }
 
-    let http = Http::new(&args.token);
+    let intents =
+        GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES;
+
+    let http = Http::new(&config.token);
     let gateway = http.get_bot_gateway().await?;
-    println!("{:?}", gateway);
+    tracing::debug!(?gateway);
     let user = http.get_current_user().await?;
-    let yozuk = Arc::new(Yozuk::builder().build());
 
-    let mut client = Client::builder(&args.token, intents)
+    let metrics = Arc::new(Metrics::new(PLATFORM));
+    if let Some(addr) = config.metrics_addr {
+        metrics.serve(addr)?;
+    }
+
+    let health = Arc::new(Health::new(config.health_stale_after));
+    if let Some(addr) = config.health_addr {
+        health.serve(addr)?;
+    }
+
+    let prefs = config
+        .data_dir
+        .as_deref()
+        .map(Preferences::open)
+        .transpose()?
+        .map(Arc::new);
+
+    let limits = Limits {
+        text_policy: config.text_policy,
+        bundle_threshold: config.bundle_threshold,
+        max_file_size: Some(
+            config
+                .max_upload_size
+                .map_or(BASE_UPLOAD_SIZE, |max| max.min(BASE_UPLOAD_SIZE)),
+        ),
+        redactor: config.redactor.clone(),
+        dry_run: config.dry_run,
+        cache: config.cache.clone(),
+        analytics: analytics(config.analytics),
+        text_only: config.text_only,
+        sections: true,
+        inline_binary: config.inline_binary,
+        verbose: config.verbose,
+        command_timeout: config.command_timeout,
+        ..Default::default()
+    };
+    let rate_limiter = config
+        .rate_limit
+        .map(|limit| Arc::new(RateLimiter::new(limit)));
+    let bot = BotConfig {
+        engine: config.engine,
+        preprocessor: Preprocessor::default().with_mentions(r"<@\d+>")?,
+        metrics: metrics.clone(),
+        rate_limiter: rate_limiter.clone(),
+        memory: config
+            .memory_ttl
+            .map(|ttl| Arc::new(ConversationMemory::new(ttl))),
+        prefs: prefs.clone(),
+        locales: config.locales,
+        archives: config.extract_archives.then(ArchiveLimits::default),
+        ..BotConfig::new(PLATFORM, limits.clone())
+    };
+
+    let guild_langs = Arc::new(Mutex::new(HashMap::new()));
+    let (tx, rx) = mpsc::unbounded_channel();
+    let transport = Arc::new(DiscordTransport {
+        user_id: user.id,
+        messages: Mutex::new(rx),
+        metrics: metrics.clone(),
+        redactor: config.redactor,
+        lang: config.lang,
+        guild_langs: guild_langs.clone(),
+        reactions: config.reactions,
+        read_embeds: config.read_embeds,
+        retry: RetryPolicy::default().with_classifier(is_retryable),
+        reply_destination: config.reply_destination,
+        download_failure: config.download_failure,
+        download_concurrency: config.download_concurrency,
+        access: AccessCache::new(ACCESS_TTL),
+        upload_limits: Default::default(),
+        max_upload_size: config.max_upload_size,
+        debug_replies: config.debug_replies,
+        limits,
+    });
+
+    let mut client = Client::builder(&config.token, intents)
         .event_handler(Handler {
-            user_id: user.id,
-            yozuk,
+            health: health.clone(),
+            lang: config.lang,
+            guild_langs,
+            messages: tx,
+            prefs: prefs.clone(),
+            metrics,
+            cache: config.cache.clone(),
+            rate_limiter,
+            owner_id: config.owner_id,
         })
         .await?;
+    client
+        .data
+        .write()
+        .await
+        .insert::<ShardManagerKey>(client.shard_manager.clone());
+
+    if let Some(store) = prefs {
+        let http = client.cache_and_http.http.clone();
+        tokio::spawn(clean_up(http, store, user.id));
+    }
 
+    if config.health_addr.is_some() {
+        tokio::spawn(watch_shards(client.shard_manager.clone(), health));
+    }
+
+    tokio::spawn(run_bot(transport, yozuk, bot));
     client.start().await?;
     Ok(())
 }
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.into(),
+    }
+}
+
+fn analytics(enabled: bool) -> Arc<dyn AnalyticsSink> {
+    if enabled {
+        Arc::new(LogAnalytics::new(PLATFORM))
+    } else {
+        Arc::new(NoopAnalytics)
+    }
+}
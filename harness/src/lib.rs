@@ -0,0 +1,227 @@
+//! Drives the shared pipeline with an in-memory transport, so that the
+//! planning logic can be exercised without connecting to a chat platform.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::Span;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    plan_response, Attachment, BotTransport, IncomingMessage, Lang, Limits, PlanItem,
+    RenderedMessage, ResponsePlan,
+};
+use yozuk_sdk::prelude::*;
+
+/// A synthetic incoming message.
+#[derive(Debug, Clone, Default)]
+pub struct Incoming {
+    pub text: String,
+    pub username: Option<String>,
+    pub alt_text: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub sent_at: Option<SystemTime>,
+}
+
+impl Incoming {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from(mut self, username: &str) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// Sets the text used if nothing is left of the message after preprocessing.
+    pub fn alt_text(mut self, text: &str) -> Self {
+        self.alt_text = Some(text.into());
+        self
+    }
+
+    pub fn sent_at(mut self, time: SystemTime) -> Self {
+        self.sent_at = Some(time);
+        self
+    }
+
+    pub fn attach<T: Into<Vec<u8>>>(mut self, data: T, media_type: MediaType) -> Self {
+        self.attachments
+            .push(Attachment::new(data.into(), media_type.into()));
+        self
+    }
+}
+
+/// Stands in for a platform frontend and captures everything it would send.
+pub struct MockTransport {
+    zuk: Yozuk,
+    limits: Limits,
+    outbox: Vec<PlanItem>,
+}
+
+impl MockTransport {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            zuk: Yozuk::builder().build(),
+            limits,
+            outbox: vec![],
+        }
+    }
+
+    /// Plans a response to `msg` the way the frontends do and records its items.
+    pub fn receive(&mut self, msg: Incoming) -> ResponsePlan {
+        let user = UserContext {
+            username: msg.username,
+            ..Default::default()
+        };
+        let plan = plan_response(
+            &self.zuk,
+            &msg.text,
+            msg.attachments.iter().map(Attachment::stream).collect(),
+            &user,
+            &self.limits,
+        );
+        self.outbox.extend(plan.items.iter().cloned());
+        plan
+    }
+
+    /// Everything sent so far, in order.
+    pub fn outbox(&self) -> &[PlanItem] {
+        &self.outbox
+    }
+}
+
+/// A [`BotTransport`] that answers a fixed queue of messages and records what
+/// [`run_bot`](yozuk_bot_core::run_bot) sends, so that the whole message flow
+/// can be tested.
+///
+/// Replies are identified by the position of their message in the queue. All
+/// messages belong to the chat `"chat"` and are sent by their username, or by
+/// `"user"` if they have none.
+#[derive(Default)]
+pub struct MemoryTransport {
+    inbox: Mutex<VecDeque<(usize, Incoming)>>,
+    attachments: Mutex<Vec<Vec<Attachment>>>,
+    sent: Mutex<Vec<(usize, RenderedMessage)>>,
+    max_file_size: Option<usize>,
+}
+
+impl MemoryTransport {
+    pub fn new<I: IntoIterator<Item = Incoming>>(messages: I) -> Self {
+        Self {
+            inbox: Mutex::new(messages.into_iter().enumerate().collect()),
+            ..Default::default()
+        }
+    }
+
+    /// Reports `max` as the largest file the chat accepts.
+    pub fn with_max_file_size(mut self, max: usize) -> Self {
+        self.max_file_size = Some(max);
+        self
+    }
+
+    /// Everything sent so far, with the index of the message it answers.
+    pub fn sent(&self) -> Vec<(usize, RenderedMessage)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl BotTransport for MemoryTransport {
+    type Reply = usize;
+
+    async fn next(&self) -> Option<IncomingMessage<usize>> {
+        let (index, msg) = self.inbox.lock().unwrap().pop_front()?;
+        let mut attachments = self.attachments.lock().unwrap();
+        attachments.resize_with(index + 1, Vec::new);
+        attachments[index] = msg.attachments;
+        Some(IncomingMessage {
+            text: msg.text,
+            alt_text: msg.alt_text,
+            user_id: msg.username.clone().unwrap_or_else(|| "user".into()),
+            username: msg.username,
+            chat_id: "chat".into(),
+            lang: Lang::En,
+            sent_at: msg.sent_at,
+            span: Span::none(),
+            reply: index,
+        })
+    }
+
+    async fn attachments(&self, reply: &usize, _: Lang) -> Result<Option<Vec<Attachment>>> {
+        let attachments = std::mem::take(&mut self.attachments.lock().unwrap()[*reply]);
+        Ok(Some(attachments))
+    }
+
+    async fn send(&self, reply: &usize, _: Lang, message: RenderedMessage) -> Result<()> {
+        self.sent.lock().unwrap().push((*reply, message));
+        Ok(())
+    }
+
+    async fn max_file_size(&self, _: &usize) -> Option<usize> {
+        self.max_file_size
+    }
+}
+
+/// Renders planned items as stable, human-readable text for golden files.
+///
+/// Binary file contents are summarized by their size.
+pub fn render(items: &[PlanItem]) -> String {
+    let mut out = String::new();
+    for item in items {
+        match item {
+            PlanItem::Text(text) => {
+                let _ = writeln!(out, "text:\n{}", text);
+            }
+            PlanItem::CodeBlock { lang, text } => {
+                let _ = writeln!(out, "code {}:\n{}", lang.as_deref().unwrap_or("-"), text);
+            }
+            PlanItem::File {
+                name,
+                media_type,
+                data,
+            } => {
+                let _ = writeln!(out, "file {} ({}, {} bytes)", name, media_type, data.len());
+            }
+            PlanItem::Apology { suggestions } => {
+                let _ = writeln!(out, "apology:");
+                for suggestion in suggestions {
+                    let _ = writeln!(out, "- {}", suggestion);
+                }
+            }
+            PlanItem::Error { category } => {
+                let _ = writeln!(out, "error {}", category);
+            }
+            PlanItem::Section { title } => {
+                let _ = writeln!(out, "section {}", title);
+            }
+        }
+    }
+    out
+}
+
+/// Compares `items` with the golden file at `path`.
+///
+/// Set `UPDATE_GOLDEN=1` to write the current rendering instead.
+pub fn assert_golden<P: AsRef<Path>>(path: P, items: &[PlanItem]) {
+    let path = path.as_ref();
+    let actual = render(items);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+    assert_eq!(
+        expected,
+        actual,
+        "{} is out of date (rerun with UPDATE_GOLDEN=1)",
+        path.display()
+    );
+}
@@ -0,0 +1,262 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use yozuk_bot_core::{
+    inline_files, Access, AccessCache, ChannelPermissions, Delivery, InlineBinary, Lang, Limits,
+    PermissionSource, PlanItem,
+};
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+
+fn file(name: &str, data: &'static [u8]) -> PlanItem {
+    PlanItem::File {
+        name: name.into(),
+        media_type: media_type!(APPLICATION / OCTET_STREAM).into(),
+        data: Bytes::from_static(data),
+    }
+}
+
+fn code(text: &str) -> PlanItem {
+    PlanItem::CodeBlock {
+        lang: None,
+        text: text.into(),
+    }
+}
+
+#[test]
+fn full_access_replies_in_place() {
+    assert_eq!(
+        Access::FULL.delivery(3),
+        Delivery::Chat {
+            attach: true,
+            page_delay: None,
+        }
+    );
+}
+
+#[test]
+fn missing_send_permission_replies_privately() {
+    let access = Access {
+        send: false,
+        ..Access::FULL
+    };
+    assert_eq!(access.delivery(1), Delivery::Private);
+}
+
+#[test]
+fn missing_attach_permission_inlines_files() {
+    let access = Access {
+        attach: false,
+        ..Access::FULL
+    };
+    assert_eq!(
+        access.delivery(1),
+        Delivery::Chat {
+            attach: false,
+            page_delay: None,
+        }
+    );
+}
+
+#[test]
+fn short_slow_mode_is_waited_out() {
+    let access = Access {
+        slow_mode: Some(Duration::from_secs(5)),
+        ..Access::FULL
+    };
+    assert_eq!(
+        access.delivery(1),
+        Delivery::Chat {
+            attach: true,
+            page_delay: None,
+        }
+    );
+    assert_eq!(
+        access.delivery(2),
+        Delivery::Chat {
+            attach: true,
+            page_delay: Some(Duration::from_secs(5)),
+        }
+    );
+}
+
+#[test]
+fn long_slow_mode_replies_privately() {
+    let access = Access {
+        slow_mode: Some(Duration::from_secs(60)),
+        ..Access::FULL
+    };
+    assert!(matches!(access.delivery(1), Delivery::Chat { .. }));
+    assert_eq!(access.delivery(2), Delivery::Private);
+}
+
+#[test]
+fn text_files_are_inlined_and_cut_off() {
+    let items = vec![
+        PlanItem::Text("result".into()),
+        file("short.txt", b"hello"),
+        file("long.txt", b"one\ntwo\nthree"),
+    ];
+    assert_eq!(
+        inline_files(items, &Limits::default(), 8, Lang::En),
+        vec![
+            PlanItem::Text("result".into()),
+            code("hello"),
+            code("one\ntwo"),
+            PlanItem::Text(
+                "(`long.txt` cut off, 13 bytes in total: I need the Attach Files permission here)"
+                    .into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn binary_files_are_inlined_or_counted() {
+    let items = vec![
+        file("small.bin", b"\xff\x00"),
+        file("large.bin", b"\xff\x00\x01"),
+    ];
+    let limits = Limits {
+        inline_binary: Some("hex:2".parse::<InlineBinary>().unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(
+        inline_files(items, &limits, 100, Lang::En),
+        vec![
+            PlanItem::Text("(hex, 2 bytes)".into()),
+            code("ff00"),
+            PlanItem::Text("(1 files omitted: I need the Attach Files permission here)".into()),
+        ]
+    );
+}
+
+/// Grants `access` in every chat but "broken", counting the lookups.
+struct FakePermissions {
+    access: Access,
+    lookups: AtomicUsize,
+}
+
+impl FakePermissions {
+    fn new(access: Access) -> Self {
+        Self {
+            access,
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl PermissionSource<&'static str> for FakePermissions {
+    async fn access(&self, chat: &&'static str) -> Result<Access> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        if *chat == "broken" {
+            bail!("missing access");
+        }
+        Ok(self.access)
+    }
+}
+
+const READ_ONLY: Access = Access {
+    send: false,
+    attach: false,
+    slow_mode: None,
+};
+
+#[tokio::test]
+async fn access_is_cached() {
+    let source = FakePermissions::new(READ_ONLY);
+    let cache = AccessCache::new(Duration::from_secs(60));
+    assert_eq!(cache.get(&source, "general").await, READ_ONLY);
+    assert_eq!(cache.get(&source, "general").await, READ_ONLY);
+    assert_eq!(source.lookups(), 1);
+    cache.get(&source, "random").await;
+    assert_eq!(source.lookups(), 2);
+}
+
+#[tokio::test]
+async fn cached_access_expires() {
+    let source = FakePermissions::new(READ_ONLY);
+    let cache = AccessCache::new(Duration::from_millis(50));
+    cache.get(&source, "general").await;
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    cache.get(&source, "general").await;
+    assert_eq!(source.lookups(), 2);
+}
+
+#[tokio::test]
+async fn failed_lookups_grant_full_access_and_are_retried() {
+    let source = FakePermissions::new(READ_ONLY);
+    let cache = AccessCache::new(Duration::from_secs(60));
+    assert_eq!(cache.get(&source, "broken").await, Access::FULL);
+    assert_eq!(cache.get(&source, "broken").await, Access::FULL);
+    assert_eq!(source.lookups(), 2);
+}
+
+#[test]
+fn missing_permissions_restrict_access() {
+    let permissions = ChannelPermissions {
+        send_messages: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        permissions.access(),
+        Access {
+            send: true,
+            attach: false,
+            slow_mode: None,
+        }
+    );
+    assert_eq!(
+        permissions.access().delivery(1),
+        Delivery::Chat {
+            attach: false,
+            page_delay: None,
+        }
+    );
+    assert_eq!(
+        ChannelPermissions::default().access().delivery(1),
+        Delivery::Private
+    );
+}
+
+#[test]
+fn threads_have_their_own_send_permission() {
+    let permissions = ChannelPermissions {
+        thread: true,
+        send_messages: true,
+        ..Default::default()
+    };
+    assert!(!permissions.access().send);
+    let permissions = ChannelPermissions {
+        thread: true,
+        send_messages_in_threads: true,
+        ..Default::default()
+    };
+    assert!(permissions.access().send);
+}
+
+#[test]
+fn moderators_are_exempt_from_slow_mode() {
+    let permissions = ChannelPermissions {
+        send_messages: true,
+        slow_mode: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+    assert_eq!(permissions.access().slow_mode, Some(Duration::from_secs(5)));
+    let moderator = ChannelPermissions {
+        moderator: true,
+        ..permissions
+    };
+    assert_eq!(moderator.access().slow_mode, None);
+    let disabled = ChannelPermissions {
+        slow_mode: Some(Duration::ZERO),
+        ..permissions
+    };
+    assert_eq!(disabled.access().slow_mode, None);
+}
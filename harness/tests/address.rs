@@ -0,0 +1,35 @@
+use std::net::IpAddr;
+use yozuk_bot_core::is_public_ip;
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[test]
+fn public_addresses_are_allowed() {
+    for addr in ["93.184.216.34", "8.8.8.8", "2606:2800:220:1::248"] {
+        assert!(is_public_ip(ip(addr)), "{}", addr);
+    }
+}
+
+#[test]
+fn local_and_private_addresses_are_rejected() {
+    for addr in [
+        "127.0.0.1",
+        "0.0.0.0",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.100.100.200",
+        "255.255.255.255",
+        "::1",
+        "::",
+        "fd00:ec2::254",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "::ffff:192.168.1.1",
+    ] {
+        assert!(!is_public_ip(ip(addr)), "{}", addr);
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+use yozuk_bot_core::{AnalyticsSink, CommandEvent, Lang, Limits};
+use yozuk_bot_harness::{Incoming, MockTransport};
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<CommandEvent>>);
+
+impl AnalyticsSink for RecordingSink {
+    fn record(&self, event: &CommandEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn records_one_event_per_command() {
+    let sink = Arc::new(RecordingSink::default());
+    let mut transport = MockTransport::new(Limits {
+        analytics: sink.clone(),
+        ..Default::default()
+    });
+    // Matches both calc and unicode.
+    transport.receive(Incoming::new("1 + 2"));
+    transport.receive(Incoming::new("md5"));
+    transport.receive(Incoming::new("asdfqwer zxcv"));
+
+    let event = |command: &str, success| CommandEvent {
+        command: command.into(),
+        lang: Lang::En,
+        success,
+        sent_at: None,
+    };
+    assert_eq!(
+        *sink.0.lock().unwrap(),
+        [
+            event("calc", true),
+            event("unicode", true),
+            event("digest", false)
+        ]
+    );
+}
@@ -0,0 +1,207 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    run_bot, unpack_archives, ArchiveLimits, Attachment, BotConfig, ErrorCategory, Limits,
+    PlanItem, PlanStatus, RenderedMessage, ResponsePlan, UnpackError,
+};
+use yozuk_bot_harness::{Incoming, MemoryTransport};
+use yozuk_sdk::prelude::*;
+use zip::write::{FileOptions, ZipWriter};
+use zip::CompressionMethod;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in entries {
+        if name.ends_with('/') {
+            writer.add_directory(*name, options).unwrap();
+        } else {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn file(data: Vec<u8>, name: &str) -> Attachment {
+    Attachment::new(data, media_type!(APPLICATION / OCTET_STREAM).into()).with_name(name)
+}
+
+fn typed(data: Vec<u8>, media_type: &str) -> Attachment {
+    Attachment::new(data, MediaTypeBuf::from_string(media_type.into()).unwrap())
+}
+
+fn unpack(attachment: Attachment, limits: &ArchiveLimits) -> Result<Attachment, UnpackError> {
+    unpack_archives(vec![attachment], limits).map(|mut unpacked| unpacked.remove(0))
+}
+
+#[test]
+fn single_files_are_extracted_from_zip() {
+    let archive = zip(&[("images/", b""), ("images/logo.png", PNG)]);
+    let unpacked = unpack(file(archive, "images.ZIP"), &Default::default()).unwrap();
+    assert_eq!(&unpacked.data[..], PNG);
+    assert_eq!(unpacked.media_type, media_type!(IMAGE / PNG));
+}
+
+#[test]
+fn gzip_is_extracted() {
+    let unpacked = unpack(file(gzip(b"hello"), "hello.txt.gz"), &Default::default()).unwrap();
+    assert_eq!(&unpacked.data[..], b"hello");
+    assert_eq!(unpacked.media_type, media_type!(TEXT / PLAIN));
+}
+
+#[test]
+fn other_attachments_are_kept() {
+    let unpacked = unpack(file(PNG.to_vec(), "logo.png"), &Default::default()).unwrap();
+    assert_eq!(&unpacked.data[..], PNG);
+    assert_eq!(unpacked.media_type, media_type!(APPLICATION / OCTET_STREAM));
+}
+
+#[test]
+fn documents_in_zip_format_are_kept() {
+    let document = zip(&[
+        ("[Content_Types].xml", b"<Types/>"),
+        ("word/document.xml", b"<document/>"),
+    ]);
+    let attachment = typed(
+        document.clone(),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    )
+    .with_name("report.docx");
+    let unpacked = unpack(attachment, &Default::default()).unwrap();
+    assert_eq!(&unpacked.data[..], &document[..]);
+    assert_eq!(unpacked.name.as_deref(), Some("report.docx"));
+}
+
+#[test]
+fn large_archives_are_kept() {
+    let archive = zip(&[("logo.png", PNG)]);
+    let limits = ArchiveLimits {
+        max_archive_size: archive.len() - 1,
+        ..Default::default()
+    };
+    let unpacked = unpack(file(archive.clone(), "logo.zip"), &limits).unwrap();
+    assert_eq!(&unpacked.data[..], &archive[..]);
+}
+
+#[test]
+fn archives_with_several_files_are_rejected() {
+    let archive = zip(&[("a.txt", b"a"), ("b.txt", b"b")]);
+    assert_eq!(
+        unpack(typed(archive, "application/zip"), &Default::default()).unwrap_err(),
+        UnpackError::FileCount
+    );
+}
+
+#[test]
+fn archives_with_too_many_entries_are_rejected() {
+    let names = (0..4).map(|i| format!("{}/", i)).collect::<Vec<_>>();
+    let mut entries = names
+        .iter()
+        .map(|name| (name.as_str(), &b""[..]))
+        .collect::<Vec<_>>();
+    entries.push(("a.txt", b"a"));
+    let limits = ArchiveLimits {
+        max_entries: 4,
+        ..Default::default()
+    };
+    assert_eq!(
+        unpack(file(zip(&entries), "dirs.zip"), &limits).unwrap_err(),
+        UnpackError::FileCount
+    );
+}
+
+#[test]
+fn zip_bombs_are_rejected() {
+    let zeros = vec![0; 1024 * 1024];
+    let archive = zip(&[("zeros.bin", &zeros)]);
+    assert!(archive.len() < 4096);
+    let limits = ArchiveLimits {
+        max_extracted_size: 64 * 1024,
+        ..Default::default()
+    };
+    assert_eq!(
+        unpack(typed(archive, "application/x-zip-compressed"), &limits).unwrap_err(),
+        UnpackError::TooLarge(64 * 1024)
+    );
+}
+
+#[test]
+fn gzip_bombs_are_rejected() {
+    let archive = typed(gzip(&vec![0; 1024 * 1024]), "application/gzip");
+    let limits = ArchiveLimits {
+        max_extracted_size: 64 * 1024,
+        ..Default::default()
+    };
+    assert_eq!(
+        unpack(archive, &limits).unwrap_err(),
+        UnpackError::TooLarge(64 * 1024)
+    );
+}
+
+#[test]
+fn broken_archives_are_rejected() {
+    let mut archive = zip(&[("logo.png", PNG)]);
+    archive.truncate(archive.len() / 2);
+    assert!(matches!(
+        unpack(typed(archive, "application/zip"), &Default::default()),
+        Err(UnpackError::Invalid(_))
+    ));
+}
+
+async fn reject(archive: Vec<u8>, limits: ArchiveLimits) -> ResponsePlan {
+    let transport = Arc::new(MemoryTransport::new([
+        Incoming::new("md5").attach(archive, MediaType::parse("application/zip").unwrap())
+    ]));
+    let config = BotConfig {
+        archives: Some(limits),
+        ..BotConfig::new("test", Limits::default())
+    };
+    run_bot(
+        transport.clone(),
+        Arc::new(Yozuk::builder().build()),
+        config,
+    )
+    .await;
+    match transport.sent().remove(0).1 {
+        RenderedMessage::Response { plan, .. } => plan,
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn rejected_archives_are_explained() {
+    let plan = reject(zip(&[("a.txt", b"a"), ("b.txt", b"b")]), Default::default()).await;
+    assert_eq!(plan.status, PlanStatus::Failure);
+    assert_eq!(plan.category, Some(ErrorCategory::CommandError));
+    assert_eq!(
+        plan.items,
+        vec![
+            PlanItem::Error {
+                category: ErrorCategory::CommandError
+            },
+            PlanItem::Text("Please send an archive that contains exactly one file.".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn zip_bombs_are_too_large_inputs() {
+    let limits = ArchiveLimits {
+        max_extracted_size: 1024,
+        ..Default::default()
+    };
+    let plan = reject(zip(&[("zeros.bin", &[0; 4096])]), limits).await;
+    assert_eq!(plan.category, Some(ErrorCategory::InputTooLarge));
+}
@@ -0,0 +1,86 @@
+use std::io::{Cursor, Read};
+use yozuk_bot_core::{plan_outputs, Lang, Limits, PlanItem};
+use yozuk_sdk::prelude::*;
+use zip::ZipArchive;
+
+fn files(title: &str, names: &[&str]) -> Output {
+    names
+        .iter()
+        .fold(Output::new().set_title(title), |output, name| {
+            output.add_block(
+                block::Data::new()
+                    .set_data(vec![0xff, name.len() as u8])
+                    .set_file_name(*name)
+                    .set_media_type(media_type!(APPLICATION / OCTET_STREAM)),
+            )
+        })
+}
+
+fn plan(output: Output, limits: Limits) -> Vec<PlanItem> {
+    plan_outputs(vec![output], &limits, Lang::En).items
+}
+
+fn bundled(threshold: usize) -> Limits {
+    Limits {
+        bundle_threshold: Some(threshold),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn files_up_to_the_threshold_are_sent_separately() {
+    let items = plan(files("Dice", &["a.bin", "b.bin"]), bundled(2));
+    assert_eq!(items.len(), 2);
+    assert!(items
+        .iter()
+        .all(|item| matches!(item, PlanItem::File { name, .. } if name.ends_with(".bin"))));
+}
+
+#[test]
+fn files_over_the_threshold_are_bundled() {
+    let items = plan(files("QR Code", &["a.bin", "b.bin", "a.bin"]), bundled(2));
+    let (name, data) = match &items[..] {
+        [PlanItem::File { name, data, .. }] => (name, data),
+        other => panic!("unexpected items: {:?}", other),
+    };
+    assert_eq!(name, "qr-code.zip");
+
+    let mut archive = ZipArchive::new(Cursor::new(data.to_vec())).unwrap();
+    let mut entries = vec![];
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).unwrap();
+        let mut content = vec![];
+        file.read_to_end(&mut content).unwrap();
+        entries.push((file.name().to_string(), content));
+    }
+    assert_eq!(
+        entries,
+        vec![
+            ("a.bin".to_string(), vec![0xff, 5]),
+            ("b.bin".to_string(), vec![0xff, 5]),
+            ("a-1.bin".to_string(), vec![0xff, 5]),
+        ]
+    );
+}
+
+#[test]
+fn bundling_is_off_without_a_threshold() {
+    let items = plan(
+        files("Dice", &["a.bin", "b.bin", "c.bin"]),
+        Limits::default(),
+    );
+    assert_eq!(items.len(), 3);
+}
+
+#[test]
+fn archives_over_the_size_limit_are_not_sent() {
+    let limits = Limits {
+        max_file_size: Some(16),
+        ..bundled(1)
+    };
+    let items = plan(files("Dice", &["a.bin", "b.bin"]), limits);
+    assert_eq!(items.len(), 2);
+    assert!(items
+        .iter()
+        .all(|item| matches!(item, PlanItem::File { name, .. } if name.ends_with(".bin"))));
+}
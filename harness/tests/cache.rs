@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    plan_response, Limits, PlanStatus, ResponsePlan, ResultCache, DEFAULT_UNCACHED_SKILLS,
+};
+use yozuk_sdk::prelude::*;
+
+fn limits(ttl: Duration) -> Limits {
+    Limits {
+        cache: Some(Arc::new(ResultCache::new(
+            ttl,
+            DEFAULT_UNCACHED_SKILLS.iter().copied(),
+        ))),
+        ..Default::default()
+    }
+}
+
+fn plan(zuk: &Yozuk, text: &str, locale: Option<&str>, limits: &Limits) -> ResponsePlan {
+    let user = UserContext {
+        locale: locale.map(Into::into),
+        ..Default::default()
+    };
+    let plan = plan_response(zuk, text, vec![], &user, limits);
+    assert_eq!(plan.status, PlanStatus::Success, "{:?}", plan.items);
+    plan
+}
+
+#[test]
+fn identical_requests_hit_the_cache() {
+    let zuk = Yozuk::builder().build();
+    let limits = limits(Duration::from_secs(60));
+    let first = plan(&zuk, "hello to md5", None, &limits);
+    assert_eq!(first.cache_hit, Some(false));
+    let second = plan(&zuk, "hello to md5", None, &limits);
+    assert_eq!(second.cache_hit, Some(true));
+    assert_eq!(format!("{:?}", first.items), format!("{:?}", second.items));
+    assert_eq!(limits.cache.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn different_requests_miss_the_cache() {
+    let zuk = Yozuk::builder().build();
+    let limits = limits(Duration::from_secs(60));
+    plan(&zuk, "hello to md5", None, &limits);
+    assert_eq!(
+        plan(&zuk, "world to md5", None, &limits).cache_hit,
+        Some(false)
+    );
+    assert_eq!(
+        plan(&zuk, "hello to md5", Some("ja-JP"), &limits).cache_hit,
+        Some(false)
+    );
+    assert_eq!(limits.cache.as_ref().unwrap().len(), 3);
+}
+
+#[test]
+fn results_expire() {
+    let zuk = Yozuk::builder().build();
+    let limits = limits(Duration::from_millis(100));
+    plan(&zuk, "hello to md5", None, &limits);
+    sleep(Duration::from_millis(150));
+    assert_eq!(
+        plan(&zuk, "hello to md5", None, &limits).cache_hit,
+        Some(false)
+    );
+}
+
+#[test]
+fn uncached_skills_are_skipped() {
+    let zuk = Yozuk::builder().build();
+    let limits = limits(Duration::from_secs(60));
+    let first = plan(&zuk, "uuid", None, &limits);
+    assert_eq!(first.cache_hit, None);
+    let second = plan(&zuk, "uuid", None, &limits);
+    assert_eq!(second.cache_hit, None);
+    assert_ne!(format!("{:?}", first.items), format!("{:?}", second.items));
+    assert!(limits.cache.as_ref().unwrap().is_empty());
+}
+
+#[test]
+fn no_cache_is_consulted_by_default() {
+    let zuk = Yozuk::builder().build();
+    assert_eq!(
+        plan(&zuk, "hello to md5", None, &Limits::default()).cache_hit,
+        None
+    );
+}
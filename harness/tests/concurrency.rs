@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use yozuk_bot_core::download_all;
+
+/// A fake downloader that records the highest number of concurrent downloads.
+#[derive(Default)]
+struct Downloader {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl Downloader {
+    async fn download(&self, id: usize) -> usize {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        // Later items finish first, so the order of the results is tested too.
+        tokio::time::sleep(Duration::from_millis(20 - id as u64)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        id
+    }
+}
+
+#[tokio::test]
+async fn limits_downloads_in_flight() {
+    for limit in [1, 2, 4] {
+        let downloader = Downloader::default();
+        let results = download_all((0..10).map(|id| downloader.download(id)), limit).await;
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+        assert_eq!(downloader.max_in_flight.load(Ordering::SeqCst), limit);
+    }
+}
+
+#[tokio::test]
+async fn treats_zero_as_one() {
+    let downloader = Downloader::default();
+    download_all((0..3).map(|id| downloader.download(id)), 0).await;
+    assert_eq!(downloader.max_in_flight.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,68 @@
+use yozuk_bot_core::{ReplyDestination, Route};
+
+#[test]
+fn destinations_are_parsed() {
+    assert_eq!(
+        "same".parse::<ReplyDestination>().unwrap(),
+        ReplyDestination::Same
+    );
+    assert_eq!(
+        "dm".parse::<ReplyDestination>().unwrap(),
+        ReplyDestination::Dm
+    );
+    assert_eq!(
+        "thread".parse::<ReplyDestination>().unwrap(),
+        ReplyDestination::Thread
+    );
+    assert!("channel".parse::<ReplyDestination>().is_err());
+}
+
+#[test]
+fn same_answers_in_place() {
+    for private in [false, true] {
+        assert_eq!(
+            ReplyDestination::Same.route(private, true, "md5", 100),
+            Route::InPlace
+        );
+    }
+}
+
+#[test]
+fn dm_answers_group_messages_privately() {
+    assert_eq!(
+        ReplyDestination::Dm.route(false, true, "md5", 100),
+        Route::Dm
+    );
+    assert_eq!(
+        ReplyDestination::Dm.route(true, true, "md5", 100),
+        Route::InPlace
+    );
+}
+
+#[test]
+fn thread_is_named_after_the_message() {
+    assert_eq!(
+        ReplyDestination::Thread.route(false, true, "  hello world md5 ", 100),
+        Route::Thread("hello world md5".into())
+    );
+    assert_eq!(
+        ReplyDestination::Thread.route(false, true, "こんにちは世界", 5),
+        Route::Thread("こんにちは".into())
+    );
+    assert_eq!(
+        ReplyDestination::Thread.route(false, true, " ", 100),
+        Route::Thread("Yozuk".into())
+    );
+}
+
+#[test]
+fn thread_falls_back_to_in_place() {
+    assert_eq!(
+        ReplyDestination::Thread.route(true, true, "md5", 100),
+        Route::InPlace
+    );
+    assert_eq!(
+        ReplyDestination::Thread.route(false, false, "md5", 100),
+        Route::InPlace
+    );
+}
@@ -0,0 +1,60 @@
+use yozuk_bot_core::{collect_downloads, Attachment, DownloadFailure, Downloaded, Lang};
+use yozuk_sdk::prelude::*;
+
+fn batch() -> Vec<(String, Result<Attachment, &'static str>)> {
+    vec![
+        (
+            "a.txt".into(),
+            Ok(Attachment::new(&b"a"[..], media_type!(TEXT / PLAIN).into())),
+        ),
+        ("b.png".into(), Err("connection reset")),
+        (
+            "c.txt".into(),
+            Ok(Attachment::new(&b"c"[..], media_type!(TEXT / PLAIN).into())),
+        ),
+    ]
+}
+
+#[test]
+fn aborts_on_partial_failure() {
+    match collect_downloads(batch(), DownloadFailure::Abort, Lang::En) {
+        Downloaded::Abort(text) => assert_eq!(
+            text,
+            "I couldn't download one of your attachments, please retry."
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn skips_failed_attachments() {
+    match collect_downloads(batch(), DownloadFailure::Skip, Lang::En) {
+        Downloaded::Proceed {
+            attachments,
+            notice,
+        } => {
+            let data = attachments
+                .iter()
+                .map(|attachment| attachment.data.as_ref())
+                .collect::<Vec<_>>();
+            assert_eq!(data, [b"a", b"c"]);
+            assert_eq!(
+                notice.as_deref(),
+                Some("Skipped attachments that couldn't be downloaded: b.png")
+            );
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn proceeds_silently_without_failures() {
+    let batch = batch().into_iter().filter(|(_, result)| result.is_ok());
+    match collect_downloads(batch, DownloadFailure::Abort, Lang::En) {
+        Downloaded::Proceed {
+            attachments,
+            notice: None,
+        } => assert_eq!(attachments.len(), 2),
+        other => panic!("unexpected {:?}", other),
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    plan_response, AnalyticsSink, CommandEvent, Lang, Limits, PlanItem, PlanStatus, ResultCache,
+};
+use yozuk_sdk::prelude::*;
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<CommandEvent>>);
+
+impl AnalyticsSink for RecordingSink {
+    fn record(&self, event: &CommandEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn dry_run_lists_commands_without_running_them() {
+    let sink = Arc::new(RecordingSink::default());
+    let cache = Arc::new(ResultCache::new(Duration::from_secs(60), [""; 0]));
+    let limits = Limits {
+        dry_run: true,
+        analytics: sink.clone(),
+        cache: Some(cache.clone()),
+        ..Default::default()
+    };
+    let plan = plan_response(
+        &Yozuk::builder().build(),
+        "aGVsbG8= base64 decode",
+        vec![],
+        &UserContext::default(),
+        &limits,
+    );
+
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert!(plan.command.is_some());
+    match &plan.items[..] {
+        [PlanItem::Text(text)] => {
+            assert!(text.starts_with(&Lang::En.tr("dry-run", &[])), "{}", text);
+            assert!(text.contains("\n- base64"), "{}", text);
+        }
+        items => panic!("unexpected items: {:?}", items),
+    }
+    assert!(sink.0.lock().unwrap().is_empty());
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn unrecognized_messages_are_still_apologized_for() {
+    let limits = Limits {
+        dry_run: true,
+        ..Default::default()
+    };
+    let plan = plan_response(
+        &Yozuk::builder().build(),
+        "!!!",
+        vec![],
+        &UserContext::default(),
+        &limits,
+    );
+    assert_eq!(plan.status, PlanStatus::Unrecognized);
+}
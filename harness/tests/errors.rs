@@ -0,0 +1,197 @@
+use std::time::Duration;
+use tracing::Level;
+use yozuk_bot_core::{
+    format_size, plan_failure, run_plan, spawn_plan, ErrorCategory, Lang, Limits, PlanItem,
+    PlanStatus, Redactor, ResponsePlan,
+};
+use yozuk_bot_harness::{Incoming, MockTransport};
+
+#[test]
+fn failed_commands_describe_the_error() {
+    let mut transport = MockTransport::new(Limits::default());
+    let plan = transport.receive(Incoming::new("md5"));
+    assert_eq!(plan.status, PlanStatus::Failure);
+    assert_eq!(plan.category, Some(ErrorCategory::CommandError));
+    assert_eq!(
+        plan.items[0],
+        PlanItem::Error {
+            category: ErrorCategory::CommandError
+        }
+    );
+    let error = plan.error.unwrap();
+    assert!(
+        error.contains("No valid input source provided"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn successful_commands_have_no_error() {
+    let mut transport = MockTransport::new(Limits::default());
+    let plan = transport.receive(Incoming::new("aGVsbG8= base64 decode"));
+    assert_eq!(plan.error, None);
+    assert_eq!(plan.category, None);
+}
+
+#[tokio::test]
+async fn panics_are_described_and_redacted() {
+    let limits = Limits {
+        redactor: Redactor::new(["secret"]).unwrap(),
+        ..Default::default()
+    };
+    let plan = spawn_plan(&limits, "boom", || -> ResponsePlan {
+        panic!("leaked secret")
+    })
+    .await;
+    assert_eq!(plan.status, PlanStatus::Failure);
+    assert_eq!(plan.category, Some(ErrorCategory::InternalError));
+    let error = plan.error.unwrap();
+    assert!(error.starts_with("panicked: leaked "), "{}", error);
+    assert!(!error.contains("secret"), "{}", error);
+}
+
+#[test]
+fn unmatched_messages_are_no_match() {
+    let mut transport = MockTransport::new(Limits::default());
+    let plan = transport.receive(Incoming::new("??"));
+    assert_eq!(plan.status, PlanStatus::Unrecognized);
+    assert_eq!(plan.category, Some(ErrorCategory::NoMatch));
+}
+
+#[tokio::test]
+async fn slow_commands_time_out() {
+    let limits = Limits {
+        command_timeout: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let plan = spawn_plan(&limits, "sleep", || {
+        std::thread::sleep(Duration::from_millis(100));
+        ResponsePlan::default()
+    })
+    .await;
+    assert_eq!(plan.category, Some(ErrorCategory::Timeout));
+    assert_eq!(
+        plan.items,
+        vec![PlanItem::Error {
+            category: ErrorCategory::Timeout
+        }]
+    );
+}
+
+#[test]
+fn blocking_runs_share_the_timeout_and_panic_handling() {
+    let limits = Limits {
+        command_timeout: Duration::from_millis(10),
+        redactor: Redactor::new(["secret"]).unwrap(),
+        ..Default::default()
+    };
+    let plan = run_plan(&limits, "sleep", || {
+        std::thread::sleep(Duration::from_millis(100));
+        ResponsePlan::default()
+    });
+    assert_eq!(plan.category, Some(ErrorCategory::Timeout));
+
+    let limits = Limits {
+        command_timeout: Duration::from_secs(30),
+        ..limits
+    };
+    let plan = run_plan(&limits, "boom", || -> ResponsePlan {
+        panic!("leaked secret")
+    });
+    assert_eq!(plan.category, Some(ErrorCategory::InternalError));
+    assert_eq!(plan.error.unwrap(), "panicked: leaked [REDACTED]");
+
+    let plan = run_plan(&limits, "fast", ResponsePlan::default);
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert_eq!(plan.category, None);
+}
+
+#[test]
+fn categories_have_their_own_message_and_level() {
+    let expected = [
+        (
+            ErrorCategory::NoMatch,
+            "Sorry, I can't understand your request.",
+            Level::INFO,
+        ),
+        (
+            ErrorCategory::InputTooLarge,
+            "Your input is too large for me to process.",
+            Level::INFO,
+        ),
+        (
+            ErrorCategory::Timeout,
+            "This command took too long and was cancelled.",
+            Level::WARN,
+        ),
+        (
+            ErrorCategory::CommandError,
+            "The command couldn't process your input.",
+            Level::ERROR,
+        ),
+        (
+            ErrorCategory::InternalError,
+            "Sorry, something went wrong while running this command.",
+            Level::ERROR,
+        ),
+    ];
+    assert_eq!(expected.len(), ErrorCategory::ALL.len());
+    for (category, message, level) in expected {
+        assert_eq!(category.message(Lang::En), message, "{}", category);
+        assert_eq!(category.level(), level, "{}", category);
+    }
+}
+
+#[test]
+fn categories_are_told_apart_in_every_language() {
+    for lang in [Lang::En, Lang::De, Lang::Ja] {
+        let mut messages = ErrorCategory::ALL
+            .iter()
+            .map(|category| category.message(lang))
+            .collect::<Vec<_>>();
+        messages.sort();
+        messages.dedup();
+        assert_eq!(messages.len(), ErrorCategory::ALL.len(), "{:?}", lang);
+    }
+}
+
+#[test]
+fn only_routine_failures_are_logged_as_info() {
+    for category in ErrorCategory::ALL {
+        let routine = matches!(
+            category,
+            ErrorCategory::NoMatch | ErrorCategory::InputTooLarge
+        );
+        assert_eq!(routine, category.level() == Level::INFO, "{}", category);
+    }
+}
+
+#[test]
+fn rejected_inputs_are_reported_with_their_category() {
+    let details = Lang::En.tr("too-large", &[("max", &format_size(10 * 1024 * 1024))]);
+    let plan = plan_failure(
+        ErrorCategory::InputTooLarge,
+        details,
+        "attachments of 20000000 bytes".into(),
+    );
+    assert_eq!(plan.status, PlanStatus::Failure);
+    assert_eq!(plan.category, Some(ErrorCategory::InputTooLarge));
+    assert_eq!(
+        plan.items,
+        vec![
+            PlanItem::Error {
+                category: ErrorCategory::InputTooLarge
+            },
+            PlanItem::Text("Too large file input (10MiB max.)".into()),
+        ]
+    );
+}
+
+#[test]
+fn sizes_are_formatted_in_whole_units() {
+    assert_eq!(format_size(10 * 1024 * 1024), "10MiB");
+    assert_eq!(format_size(512 * 1024), "512KiB");
+    assert_eq!(format_size(1000), "1000B");
+    assert_eq!(format_size(0), "0B");
+}
@@ -0,0 +1,42 @@
+use yozuk_bot_core::{Admission, ContactGate};
+
+#[test]
+fn open_gate_allows_everyone() {
+    let gate = ContactGate::new(false, Vec::<String>::new());
+    assert!(gate.is_open());
+    assert_eq!(gate.check("alice@example.com", false), Admission::Allowed);
+}
+
+#[test]
+fn verified_contacts_are_allowed() {
+    let gate = ContactGate::new(true, Vec::<String>::new());
+    assert_eq!(gate.check("alice@example.com", true), Admission::Allowed);
+}
+
+#[test]
+fn unverified_contacts_are_instructed_once() {
+    let gate = ContactGate::new(true, Vec::<String>::new());
+    assert_eq!(gate.check("bob@example.com", false), Admission::Instruct);
+    assert_eq!(gate.check("bob@example.com", false), Admission::Ignore);
+    assert_eq!(gate.check("BOB@example.com", false), Admission::Ignore);
+    assert_eq!(gate.check("carol@example.com", false), Admission::Instruct);
+
+    // Instructions are repeated after access has been granted and lost again.
+    assert_eq!(gate.check("bob@example.com", true), Admission::Allowed);
+    assert_eq!(gate.check("bob@example.com", false), Admission::Instruct);
+}
+
+#[test]
+fn allow_list_skips_verification() {
+    let gate = ContactGate::new(true, ["Alice@Example.com"]);
+    assert_eq!(gate.check("alice@example.com", false), Admission::Allowed);
+    assert_eq!(gate.check("bob@example.com", false), Admission::Instruct);
+}
+
+#[test]
+fn allow_list_alone_restricts_access() {
+    let gate = ContactGate::new(false, ["alice@example.com"]);
+    assert!(!gate.is_open());
+    assert_eq!(gate.check("alice@example.com", false), Admission::Allowed);
+    assert_eq!(gate.check("bob@example.com", true), Admission::Instruct);
+}
@@ -0,0 +1,281 @@
+use std::path::PathBuf;
+use yozuk_bot_core::{plan_outputs, Lang, Limits, PlanItem, PlanStatus};
+use yozuk_bot_harness::{assert_golden, Incoming, MockTransport};
+use yozuk_sdk::prelude::*;
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+fn run(limits: Limits, msg: Incoming) -> MockTransport {
+    let mut transport = MockTransport::new(limits);
+    transport.receive(msg);
+    transport
+}
+
+#[test]
+fn utf8_data_is_inlined() {
+    let transport = run(
+        Limits::default(),
+        Incoming::new("aGVsbG8= base64 decode").from("alice"),
+    );
+    assert_golden(golden("utf8_data.txt"), transport.outbox());
+}
+
+#[test]
+fn binary_data_is_sent_as_file() {
+    let transport = run(
+        Limits::default(),
+        Incoming::new("/wD+ZmZm base64 decode").from("alice"),
+    );
+    assert_golden(golden("binary_data.txt"), transport.outbox());
+}
+
+#[test]
+fn file_names_are_kept() {
+    let transport = run(Limits::default(), Incoming::new("hello to QRCode"));
+    assert_golden(golden("file_name.txt"), transport.outbox());
+}
+
+#[test]
+fn attachments_are_read() {
+    let transport = run(
+        Limits::default(),
+        Incoming::new("base64").attach("/wD+", media_type!(TEXT / PLAIN)),
+    );
+    assert_golden(golden("attachment.txt"), transport.outbox());
+}
+
+#[test]
+fn long_output_is_split_at_line_breaks() {
+    let limits = Limits {
+        max_text_length: Some(40),
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("color #ff0000"));
+    assert_golden(golden("split.txt"), transport.outbox());
+}
+
+#[test]
+fn unrecognized_with_suggestions() {
+    let mut transport = MockTransport::new(Limits::default());
+    let plan = transport.receive(Incoming::new("md"));
+    assert_eq!(plan.status, PlanStatus::Unrecognized);
+    assert_golden(golden("suggestions.txt"), transport.outbox());
+}
+
+#[test]
+fn unrecognized_without_suggestions() {
+    let limits = Limits {
+        suggestions: 0,
+        ..Default::default()
+    };
+    let mut transport = MockTransport::new(limits);
+    let plan = transport.receive(Incoming::new("md"));
+    assert_eq!(plan.status, PlanStatus::Unrecognized);
+    assert_golden(golden("no_suggestions.txt"), transport.outbox());
+}
+
+#[test]
+fn text_only_summarizes_binary_data() {
+    let limits = Limits {
+        text_only: true,
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("hello to QRCode"));
+    assert!(transport
+        .outbox()
+        .iter()
+        .all(|item| !matches!(item, PlanItem::File { .. })));
+    assert_golden(golden("text_only_summary.txt"), transport.outbox());
+}
+
+#[test]
+fn text_only_inlines_small_binary_data() {
+    let limits = Limits {
+        text_only: true,
+        inline_binary: Some("hex:6".parse().unwrap()),
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("/wD+ZmZm base64 decode"));
+    assert_golden(golden("text_only_inline.txt"), transport.outbox());
+}
+
+#[test]
+fn small_binary_data_is_inlined() {
+    for (inline, name) in [
+        ("hex:6", "inline_hex.txt"),
+        ("base64:6", "inline_base64.txt"),
+    ] {
+        let limits = Limits {
+            inline_binary: Some(inline.parse().unwrap()),
+            ..Default::default()
+        };
+        let transport = run(limits, Incoming::new("/wD+ZmZm base64 decode"));
+        assert_golden(golden(name), transport.outbox());
+    }
+}
+
+#[test]
+fn larger_binary_data_is_sent_as_file() {
+    for inline in ["hex:5", "base64:5"] {
+        let limits = Limits {
+            inline_binary: Some(inline.parse().unwrap()),
+            ..Default::default()
+        };
+        let transport = run(limits, Incoming::new("/wD+ZmZm base64 decode"));
+        assert!(
+            matches!(transport.outbox(), [PlanItem::File { data, .. }] if data.len() == 6),
+            "{}: {:?}",
+            inline,
+            transport.outbox()
+        );
+    }
+}
+
+#[test]
+fn files_over_the_size_limit_are_omitted() {
+    let limits = Limits {
+        max_file_size: Some(5),
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("/wD+ZmZm base64 decode"));
+    assert_golden(golden("file_too_large.txt"), transport.outbox());
+}
+
+#[test]
+fn raw_flag_sends_small_binary_data_as_file() {
+    let limits = Limits {
+        inline_binary: Some("hex:64".parse().unwrap()),
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("/wD+ZmZm base64 decode --raw"));
+    assert!(matches!(transport.outbox(), [PlanItem::File { .. }]));
+}
+
+#[test]
+fn raw_flag_sends_text_as_file() {
+    let limits = Limits {
+        max_text_length: Some(4),
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("aGVsbG8= base64 decode --raw"));
+    assert_golden(golden("raw.txt"), transport.outbox());
+}
+
+#[test]
+fn raw_flag_can_be_disabled() {
+    let limits = Limits {
+        raw_flag: None,
+        ..Default::default()
+    };
+    let mut transport = MockTransport::new(limits);
+    let plan = transport.receive(Incoming::new("aGVsbG8= base64 decode --raw"));
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert!(plan
+        .items
+        .iter()
+        .all(|item| !matches!(item, PlanItem::File { .. })));
+}
+
+#[test]
+fn sections_separate_outputs() {
+    let limits = Limits {
+        sections: true,
+        ..Default::default()
+    };
+    let outputs = vec![
+        Output::new()
+            .set_title("Calculator")
+            .add_block(block::Data::new().set_text_data("3")),
+        Output::new()
+            .set_title("QR Code")
+            .add_block(block::Comment::new().set_text("Generated:"))
+            .add_block(
+                block::Data::new()
+                    .set_data(vec![0xffu8; 4])
+                    .set_file_name("qrcode.png")
+                    .set_media_type(media_type!(IMAGE / PNG)),
+            ),
+    ];
+    let plan = plan_outputs(outputs, &limits, Lang::En);
+    assert_golden(golden("sections.txt"), &plan.items);
+}
+
+#[test]
+fn single_outputs_have_no_section() {
+    let limits = Limits {
+        sections: true,
+        ..Default::default()
+    };
+    let transport = run(limits, Incoming::new("aGVsbG8= base64 decode"));
+    assert_golden(golden("utf8_data.txt"), transport.outbox());
+}
+
+#[test]
+fn many_blocks_are_truncated() {
+    let limits = Limits {
+        max_blocks: Some(3),
+        ..Default::default()
+    };
+    let output = (0..50).fold(Output::new(), |output, n| {
+        output.add_block(block::Comment::new().set_text(n.to_string()))
+    });
+    let plan = plan_outputs(vec![output], &limits, Lang::En);
+    assert_eq!(
+        plan.items,
+        vec![
+            PlanItem::Text("0".into()),
+            PlanItem::Text("1".into()),
+            PlanItem::Text("2".into()),
+            PlanItem::Text("…(truncated, 47 more)".into()),
+        ]
+    );
+}
+
+#[test]
+fn truncation_does_not_leave_an_empty_section() {
+    let limits = Limits {
+        sections: true,
+        max_blocks: Some(1),
+        ..Default::default()
+    };
+    let outputs = vec![
+        Output::new().add_block(block::Comment::new().set_text("first")),
+        Output::new().add_block(block::Comment::new().set_text("second")),
+    ];
+    let plan = plan_outputs(outputs, &limits, Lang::En);
+    assert_eq!(
+        plan.items,
+        vec![
+            PlanItem::Section {
+                title: String::new()
+            },
+            PlanItem::Text("first".into()),
+            PlanItem::Text("…(truncated, 1 more)".into()),
+        ]
+    );
+}
+
+#[test]
+fn empty_outputs_are_explained() {
+    let outputs = vec![
+        Output::new().set_title("Empty"),
+        Output::new()
+            .add_block(block::Comment::new().set_text(""))
+            .add_block(block::Data::new().set_data(Vec::<u8>::new())),
+    ];
+    let plan = plan_outputs(outputs, &Limits::default(), Lang::En);
+    assert_golden(golden("no_output.txt"), &plan.items);
+}
+
+#[test]
+fn no_outputs_at_all_are_explained() {
+    let plan = plan_outputs(vec![], &Limits::default(), Lang::De);
+    assert_eq!(
+        plan.items,
+        vec![PlanItem::Text(Lang::De.tr("no-output", &[]))]
+    );
+}
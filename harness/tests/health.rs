@@ -0,0 +1,78 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+use yozuk_bot_core::Health;
+
+const STALE_AFTER: Duration = Duration::from_millis(200);
+
+#[test]
+fn ready_only_while_connected() {
+    let health = Health::new(STALE_AFTER);
+    assert!(!health.is_ready());
+    health.set_connected(true);
+    assert!(health.is_ready());
+    health.set_connected(false);
+    assert!(!health.is_ready());
+}
+
+#[test]
+fn events_must_be_recent() {
+    let health = Health::new(STALE_AFTER);
+    health.set_connected(true);
+    sleep(STALE_AFTER + Duration::from_millis(50));
+    assert!(!health.is_ready());
+    health.event();
+    assert!(health.is_ready());
+}
+
+#[test]
+fn events_alone_are_not_enough() {
+    let health = Health::new(STALE_AFTER);
+    health.event();
+    assert!(!health.is_ready());
+}
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test]
+async fn ready_endpoint_reports_staleness() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let health = Arc::new(Health::new(STALE_AFTER));
+    health.serve(addr).unwrap();
+    health.set_connected(true);
+
+    let request = |path: &'static str| tokio::task::spawn_blocking(move || get(addr, path));
+    let response = request("/ready").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains(r#""status":"ok""#), "{}", response);
+
+    tokio::time::sleep(STALE_AFTER + Duration::from_millis(50)).await;
+    let response = request("/ready").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    assert!(
+        response.contains(r#""status":"unavailable""#),
+        "{}",
+        response
+    );
+
+    let response = request("/live").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let response = request("/metrics").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    help_text, is_help, run_bot, AnalyticsSink, BotConfig, CommandEvent, Lang, Limits,
+    RenderedMessage,
+};
+use yozuk_bot_harness::{Incoming, MemoryTransport};
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<CommandEvent>>);
+
+impl AnalyticsSink for RecordingSink {
+    fn record(&self, event: &CommandEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn help_triggers_are_recognized() {
+    for text in ["help", "HELP", " ? ", "/help"] {
+        assert!(is_help(text), "{:?}", text);
+    }
+    for text in ["help me", "??", "md5 help"] {
+        assert!(!is_help(text), "{:?}", text);
+    }
+}
+
+#[test]
+fn help_lists_skills_and_docs() {
+    let text = help_text(&Yozuk::builder().build(), Lang::En);
+    assert!(text.starts_with("Available skills: "), "{}", text);
+    assert!(text.contains("base64"), "{}", text);
+    assert!(text.contains("https://"), "{}", text);
+}
+
+#[tokio::test]
+async fn help_does_not_run_commands() {
+    let sink = Arc::new(RecordingSink::default());
+    let config = BotConfig {
+        help: true,
+        ..BotConfig::new(
+            "test",
+            Limits {
+                analytics: sink.clone(),
+                ..Default::default()
+            },
+        )
+    };
+    let metrics = config.metrics.clone();
+    let transport = Arc::new(MemoryTransport::new([
+        Incoming::new("help"),
+        Incoming::new("/help"),
+    ]));
+    run_bot(
+        transport.clone(),
+        Arc::new(Yozuk::builder().build()),
+        config,
+    )
+    .await;
+
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 2);
+    assert!(sent
+        .iter()
+        .all(|(_, message)| matches!(message, RenderedMessage::Notice(_))));
+    assert!(sink.0.lock().unwrap().is_empty());
+    assert_eq!(metrics.stats().commands, 0);
+}
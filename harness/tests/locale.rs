@@ -0,0 +1,72 @@
+use yozuk_bot_core::{translate, Lang, LocaleOverride, LocaleOverrides};
+
+fn overrides() -> LocaleOverrides {
+    ["123=de", "alice@example.com=ja_JP"]
+        .iter()
+        .map(|s| s.parse::<LocaleOverride>().unwrap())
+        .collect()
+}
+
+#[test]
+fn message_hint_wins() {
+    assert_eq!(overrides().resolve(Some("ja"), ["123"], Lang::En), Lang::Ja);
+}
+
+#[test]
+fn unsupported_hints_are_ignored() {
+    assert_eq!(
+        overrides().resolve(Some("fr-FR"), ["123"], Lang::En),
+        Lang::De
+    );
+}
+
+#[test]
+fn channel_override_beats_default() {
+    assert_eq!(overrides().resolve(None, ["123"], Lang::En), Lang::De);
+    assert_eq!(
+        overrides().resolve(None, ["456", "alice@example.com"], Lang::En),
+        Lang::Ja
+    );
+    assert_eq!(
+        overrides().resolve(None, ["123", "alice@example.com"], Lang::En),
+        Lang::De
+    );
+}
+
+#[test]
+fn default_applies_without_override() {
+    assert_eq!(overrides().resolve(None, ["456"], Lang::De), Lang::De);
+    assert_eq!(
+        LocaleOverrides::default().resolve(None, ["123"], Lang::Ja),
+        Lang::Ja
+    );
+}
+
+#[test]
+fn invalid_overrides_are_rejected() {
+    assert!("123".parse::<LocaleOverride>().is_err());
+    assert!("123=fr".parse::<LocaleOverride>().is_err());
+}
+
+#[test]
+fn missing_keys_fall_back_to_english() {
+    let table = [("unrecognized", "Nicht verstanden.")];
+    assert_eq!(translate(&table, "unrecognized", &[]), "Nicht verstanden.");
+    assert_eq!(
+        translate(&table, "timed-out", &[]),
+        Lang::En.tr("timed-out", &[])
+    );
+    assert_eq!(
+        translate(&table, "archive-too-large", &[("size", "10")]),
+        Lang::En.tr("archive-too-large", &[("size", "10")])
+    );
+    assert!(!Lang::En
+        .tr("archive-too-large", &[("size", "10")])
+        .contains("{size}"));
+}
+
+#[test]
+fn unknown_keys_are_shown_as_they_are() {
+    assert_eq!(Lang::De.tr("no-such-key", &[]), "no-such-key");
+    assert_eq!(translate(&[], "no-such-key", &[]), "no-such-key");
+}
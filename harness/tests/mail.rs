@@ -0,0 +1,85 @@
+use yozuk_bot_core::{html_to_text, strip_forward_header, strip_reply, MailAccount};
+
+#[test]
+fn extracts_commands_from_html_mails() {
+    let cases = [
+        ("<p>uuid 3</p>", "uuid 3"),
+        (
+            "<html><head><title>x</title><style>p { color: red; }</style></head>\
+             <body><div dir=\"ltr\">1&nbsp;+&nbsp;2</div></body></html>",
+            "1 + 2",
+        ),
+        (
+            "<div>&quot;hello&quot; to md5</div><div><br></div>\
+             <div>-- <br>Alice Example<br>Example Corp</div>",
+            "\"hello\" to md5",
+        ),
+        (
+            "<div>base64 a&amp;b &#60;&#x3E;</div>\
+             <div class=\"gmail_quote\">On Mon, Bob wrote:<br>\
+             <blockquote>earlier <b>command</b></blockquote></div>",
+            "base64 a&b <>\nOn Mon, Bob wrote:",
+        ),
+        (
+            "<p>color\n    #ff0000</p>\n<p>&gt; quoted</p>",
+            "color #ff0000",
+        ),
+        ("<p>&unknown; &#1234567890;</p>", "&unknown; &#1234567890;"),
+    ];
+    for (html, expected) in cases {
+        assert_eq!(html_to_text(html), expected, "{:?}", html);
+    }
+}
+
+#[test]
+fn strips_quotes_and_signatures() {
+    assert_eq!(
+        strip_reply("uuid\n> previous\n>> older\n\n-- \nAlice"),
+        "uuid"
+    );
+    assert_eq!(strip_reply("1 + 2\n--\nsig"), "1 + 2");
+}
+
+#[test]
+fn strips_forward_headers() {
+    let cases = [
+        (
+            "---------- Forwarded message ---------\n\
+             From: Bob <bob@example.com>\n\
+             Date: Mon, 3 Oct 2022 at 10:00\n\
+             Subject: \n\
+             To: Alice <alice@example.com>\n\
+             \n\
+             Wie spät ist es?",
+            "Wie spät ist es?",
+        ),
+        (
+            "Begin forwarded message:\n\nFrom: Bob\nSubject: hi\n\naGVsbG8=",
+            "aGVsbG8=",
+        ),
+        ("---------- Forwarded message ---------\nFrom: Bob", ""),
+        ("From: the start\nto the end", "From: the start\nto the end"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(strip_forward_header(text), expected, "{:?}", text);
+    }
+}
+
+#[test]
+fn parses_mail_accounts() {
+    std::env::set_var("YOZUK_TEST_PASSWORD", "hunter2");
+    let account: MailAccount = "mail=bot@example.com, password_env=YOZUK_TEST_PASSWORD"
+        .parse()
+        .unwrap();
+    assert_eq!(account.mail, "bot@example.com");
+    assert_eq!(account.password, "hunter2");
+    assert!(!format!("{:?}", account).contains("hunter2"));
+
+    for invalid in [
+        "mail=bot@example.com",
+        "mail=bot@example.com,password=hunter2",
+        "mail=bot@example.com,password_env=YOZUK_TEST_UNSET",
+    ] {
+        assert!(invalid.parse::<MailAccount>().is_err(), "{:?}", invalid);
+    }
+}
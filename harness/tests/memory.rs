@@ -0,0 +1,76 @@
+use std::time::Duration;
+use yozuk::Yozuk;
+use yozuk_bot_core::{Attachment, ConversationMemory, Limits, PlanItem, PlanStatus};
+use yozuk_sdk::prelude::*;
+
+fn memory() -> ConversationMemory<&'static str> {
+    ConversationMemory::new(Duration::from_secs(60))
+}
+
+#[test]
+fn raw_flag_is_applied() {
+    let zuk = Yozuk::builder().build();
+    let plan = memory().plan(
+        "chat",
+        &zuk,
+        "aGVsbG8= base64 decode --raw",
+        vec![],
+        &UserContext::default(),
+        &Limits::default(),
+    );
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert!(
+        plan.items
+            .iter()
+            .any(|item| matches!(item, PlanItem::File { data, .. } if &data[..] == b"hello")),
+        "{:?}",
+        plan.items
+    );
+}
+
+fn run(memory: &ConversationMemory<&'static str>, chat: &'static str, text: &str, data: &[u8]) {
+    let zuk = Yozuk::builder().build();
+    let attachments = if data.is_empty() {
+        vec![]
+    } else {
+        vec![Attachment::new(
+            data.to_vec(),
+            media_type!(APPLICATION / OCTET_STREAM).into(),
+        )]
+    };
+    let plan = memory.plan(
+        chat,
+        &zuk,
+        text,
+        attachments,
+        &UserContext::default(),
+        &Limits::default(),
+    );
+    assert_eq!(plan.status, PlanStatus::Success, "{:?}", plan.items);
+}
+
+#[test]
+fn least_recently_used_chats_are_dropped_over_the_byte_budget() {
+    let memory = memory().with_max_bytes(16);
+    run(&memory, "a", "md5", b"12345678");
+    run(&memory, "b", "md5", b"12345678");
+    assert_eq!(memory.len(), 2);
+    run(&memory, "c", "md5", b"12345678");
+    assert_eq!(memory.len(), 2);
+}
+
+#[test]
+fn attachments_over_the_budget_are_not_remembered() {
+    let memory = memory().with_max_bytes(4);
+    run(&memory, "a", "md5", b"12345678");
+    assert!(memory.is_empty());
+}
+
+#[test]
+fn long_texts_are_not_remembered() {
+    let memory = memory();
+    run(&memory, "a", &format!("{} to md5", "a".repeat(3000)), b"");
+    assert!(memory.is_empty());
+    run(&memory, "a", "aGVsbG8= base64 decode", b"");
+    assert_eq!(memory.len(), 1);
+}
@@ -0,0 +1,78 @@
+use yozuk_bot_core::{plan_outputs, set_mode, ChatMode, Lang, Limits, PlanItem, Preferences};
+use yozuk_sdk::prelude::*;
+
+fn outputs() -> Vec<Output> {
+    vec![
+        Output::new()
+            .add_block(block::Comment::new().set_text("Decoded:"))
+            .add_block(block::Data::new().set_text_data("hello")),
+        Output::new().add_block(block::Comment::new().set_text("No data here")),
+    ]
+}
+
+#[test]
+fn quiet_drops_comments_next_to_data() {
+    let limits = Limits {
+        quiet: true,
+        ..Default::default()
+    };
+    let plan = plan_outputs(outputs(), &limits, Lang::En);
+    assert_eq!(
+        plan.items,
+        vec![
+            PlanItem::CodeBlock {
+                lang: None,
+                text: "hello".into()
+            },
+            PlanItem::Text("No data here".into()),
+        ]
+    );
+}
+
+#[test]
+fn verbose_keeps_comments() {
+    let plan = plan_outputs(outputs(), &Limits::default(), Lang::En);
+    assert_eq!(plan.items.len(), 3);
+    assert_eq!(plan.items[0], PlanItem::Text("Decoded:".into()));
+}
+
+#[test]
+fn modes_are_stored_per_chat() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Preferences::open(dir.path()).unwrap();
+
+    assert_eq!(
+        set_mode(&store, "test", "1", "mode quiet", Lang::En)
+            .unwrap()
+            .unwrap(),
+        "Output mode set to quiet."
+    );
+    assert_eq!(
+        ChatMode::load(&store, "test", "1").unwrap(),
+        ChatMode::Quiet
+    );
+    assert_eq!(
+        ChatMode::load(&store, "test", "2").unwrap(),
+        ChatMode::Verbose
+    );
+
+    set_mode(&store, "test", "1", "Mode VERBOSE", Lang::En)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        ChatMode::load(&store, "test", "1").unwrap(),
+        ChatMode::Verbose
+    );
+}
+
+#[test]
+fn unknown_modes_list_the_valid_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Preferences::open(dir.path()).unwrap();
+    let reply = set_mode(&store, "test", "1", "mode loud", Lang::En)
+        .unwrap()
+        .unwrap();
+    assert_eq!(reply, "Unknown mode \"loud\". Valid modes: quiet, verbose");
+    assert!(set_mode(&store, "test", "1", "mode", Lang::En).is_none());
+    assert!(set_mode(&store, "test", "1", "quiet mode", Lang::En).is_none());
+}
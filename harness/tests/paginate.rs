@@ -0,0 +1,175 @@
+use yozuk_bot_core::{paginate, PageLimits};
+
+const INPUTS: &[&str] = &[
+    "short",
+    "The quick brown fox jumps over the lazy dog.",
+    "line one\nline two\nline three\nline four",
+    "```\nfn main() {\n    println!(\"hello\");\n}\n```",
+    "before\n```json\n{\"a\": 1,\n \"b\": [1, 2, 3],\n \"c\": \"value\"}\n```\nafter",
+    "日本語のテキストを長く書いてみる。改行なしで続く文章です。",
+    "emoji 😀😃😄😁😆😅 mixed with text 🎉 and more words",
+    "Ünïcödé wörds wïth äccents spread across many wörds",
+    "averyveryveryverylongwordwithoutanyspacesatallthatmustbesplitsomewhere",
+    "```\n日本語😀日本語😀日本語😀日本語😀\n```",
+];
+
+fn fences_balanced(page: &str) -> bool {
+    page.lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 0
+}
+
+fn strip_marker(page: &str, total: usize) -> &str {
+    if total > 1 {
+        page.rsplit_once('\n').map_or("", |(body, _)| body)
+    } else {
+        page
+    }
+}
+
+fn words(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .flat_map(str::split_whitespace)
+        .collect()
+}
+
+fn check(input: &str, limits: &PageLimits) {
+    let pages = paginate([input], limits);
+    let total = pages.len();
+    for (index, page) in pages.iter().enumerate() {
+        let context = format!("{:?} {:?} page {}: {:?}", input, limits, index, page);
+        if let Some(max) = limits.max_chars {
+            assert!(page.chars().count() <= max, "{}", context);
+        }
+        if let Some(max) = limits.max_bytes {
+            assert!(page.len() <= max, "{}", context);
+        }
+        if limits.fence_aware {
+            assert!(fences_balanced(page), "{}", context);
+        }
+        if total > 1 {
+            assert!(
+                page.ends_with(&format!("({}/{})", index + 1, total)),
+                "{}",
+                context
+            );
+        }
+    }
+    // Nothing is lost or duplicated apart from whitespace at the split points.
+    let joined = pages
+        .iter()
+        .map(|page| strip_marker(page, total))
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_eq!(words(&joined), words(input), "{:?} {:?}", input, limits);
+}
+
+#[test]
+fn pages_respect_limits() {
+    for input in INPUTS {
+        for max in 24..=80 {
+            for prefer_line_breaks in [true, false] {
+                check(
+                    input,
+                    &PageLimits {
+                        max_chars: Some(max),
+                        prefer_line_breaks,
+                        ..Default::default()
+                    },
+                );
+                check(
+                    input,
+                    &PageLimits {
+                        max_bytes: Some(max),
+                        prefer_line_breaks,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn single_page_is_unchanged() {
+    let limits = PageLimits {
+        max_chars: Some(2000),
+        ..Default::default()
+    };
+    for input in INPUTS {
+        assert_eq!(paginate([input], &limits), [*input]);
+    }
+    assert_eq!(paginate(Vec::<String>::new(), &limits), [""]);
+}
+
+#[test]
+fn parts_are_kept_together() {
+    let limits = PageLimits {
+        max_chars: Some(30),
+        ..Default::default()
+    };
+    assert_eq!(
+        paginate(["first part", "second part", "third part is long"], &limits),
+        [
+            "first part\nsecond part\n(1/2)",
+            "third part is long\n(2/2)"
+        ]
+    );
+}
+
+#[test]
+fn fences_are_reopened() {
+    let limits = PageLimits {
+        max_chars: Some(24),
+        ..Default::default()
+    };
+    assert_eq!(
+        paginate(["```json\n[1,\n2,\n3,\n4,\n5,\n6]\n```"], &limits),
+        [
+            "```json\n[1,\n2,\n```\n(1/3)",
+            "```json\n3,\n4,\n```\n(2/3)",
+            "```json\n5,\n6]\n```\n(3/3)"
+        ]
+    );
+}
+
+#[test]
+fn fences_are_ignored_unless_requested() {
+    let limits = PageLimits {
+        max_chars: Some(20),
+        fence_aware: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        paginate(["```\naaaa\nbbbb\ncccc\n```"], &limits),
+        ["```\naaaa\nbbbb\n(1/2)", "cccc\n```\n(2/2)"]
+    );
+}
+
+#[test]
+fn multi_byte_characters_are_not_split() {
+    let limits = PageLimits {
+        max_bytes: Some(10),
+        ..Default::default()
+    };
+    assert_eq!(
+        paginate(["あいうえ"], &limits),
+        ["あ\n(1/4)", "い\n(2/4)", "う\n(3/4)", "え\n(4/4)"]
+    );
+}
+
+#[test]
+fn words_are_kept_together() {
+    let limits = PageLimits {
+        max_chars: Some(24),
+        prefer_line_breaks: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        paginate(["alpha beta gamma delta epsilon"], &limits),
+        ["alpha beta gamma\n(1/2)", "delta epsilon\n(2/2)"]
+    );
+}
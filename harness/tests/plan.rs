@@ -0,0 +1,73 @@
+use yozuk::Yozuk;
+use yozuk_bot_core::{plan_response, Limits, PlanItem, PlanStatus, ResponsePlan, TextPolicy};
+use yozuk_sdk::prelude::*;
+
+fn plan(text: &str, limits: &Limits) -> ResponsePlan {
+    let zuk = Yozuk::builder().build();
+    plan_response(&zuk, text, vec![], &UserContext::default(), limits)
+}
+
+#[test]
+fn comments_are_planned_as_text() {
+    let plan = plan("hello", &Limits::default());
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert!(matches!(plan.items[..], [PlanItem::Text(_)]));
+}
+
+#[test]
+fn text_data_is_planned_as_code_block() {
+    let plan = plan("aGVsbG8= base64 decode", &Limits::default());
+    assert_eq!(plan.status, PlanStatus::Success);
+    assert_eq!(plan.command.as_deref(), Some("yozuk-skill-base64"));
+    assert_eq!(
+        plan.items,
+        vec![PlanItem::CodeBlock {
+            lang: None,
+            text: "hello".into(),
+        }]
+    );
+}
+
+#[test]
+fn data_is_planned_as_file_if_the_policy_says_so() {
+    let limits = Limits {
+        text_policy: TextPolicy::AlwaysFile,
+        ..Default::default()
+    };
+    let plan = plan("aGVsbG8= base64 decode", &limits);
+    match &plan.items[..] {
+        [PlanItem::File { data, .. }] => assert_eq!(&data[..], b"hello"),
+        other => panic!("unexpected items: {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_commands_are_planned_as_apology() {
+    let plan = plan("zzzqqq xyz", &Limits::default());
+    assert_eq!(plan.status, PlanStatus::Unrecognized);
+    assert_eq!(plan.command, None);
+    assert!(matches!(plan.items[..], [PlanItem::Apology { .. }]));
+}
+
+#[test]
+fn outputs_are_planned_as_sections_if_enabled() {
+    let limits = Limits {
+        sections: true,
+        ..Default::default()
+    };
+    let titles = plan("1660000000", &limits)
+        .items
+        .into_iter()
+        .filter_map(|item| match item {
+            PlanItem::Section { title } => Some(title),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Timestamp Converter", "Base Converter"]);
+
+    let plan = plan("1660000000", &Limits::default());
+    assert!(plan
+        .items
+        .iter()
+        .all(|item| !matches!(item, PlanItem::Section { .. })));
+}
@@ -0,0 +1,124 @@
+use rusqlite::Connection;
+use std::fs;
+use std::path::Path;
+use yozuk_bot_core::{Preferences, UserPrefs};
+
+const DB_FILE: &str = "preferences.sqlite";
+
+/// Schema after the first migration, before chat values were added.
+const SCHEMA_1: &str = r#"
+    CREATE TABLE users (
+        platform TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        locale TEXT,
+        timezone TEXT,
+        PRIMARY KEY (platform, user_id)
+    );
+    CREATE TABLE user_values (
+        platform TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (platform, user_id, key)
+    );
+    INSERT INTO users VALUES ('test', 'alice', 'de', NULL);
+    PRAGMA user_version = 1;
+"#;
+
+fn user_version(dir: &Path) -> usize {
+    let conn = Connection::open(dir.join(DB_FILE)).unwrap();
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn new_databases_are_migrated_from_version_0() {
+    let dir = tempfile::tempdir().unwrap();
+    Connection::open(dir.path().join(DB_FILE)).unwrap();
+    assert_eq!(user_version(dir.path()), 0);
+
+    let store = Preferences::open(dir.path()).unwrap();
+    store
+        .set_chat_value("test", "chat", "mode", "quiet")
+        .unwrap();
+    assert_eq!(
+        store.chat_value("test", "chat", "mode").unwrap().as_deref(),
+        Some("quiet")
+    );
+    drop(store);
+    assert_eq!(user_version(dir.path()), 2);
+}
+
+#[test]
+fn older_databases_keep_their_data() {
+    let dir = tempfile::tempdir().unwrap();
+    Connection::open(dir.path().join(DB_FILE))
+        .unwrap()
+        .execute_batch(SCHEMA_1)
+        .unwrap();
+
+    let store = Preferences::open(dir.path()).unwrap();
+    assert_eq!(
+        store.get("test", "alice").unwrap(),
+        UserPrefs {
+            locale: Some("de".into()),
+            ..Default::default()
+        }
+    );
+    assert_eq!(store.chat_value("test", "chat", "mode").unwrap(), None);
+    drop(store);
+    assert_eq!(user_version(dir.path()), 2);
+}
+
+#[test]
+fn reopening_does_not_migrate_again() {
+    let dir = tempfile::tempdir().unwrap();
+    Preferences::open(dir.path()).unwrap();
+    Preferences::open(dir.path()).unwrap();
+    assert_eq!(user_version(dir.path()), 2);
+}
+
+#[test]
+fn newer_databases_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    Connection::open(dir.path().join(DB_FILE))
+        .unwrap()
+        .pragma_update(None, "user_version", 99)
+        .unwrap();
+    let err = Preferences::open(dir.path()).err().unwrap().to_string();
+    assert!(err.contains("newer version"), "{}", err);
+}
+
+#[test]
+fn corrupt_databases_are_reported_and_left_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(DB_FILE);
+    let garbage = b"this is not a database, just some bytes".repeat(200);
+    fs::write(&path, &garbage).unwrap();
+
+    let err = Preferences::open(dir.path()).err().unwrap().to_string();
+    assert!(err.contains("is corrupt"), "{}", err);
+    assert_eq!(fs::read(&path).unwrap(), garbage);
+}
+
+#[test]
+fn damaged_pages_are_detected() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Preferences::open(dir.path()).unwrap();
+    for index in 0..200 {
+        let chat = format!("chat{}", index);
+        store
+            .set_chat_value("test", &chat, "mode", "quiet")
+            .unwrap();
+    }
+    drop(store);
+
+    let path = dir.path().join(DB_FILE);
+    let mut data = fs::read(&path).unwrap();
+    let page_size = u16::from_be_bytes([data[16], data[17]]) as usize;
+    data[page_size * 2..page_size * 3].fill(0xff);
+    fs::write(&path, &data).unwrap();
+
+    let err = Preferences::open(dir.path()).err().unwrap().to_string();
+    assert!(err.contains("is corrupt"), "{}", err);
+}
@@ -0,0 +1,91 @@
+use yozuk_bot_core::{preview_text, Preprocessor, Step};
+
+#[test]
+fn cleans_up_input() {
+    let preprocessor = Preprocessor::default().with_mentions(r"<@\d+>").unwrap();
+    let cases = [
+        ("<@1234> 1 + 2", "1 + 2"),
+        ("1   +\t2  ", "1 + 2"),
+        ("md5\u{200B} hello", "md5 hello"),
+        ("please, 1 + 2", "1 + 2"),
+        ("1 + 2 please", "1 + 2"),
+        ("> !! uuid", "uuid"),
+        ("?", "?"),
+        (" !! ", "!!"),
+        ("\u{201C}hello\u{201D} to md5", "\"hello\" to md5"),
+        ("\u{2018}hi\u{2019} \u{2014} \u{2212}1", "'hi' - -1"),
+        ("```hello```", "hello"),
+        ("```json\n{\"a\":  1}\n```", "{\"a\": 1}"),
+        ("```\n  indented\n```", "indented"),
+        ("```a``` and ```b```", "```a``` and ```b```"),
+        ("<@1> please <@2>", ""),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(preprocessor.process(input).text, expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn keeps_other_scripts_untouched() {
+    let preprocessor = Preprocessor::default();
+    let cases = [
+        "👍",
+        "🎉🎉🎉",
+        "👨\u{200D}👩\u{200D}👧",
+        "🏳\u{FE0F}\u{200D}🌈",
+        "مرحبا بالعالم",
+        "\u{200F}שלום עולם",
+        "می\u{200C}خواهم",
+        "こんにちは、世界",
+    ];
+    for input in cases {
+        let result = preprocessor.process(input);
+        assert_eq!(result.text, input);
+        assert!(result.removed.is_empty(), "{:?}", input);
+    }
+}
+
+#[test]
+fn reports_removed_text() {
+    let preprocessor = Preprocessor::default().with_mentions(r"<@\d+>").unwrap();
+    let result = preprocessor.process("<@42> please \u{201C}a\u{201D}\u{200B}");
+    assert_eq!(result.text, "\"a\"");
+    assert_eq!(
+        result.removed,
+        [
+            (Step::StripMentions, "<@42>".to_string()),
+            (Step::NormalizePunctuation, "\u{201C}".to_string()),
+            (Step::NormalizePunctuation, "\u{201D}".to_string()),
+            (Step::CollapseWhitespace, "\\u{200b}".to_string()),
+            (Step::TrimFiller, "please".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn runs_only_the_given_steps() {
+    let preprocessor = Preprocessor::new(vec![Step::CollapseWhitespace]);
+    assert_eq!(
+        preprocessor.process("please  \u{201C}x\u{201D}").text,
+        "please \u{201C}x\u{201D}"
+    );
+}
+
+#[test]
+fn link_previews_are_joined() {
+    let link_only = [(Some("https://example.com/"), None)];
+    assert_eq!(preview_text(link_only), "https://example.com/");
+
+    let embed_only = [(None, Some("aGVsbG8= base64 decode"))];
+    assert_eq!(preview_text(embed_only), "aGVsbG8= base64 decode");
+
+    let both = [
+        (Some("https://example.com/"), Some("Example Domain")),
+        (Some("https://example.org/"), None),
+    ];
+    assert_eq!(
+        preview_text(both),
+        "https://example.com/\nExample Domain\nhttps://example.org/"
+    );
+    assert_eq!(preview_text([]), "");
+}
@@ -0,0 +1,40 @@
+use anyhow::anyhow;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use yozuk_bot_core::Progress;
+
+const DELAY: Duration = Duration::from_millis(50);
+
+fn start(sent: &Arc<AtomicU32>) -> Progress<u32> {
+    let sent = sent.clone();
+    Progress::start(DELAY, move || async move {
+        Ok(sent.fetch_add(1, Ordering::SeqCst) + 1)
+    })
+}
+
+#[tokio::test]
+async fn fast_commands_send_nothing() {
+    let sent = Arc::new(AtomicU32::new(0));
+    let progress = start(&sent);
+    assert_eq!(progress.finish().await, None);
+    tokio::time::sleep(DELAY * 2).await;
+    assert_eq!(sent.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn slow_commands_return_the_placeholder() {
+    let sent = Arc::new(AtomicU32::new(0));
+    let progress = start(&sent);
+    tokio::time::sleep(DELAY * 2).await;
+    assert_eq!(progress.finish().await, Some(1));
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn failed_placeholders_are_ignored() {
+    let progress: Progress<u32> =
+        Progress::start(Duration::ZERO, || async { Err(anyhow!("offline")) });
+    tokio::time::sleep(DELAY).await;
+    assert_eq!(progress.finish().await, None);
+}
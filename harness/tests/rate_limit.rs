@@ -0,0 +1,60 @@
+use std::thread::sleep;
+use std::time::Duration;
+use yozuk_bot_core::{Decision, RateLimit, RateLimiter};
+
+const PERIOD: Duration = Duration::from_millis(200);
+
+fn limiter(capacity: u32) -> RateLimiter<&'static str> {
+    RateLimiter::new(RateLimit {
+        capacity,
+        period: PERIOD,
+    })
+}
+
+#[test]
+fn senders_are_limited_after_their_capacity() {
+    let limiter = limiter(2);
+    assert_eq!(limiter.check("alice"), Decision::Allowed);
+    assert_eq!(limiter.check("alice"), Decision::Allowed);
+    match limiter.check("alice") {
+        Decision::Limited { retry_after } => assert!(retry_after <= PERIOD / 2),
+        Decision::Allowed => panic!("the third request was allowed"),
+    }
+    assert_eq!(limiter.check("bob"), Decision::Allowed);
+}
+
+#[test]
+fn buckets_are_refilled_over_time() {
+    let limiter = limiter(2);
+    limiter.check("alice");
+    limiter.check("alice");
+    assert!(matches!(limiter.check("alice"), Decision::Limited { .. }));
+    sleep(PERIOD / 2 + Duration::from_millis(20));
+    assert_eq!(limiter.check("alice"), Decision::Allowed);
+    assert!(matches!(limiter.check("alice"), Decision::Limited { .. }));
+}
+
+#[test]
+fn full_buckets_are_evicted() {
+    let limiter = limiter(1);
+    limiter.check("alice");
+    limiter.check("bob");
+    assert_eq!(limiter.len(), 2);
+    sleep(PERIOD + Duration::from_millis(20));
+    limiter.check("carol");
+    assert_eq!(limiter.len(), 1);
+}
+
+#[test]
+fn limits_are_parsed() {
+    assert_eq!(
+        "5/60".parse::<RateLimit>().unwrap(),
+        RateLimit {
+            capacity: 5,
+            period: Duration::from_secs(60),
+        }
+    );
+    for invalid in ["5", "0/60", "5/0", "five/60"] {
+        assert!(invalid.parse::<RateLimit>().is_err(), "{:?}", invalid);
+    }
+}
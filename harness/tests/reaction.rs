@@ -0,0 +1,52 @@
+use yozuk_bot_core::{PlanStatus, Reaction, ReactionUpdate};
+
+#[test]
+fn requests_are_marked_pending_first() {
+    assert_eq!(
+        Reaction::update(None, Reaction::Pending),
+        ReactionUpdate {
+            remove: None,
+            add: Some(Reaction::Pending),
+        }
+    );
+}
+
+#[test]
+fn pending_is_replaced_with_the_outcome() {
+    assert_eq!(
+        Reaction::update(Some(Reaction::Pending), Reaction::Done),
+        ReactionUpdate {
+            remove: Some(Reaction::Pending),
+            add: Some(Reaction::Done),
+        }
+    );
+    assert_eq!(
+        Reaction::update(Some(Reaction::Pending), Reaction::Failed),
+        ReactionUpdate {
+            remove: Some(Reaction::Pending),
+            add: Some(Reaction::Failed),
+        }
+    );
+}
+
+#[test]
+fn unchanged_reactions_are_kept() {
+    assert_eq!(
+        Reaction::update(Some(Reaction::Done), Reaction::Done),
+        ReactionUpdate::default()
+    );
+}
+
+#[test]
+fn only_success_is_done() {
+    assert_eq!(Reaction::outcome(Some(PlanStatus::Success)), Reaction::Done);
+    assert_eq!(
+        Reaction::outcome(Some(PlanStatus::Failure)),
+        Reaction::Failed
+    );
+    assert_eq!(
+        Reaction::outcome(Some(PlanStatus::Unrecognized)),
+        Reaction::Failed
+    );
+    assert_eq!(Reaction::outcome(None), Reaction::Failed);
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+use yozuk_bot_core::{
+    cleanup_scanned, next_cleanup_page, plan_cleanup, set_cleanup_scanned, set_retention, Cleanup,
+    OwnMessage, Preferences, Retention,
+};
+
+const HOUR: Duration = Duration::from_secs(3600);
+const DAY: Duration = Duration::from_secs(86400);
+
+fn message(id: usize, age: Duration) -> OwnMessage<usize> {
+    OwnMessage {
+        id,
+        age,
+        pinned: false,
+    }
+}
+
+#[test]
+fn parses_retentions() {
+    assert_eq!("30m".parse::<Retention>().unwrap(), Retention(HOUR / 2));
+    assert_eq!("24h".parse::<Retention>().unwrap(), Retention(DAY));
+    assert_eq!("7d".parse::<Retention>().unwrap(), Retention(DAY * 7));
+    for invalid in ["", "24", "0h", "h", "1w", "-1d", "1.5h"] {
+        assert!(invalid.parse::<Retention>().is_err(), "{:?}", invalid);
+    }
+}
+
+#[test]
+fn displays_the_largest_whole_unit() {
+    assert_eq!(Retention(DAY).to_string(), "1d");
+    assert_eq!(Retention(HOUR * 36).to_string(), "36h");
+    assert_eq!(Retention(HOUR / 2).to_string(), "30m");
+}
+
+#[test]
+fn retentions_are_stored_per_channel() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Preferences::open(dir.path()).unwrap();
+    set_retention(&store, "test", "1", Some(Retention(DAY))).unwrap();
+    set_retention(&store, "test", "2", Some(Retention(HOUR))).unwrap();
+    assert_eq!(
+        Retention::load(&store, "test", "1").unwrap(),
+        Some(Retention(DAY))
+    );
+    assert_eq!(
+        Retention::all(&store, "test").unwrap(),
+        vec![("1".into(), Retention(DAY)), ("2".into(), Retention(HOUR))]
+    );
+
+    set_retention(&store, "test", "1", None).unwrap();
+    assert_eq!(Retention::load(&store, "test", "1").unwrap(), None);
+    assert_eq!(Retention::all(&store, "test").unwrap().len(), 1);
+}
+
+#[test]
+fn keeps_recent_and_pinned_messages() {
+    let messages = vec![
+        message(1, HOUR),
+        OwnMessage {
+            pinned: true,
+            ..message(2, DAY * 2)
+        },
+        message(3, DAY * 2),
+        message(4, DAY * 3),
+    ];
+    assert_eq!(
+        plan_cleanup(messages, Retention(DAY)),
+        Cleanup {
+            bulk: vec![vec![3, 4]],
+            single: vec![],
+        }
+    );
+}
+
+#[test]
+fn old_messages_are_deleted_one_by_one() {
+    let messages = vec![message(1, DAY * 2), message(2, DAY * 20)];
+    assert_eq!(
+        plan_cleanup(messages, Retention(DAY)),
+        Cleanup {
+            bulk: vec![],
+            // A batch of one is moved to the end.
+            single: vec![2, 1],
+        }
+    );
+}
+
+#[test]
+fn bulk_deletes_are_batched() {
+    let messages = (0..201).map(|id| message(id, DAY * 2));
+    let cleanup = plan_cleanup(messages, Retention(DAY));
+    assert_eq!(cleanup.bulk.len(), 2);
+    assert!(cleanup.bulk.iter().all(|batch| batch.len() == 100));
+    assert_eq!(cleanup.single, vec![200]);
+    assert_eq!(cleanup.len(), 201);
+}
+
+#[test]
+fn cleanup_positions_are_stored_per_channel() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Preferences::open(dir.path()).unwrap();
+    assert_eq!(cleanup_scanned(&store, "test", "1").unwrap(), None);
+    set_cleanup_scanned(&store, "test", "1", 42).unwrap();
+    set_cleanup_scanned(&store, "test", "1", 1234).unwrap();
+    assert_eq!(cleanup_scanned(&store, "test", "1").unwrap(), Some(1234));
+    assert_eq!(cleanup_scanned(&store, "test", "2").unwrap(), None);
+}
+
+#[test]
+fn cleanup_pages_until_the_cutoff() {
+    // A full page older than the cutoff continues after its newest message.
+    assert_eq!(next_cleanup_page(&[30, 20, 10], 3, 100), Some(30));
+    // A page that isn't full is the end of the channel.
+    assert_eq!(next_cleanup_page(&[20, 10], 3, 100), None);
+    assert_eq!(next_cleanup_page(&[], 3, 100), None);
+    // Messages from the cutoff on are too young to delete.
+    assert_eq!(next_cleanup_page(&[120, 90, 80], 3, 100), None);
+}
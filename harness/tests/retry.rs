@@ -0,0 +1,80 @@
+use anyhow::anyhow;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use yozuk_bot_core::{retry, RetryPolicy};
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+        ..Default::default()
+    }
+}
+
+/// A fake send that fails the first `failures` times it is called.
+struct FlakySend {
+    failures: u32,
+    calls: AtomicU32,
+}
+
+impl FlakySend {
+    fn new(failures: u32) -> Self {
+        Self {
+            failures,
+            calls: AtomicU32::new(0),
+        }
+    }
+
+    async fn send(&self, text: &str) -> anyhow::Result<String> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if call <= self.failures {
+            Err(anyhow!("connection reset"))
+        } else {
+            Ok(text.into())
+        }
+    }
+
+    fn calls(&self) -> u32 {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let fake = FlakySend::new(1);
+    let sent = retry(&policy(), || fake.send("hello")).await.unwrap();
+    assert_eq!(sent, "hello");
+    assert_eq!(fake.calls(), 2);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let fake = FlakySend::new(u32::MAX);
+    let err = retry(&policy(), || fake.send("hello")).await.unwrap_err();
+    assert_eq!(err.to_string(), "connection reset");
+    assert_eq!(fake.calls(), 3);
+}
+
+#[tokio::test]
+async fn permanent_failures_are_not_retried() {
+    let fake = FlakySend::new(1);
+    let policy = policy().with_classifier(|_| false);
+    assert!(retry(&policy, || fake.send("hello")).await.is_err());
+    assert_eq!(fake.calls(), 1);
+}
+
+#[tokio::test]
+async fn slow_attempts_time_out() {
+    let policy = RetryPolicy {
+        max_attempts: 1,
+        timeout: Some(Duration::from_millis(10)),
+        ..policy()
+    };
+    let err = retry(&policy, || async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok::<_, anyhow::Error>(())
+    })
+    .await
+    .unwrap_err();
+    assert!(err.to_string().starts_with("timed out"), "{}", err);
+}
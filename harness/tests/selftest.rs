@@ -0,0 +1,16 @@
+use yozuk::Yozuk;
+use yozuk_bot_core::{selftest, EngineOptions};
+
+#[test]
+fn selftest_passes_with_default_engine() {
+    selftest(&Yozuk::builder().build()).unwrap();
+}
+
+#[test]
+fn selftest_passes_with_engine_options() {
+    let engine = EngineOptions {
+        timezone: Some("Europe/Berlin".parse().unwrap()),
+        ..Default::default()
+    };
+    selftest(&engine.build()).unwrap();
+}
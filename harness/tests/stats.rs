@@ -0,0 +1,71 @@
+use std::time::Duration;
+use yozuk_bot_core::{Metrics, PlanStatus, RateLimiter, ResponsePlan};
+
+fn plan(command: Option<&str>, status: PlanStatus, millis: u64) -> ResponsePlan {
+    ResponsePlan {
+        command: command.map(Into::into),
+        status,
+        duration: Duration::from_millis(millis),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn stats_start_empty() {
+    let stats = Metrics::new("test").stats();
+    assert_eq!(stats.messages, 0);
+    assert_eq!(stats.commands, 0);
+    assert_eq!(stats.mean_latency, None);
+    assert_eq!(stats.p95_latency, None);
+}
+
+#[test]
+fn stats_read_the_counters() {
+    let metrics = Metrics::new("test");
+    for _ in 0..4 {
+        metrics.message_received();
+    }
+    metrics.record(&plan(Some("calc"), PlanStatus::Success, 1));
+    metrics.record(&plan(Some("calc"), PlanStatus::Failure, 1));
+    metrics.record(&plan(Some("digest"), PlanStatus::Success, 2));
+    metrics.record(&plan(None, PlanStatus::Unrecognized, 0));
+
+    let stats = metrics.stats();
+    assert_eq!(stats.messages, 4);
+    assert_eq!(stats.commands, 3);
+    assert_eq!(stats.command_errors, 1);
+    assert_eq!(stats.unrecognized, 1);
+    let mean = stats.mean_latency.unwrap();
+    assert!(mean > Duration::from_millis(1) && mean < Duration::from_millis(2));
+}
+
+#[test]
+fn p95_latency_is_a_bucket_bound() {
+    let metrics = Metrics::new("test");
+    for _ in 0..19 {
+        metrics.record(&plan(Some("calc"), PlanStatus::Success, 1));
+    }
+    metrics.record(&plan(Some("calc"), PlanStatus::Success, 200));
+    assert_eq!(metrics.stats().p95_latency, Some(Duration::from_millis(5)));
+
+    metrics.record(&plan(Some("calc"), PlanStatus::Success, 200));
+    assert_eq!(
+        metrics.stats().p95_latency,
+        Some(Duration::from_millis(250))
+    );
+
+    // Beyond the largest bucket.
+    let metrics = Metrics::new("test");
+    metrics.record(&plan(Some("calc"), PlanStatus::Success, 60_000));
+    assert_eq!(metrics.stats().p95_latency, Some(Duration::from_secs(10)));
+}
+
+#[test]
+fn rate_limiters_count_their_senders() {
+    let limiter = RateLimiter::new("5/60".parse().unwrap());
+    assert!(limiter.is_empty());
+    limiter.check("alice");
+    limiter.check("bob");
+    limiter.check("alice");
+    assert_eq!(limiter.len(), 2);
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use yozuk_bot_core::{Attachment, Limits, PlanStatus};
+use yozuk_bot_harness::{render, Incoming, MockTransport};
+use yozuk_sdk::prelude::*;
+
+fn receive(attachment: Attachment) -> (PlanStatus, String) {
+    let mut transport = MockTransport::new(Limits::default());
+    let mut msg = Incoming::new("base64");
+    msg.attachments.push(attachment);
+    let plan = transport.receive(msg);
+    (plan.status, render(&plan.items))
+}
+
+#[test]
+fn streamed_files_match_buffered_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.bin");
+    fs::write(&path, "/wD+").unwrap();
+
+    let buffered = receive(Attachment::new("/wD+", media_type!(TEXT / PLAIN).into()));
+    let streamed = Attachment::from_file(&path, media_type!(TEXT / PLAIN).into());
+    assert!(streamed.is_streamed());
+    assert_eq!(receive(streamed), buffered);
+}
+
+#[test]
+fn files_are_opened_for_every_stream() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.bin");
+    fs::write(&path, "/wD+").unwrap();
+
+    let attachment = Attachment::from_file(&path, media_type!(TEXT / PLAIN).into());
+    let first = receive(attachment.clone());
+    assert_eq!(receive(attachment), first);
+}
+
+#[test]
+fn missing_files_read_as_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let attachment = Attachment::from_file(
+        dir.path().join("missing.bin"),
+        media_type!(TEXT / PLAIN).into(),
+    );
+    let (_, items) = receive(attachment);
+    assert!(!items.contains("L3dEKw=="));
+}
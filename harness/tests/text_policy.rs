@@ -0,0 +1,54 @@
+use yozuk_bot_core::{plan_outputs, Lang, Limits, PlanItem, TextPolicy};
+use yozuk_sdk::prelude::*;
+
+fn plan(text: &str, text_policy: TextPolicy) -> Vec<PlanItem> {
+    let output = Output::new().add_block(block::Data::new().set_text_data(text));
+    let limits = Limits {
+        text_policy,
+        ..Default::default()
+    };
+    plan_outputs(vec![output], &limits, Lang::En).items
+}
+
+#[test]
+fn text_up_to_the_threshold_is_inlined() {
+    assert!(matches!(
+        &plan("12345", TextPolicy::Threshold(5))[..],
+        [PlanItem::CodeBlock { text, .. }] if text == "12345"
+    ));
+}
+
+#[test]
+fn text_over_the_threshold_is_sent_as_file() {
+    assert!(matches!(
+        &plan("123456", TextPolicy::Threshold(5))[..],
+        [PlanItem::File { data, .. }] if &data[..] == b"123456"
+    ));
+}
+
+#[test]
+fn fixed_policies_ignore_the_length() {
+    assert!(TextPolicy::AlwaysInline.is_inline(usize::MAX));
+    assert!(!TextPolicy::AlwaysFile.is_inline(0));
+    assert!(matches!(
+        &plan("1", TextPolicy::AlwaysFile)[..],
+        [PlanItem::File { .. }]
+    ));
+}
+
+#[test]
+fn policies_are_parsed() {
+    assert_eq!(
+        "file".parse::<TextPolicy>().unwrap(),
+        TextPolicy::AlwaysFile
+    );
+    assert_eq!(
+        "inline".parse::<TextPolicy>().unwrap(),
+        TextPolicy::AlwaysInline
+    );
+    assert_eq!(
+        "1024".parse::<TextPolicy>().unwrap(),
+        TextPolicy::Threshold(1024)
+    );
+    assert!("sometimes".parse::<TextPolicy>().is_err());
+}
@@ -0,0 +1,247 @@
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    load_or_skip, run_bot, unix_time, AnalyticsSink, BotConfig, CommandEvent, Limits, PlanItem,
+    PlanStatus, Preferences, Preprocessor, RateLimiter, Redactor, RenderedMessage,
+};
+use yozuk_bot_harness::{Incoming, MemoryTransport};
+use yozuk_sdk::prelude::*;
+
+async fn run<I>(config: BotConfig, messages: I) -> Vec<(usize, RenderedMessage)>
+where
+    I: IntoIterator<Item = Incoming>,
+{
+    let transport = Arc::new(MemoryTransport::new(messages));
+    let zuk = Arc::new(Yozuk::builder().build());
+    run_bot(transport.clone(), zuk, config).await;
+    let mut sent = transport.sent();
+    sent.sort_by_key(|(index, _)| *index);
+    sent
+}
+
+fn config() -> BotConfig {
+    BotConfig::new("test", Limits::default())
+}
+
+#[tokio::test]
+async fn commands_are_answered() {
+    let sent = run(config(), [Incoming::new("aGVsbG8= base64 decode")]).await;
+    assert_eq!(sent.len(), 1);
+    match &sent[0].1 {
+        RenderedMessage::Response { plan, input } => {
+            assert_eq!(input, "aGVsbG8= base64 decode");
+            assert_eq!(plan.status, PlanStatus::Success);
+            assert!(plan
+                .items
+                .iter()
+                .any(|item| matches!(item, PlanItem::CodeBlock { text, .. } if text == "hello")));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn attachments_are_passed_on() {
+    let msg = Incoming::new("base64").attach("/wD+", media_type!(TEXT / PLAIN));
+    let sent = run(config(), [msg]).await;
+    match &sent[0].1 {
+        RenderedMessage::Response { plan, .. } => {
+            assert!(plan.items.iter().any(
+                |item| matches!(item, PlanItem::CodeBlock { text, .. } if text == "L3dEKw==")
+            ));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn rate_limited_senders_get_a_notice() {
+    let config = BotConfig {
+        rate_limiter: Some(Arc::new(RateLimiter::new("1/60".parse().unwrap()))),
+        ..config()
+    };
+    let sent = run(
+        config,
+        [
+            Incoming::new("aGVsbG8= base64 decode").from("alice"),
+            Incoming::new("aGVsbG8= base64 decode").from("alice"),
+        ],
+    )
+    .await;
+    let notices = sent
+        .iter()
+        .filter_map(|(_, msg)| match msg {
+            RenderedMessage::Notice(text) => Some(text),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(notices.len(), 1);
+    assert!(notices[0].contains("seconds"), "{}", notices[0]);
+}
+
+#[tokio::test]
+async fn preferences_are_answered_with_a_notice() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = BotConfig {
+        prefs: Some(Arc::new(Preferences::open(dir.path()).unwrap())),
+        ..config()
+    };
+    let sent = run(config, [Incoming::new("set timezone Europe/Berlin")]).await;
+    assert!(
+        matches!(&sent[..], [(0, RenderedMessage::Notice(text))] if text == "Timezone set to Europe/Berlin."),
+        "{:?}",
+        sent
+    );
+}
+
+#[tokio::test]
+async fn help_is_only_answered_if_enabled() {
+    let sent = run(
+        BotConfig {
+            help: true,
+            ..config()
+        },
+        [Incoming::new("help")],
+    )
+    .await;
+    assert!(matches!(&sent[0].1, RenderedMessage::Notice(_)));
+
+    let sent = run(config(), [Incoming::new("help")]).await;
+    assert!(matches!(&sent[0].1, RenderedMessage::Response { .. }));
+}
+
+#[tokio::test]
+async fn question_mark_asks_for_help() {
+    let sent = run(
+        BotConfig {
+            help: true,
+            ..config()
+        },
+        [Incoming::new("?")],
+    )
+    .await;
+    assert!(
+        matches!(&sent[0].1, RenderedMessage::Notice(_)),
+        "{:?}",
+        sent
+    );
+}
+
+#[tokio::test]
+async fn alt_text_is_used_for_empty_messages() {
+    let config = BotConfig {
+        preprocessor: Preprocessor::default().with_mentions(r"<@\d+>").unwrap(),
+        ..config()
+    };
+    let msg = Incoming::new("<@123>").alt_text("aGVsbG8= base64 decode");
+    let sent = run(config, [msg]).await;
+    match &sent[0].1 {
+        RenderedMessage::Response { input, .. } => assert_eq!(input, "aGVsbG8= base64 decode"),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn attachments_without_text_are_processed() {
+    let msg = Incoming::new("").attach("aGVsbG8=", media_type!(TEXT / PLAIN));
+    let sent = run(config(), [msg]).await;
+    assert_eq!(sent.len(), 1);
+    match &sent[0].1 {
+        RenderedMessage::Response { plan, input } => {
+            assert_eq!(input, "");
+            assert_eq!(plan.status, PlanStatus::Success);
+            assert_eq!(plan.command.as_deref(), Some("yozuk-skill-base64"));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn transports_can_lower_the_file_size_limit() {
+    let config = BotConfig::new(
+        "test",
+        Limits {
+            max_file_size: Some(100),
+            ..Default::default()
+        },
+    );
+    let transport = Arc::new(
+        MemoryTransport::new([Incoming::new("/wD+ZmZm base64 decode")]).with_max_file_size(5),
+    );
+    let zuk = Arc::new(Yozuk::builder().build());
+    run_bot(transport.clone(), zuk, config).await;
+    match &transport.sent()[0].1 {
+        RenderedMessage::Response { plan, .. } => assert!(
+            !plan
+                .items
+                .iter()
+                .any(|item| matches!(item, PlanItem::File { .. })),
+            "{:?}",
+            plan.items
+        ),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<CommandEvent>>);
+
+impl AnalyticsSink for RecordingSink {
+    fn record(&self, event: &CommandEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[tokio::test]
+async fn commands_see_the_send_time_of_the_message() {
+    let sink = Arc::new(RecordingSink::default());
+    let config = BotConfig::new(
+        "test",
+        Limits {
+            analytics: sink.clone(),
+            ..Default::default()
+        },
+    );
+    let sent_at = unix_time(1_600_000_000).unwrap();
+    run(config, [Incoming::new("1 + 2").sent_at(sent_at)]).await;
+    let events = sink.0.lock().unwrap();
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|event| event.sent_at == Some(sent_at)));
+}
+
+#[test]
+fn unix_timestamps_are_converted() {
+    assert_eq!(
+        unix_time(1_600_000_000),
+        Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+    );
+    assert_eq!(unix_time(-1), None);
+}
+
+struct FakeStore {
+    deleted: u32,
+}
+
+impl FakeStore {
+    async fn load(&self, id: u32) -> Result<Option<String>> {
+        if id == self.deleted {
+            bail!("message {} not found", id);
+        }
+        Ok(Some(format!("message {}", id)))
+    }
+}
+
+#[tokio::test]
+async fn messages_that_fail_to_load_are_skipped() {
+    let store = FakeStore { deleted: 2 };
+    let redactor = Redactor::default();
+    let mut loaded = vec![];
+    for id in 1..=3 {
+        if let Some(msg) = load_or_skip(store.load(id), &redactor).await {
+            loaded.push(msg);
+        }
+    }
+    assert_eq!(loaded, ["message 1", "message 3"]);
+}
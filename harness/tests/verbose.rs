@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+use yozuk::Yozuk;
+use yozuk_bot_core::{plan_response, Limits, PlanItem, ResponsePlan, ResultCache};
+use yozuk_sdk::prelude::*;
+
+fn plan(zuk: &Yozuk, text: &str, limits: &Limits) -> ResponsePlan {
+    plan_response(zuk, text, vec![], &UserContext::default(), limits)
+}
+
+fn footer(plan: &ResponsePlan) -> Option<&str> {
+    plan.items.iter().find_map(|item| match item {
+        PlanItem::Text(text) if text.starts_with("debug: ") => Some(text.as_str()),
+        _ => None,
+    })
+}
+
+#[test]
+fn footer_is_left_out_by_default() {
+    let zuk = Yozuk::builder().build();
+    let plan = plan(&zuk, "hello to md5", &Limits::default());
+    assert_eq!(footer(&plan), None, "{:?}", plan.items);
+}
+
+#[test]
+fn footer_names_skills_and_duration() {
+    let zuk = Yozuk::builder().build();
+    let limits = Limits {
+        verbose: true,
+        ..Default::default()
+    };
+    let plan = plan(&zuk, "hello to md5", &limits);
+    assert!(matches!(plan.items.last(), Some(PlanItem::Text(text)) if text.starts_with("debug: ")));
+    let footer = footer(&plan).unwrap();
+    assert!(footer.ends_with(" ms)"), "{}", footer);
+    assert!(!footer.contains("cached"), "{}", footer);
+}
+
+#[test]
+fn footer_notes_cache_hits() {
+    let zuk = Yozuk::builder().build();
+    let limits = Limits {
+        verbose: true,
+        cache: Some(Arc::new(ResultCache::new(Duration::from_secs(60), [""; 0]))),
+        ..Default::default()
+    };
+    plan(&zuk, "hello to md5", &limits);
+    let plan = plan(&zuk, "hello to md5", &limits);
+    assert!(footer(&plan).unwrap().ends_with(" ms, cached)"));
+}
+
+#[test]
+fn unrecognized_messages_have_no_footer() {
+    let zuk = Yozuk::builder().build();
+    let limits = Limits {
+        verbose: true,
+        ..Default::default()
+    };
+    assert_eq!(footer(&plan(&zuk, "!!!", &limits)), None);
+}
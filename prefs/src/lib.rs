@@ -0,0 +1,217 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const DB_FILE: &str = "preferences.sqlite";
+
+/// Schema migrations; the database records how many of them have been applied
+/// in `user_version`. Append new entries, never edit existing ones.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE users (
+        platform TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        locale TEXT,
+        timezone TEXT,
+        PRIMARY KEY (platform, user_id)
+    );
+    CREATE TABLE user_values (
+        platform TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (platform, user_id, key)
+    );
+"#,
+    r#"
+    CREATE TABLE chat_values (
+        platform TEXT NOT NULL,
+        chat_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (platform, chat_id, key)
+    );
+"#,
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserPrefs {
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub values: BTreeMap<String, String>,
+}
+
+/// Per-user preferences stored in an SQLite database.
+pub struct Preferences {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl Preferences {
+    /// Opens or creates the database in `data_dir`.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(data_dir)
+            .with_context(|| format!("Failed to create {}", data_dir.display()))?;
+        let path = data_dir.join(DB_FILE);
+        let conn = Connection::open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let prefs = Self {
+            path,
+            conn: Mutex::new(conn),
+        };
+        prefs.check().and_then(|_| prefs.migrate())?;
+        Ok(prefs)
+    }
+
+    pub fn get(&self, platform: &str, user_id: &str) -> Result<UserPrefs> {
+        let conn = self.conn.lock().unwrap();
+        let (locale, timezone) = conn
+            .query_row(
+                "SELECT locale, timezone FROM users WHERE platform = ?1 AND user_id = ?2",
+                params![platform, user_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .unwrap_or_default();
+
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM user_values WHERE platform = ?1 AND user_id = ?2")?;
+        let values = stmt
+            .query_map(params![platform, user_id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(UserPrefs {
+            locale,
+            timezone,
+            values,
+        })
+    }
+
+    pub fn set(&self, platform: &str, user_id: &str, prefs: &UserPrefs) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO users (platform, user_id, locale, timezone)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![platform, user_id, prefs.locale, prefs.timezone],
+        )?;
+        tx.execute(
+            "DELETE FROM user_values WHERE platform = ?1 AND user_id = ?2",
+            params![platform, user_id],
+        )?;
+        for (key, value) in &prefs.values {
+            tx.execute(
+                "INSERT INTO user_values (platform, user_id, key, value) VALUES (?1, ?2, ?3, ?4)",
+                params![platform, user_id, key, value],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Reads, modifies and writes back the preferences of a user.
+    pub fn update<F>(&self, platform: &str, user_id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut UserPrefs),
+    {
+        let mut prefs = self.get(platform, user_id)?;
+        f(&mut prefs);
+        self.set(platform, user_id, &prefs)
+    }
+
+    /// Returns a setting that applies to everyone in a chat.
+    pub fn chat_value(&self, platform: &str, chat_id: &str, key: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let value = conn
+            .query_row(
+                "SELECT value FROM chat_values WHERE platform = ?1 AND chat_id = ?2 AND key = ?3",
+                params![platform, chat_id, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn set_chat_value(
+        &self,
+        platform: &str,
+        chat_id: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO chat_values (platform, chat_id, key, value)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![platform, chat_id, key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_chat_value(&self, platform: &str, chat_id: &str, key: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM chat_values WHERE platform = ?1 AND chat_id = ?2 AND key = ?3",
+            params![platform, chat_id, key],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the chats that have a value for `key`, with that value.
+    pub fn chats_with(&self, platform: &str, key: &str) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT chat_id, value FROM chat_values WHERE platform = ?1 AND key = ?2
+             ORDER BY chat_id",
+        )?;
+        let chats = stmt
+            .query_map(params![platform, key], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(chats)
+    }
+
+    /// Detects corrupt or foreign files before anything is written to them.
+    fn check(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0));
+        match result {
+            Ok(status) if status == "ok" => Ok(()),
+            Ok(status) => self.corrupt(status),
+            Err(err) => self.corrupt(err),
+        }
+    }
+
+    fn corrupt<T: std::fmt::Display>(&self, detail: T) -> Result<()> {
+        bail!(
+            "The preference database {} is corrupt ({}). \
+             Restore it from a backup or move it aside to start with empty preferences.",
+            self.path.display(),
+            detail
+        )
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            bail!(
+                "The preference database {} was created by a newer version (schema {}, supported {}).",
+                self.path.display(),
+                version,
+                MIGRATIONS.len()
+            );
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! Runs the pipeline of the frontends on lines read from stdin, so that
+//! rendering changes can be tried without any chat account.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    plan_response, Attachment, EngineOptions, Lang, Limits, PlanItem, Preprocessor,
+};
+use yozuk_sdk::prelude::*;
+
+pub struct Repl {
+    zuk: Yozuk,
+    limits: Limits,
+    engine: EngineOptions,
+    preprocessor: Preprocessor,
+    lang: Lang,
+    output_dir: PathBuf,
+    attachments: Vec<Attachment>,
+}
+
+impl Repl {
+    pub fn new(limits: Limits, engine: EngineOptions, lang: Lang, output_dir: PathBuf) -> Self {
+        Self {
+            zuk: engine.build(),
+            limits,
+            engine,
+            preprocessor: Preprocessor::default(),
+            lang,
+            output_dir,
+            attachments: vec![],
+        }
+    }
+
+    /// Handles lines until `:quit` or the end of `input`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line == ":quit" {
+                break;
+            } else if let Some(path) = line.strip_prefix(":attach ") {
+                self.attach(Path::new(path.trim()))?;
+            } else if !line.is_empty() {
+                self.respond(line, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a file to the next command.
+    fn attach(&mut self, path: &Path) -> Result<()> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        self.attachments.push(Attachment::new(
+            data,
+            media_type!(APPLICATION / OCTET_STREAM).into(),
+        ));
+        Ok(())
+    }
+
+    fn respond<W: Write>(&mut self, line: &str, output: &mut W) -> Result<()> {
+        let text = self.preprocessor.process(line).text;
+        let mut user = UserContext {
+            locale: Some(self.lang.code().into()),
+            ..Default::default()
+        };
+        self.engine.apply(&mut user);
+
+        let streams = self
+            .attachments
+            .drain(..)
+            .map(|attachment| attachment.stream())
+            .collect();
+        let plan = plan_response(&self.zuk, &text, streams, &user, &self.limits);
+        for item in plan.items {
+            match item {
+                PlanItem::Text(text) | PlanItem::CodeBlock { text, .. } => {
+                    writeln!(output, "{}", text)?;
+                }
+                PlanItem::File {
+                    name,
+                    media_type,
+                    data,
+                } => {
+                    let name = Path::new(&name)
+                        .file_name()
+                        .map(|name| name.to_os_string())
+                        .unwrap_or_else(|| "data".into());
+                    fs::create_dir_all(&self.output_dir)?;
+                    fs::write(self.output_dir.join(&name), &data)?;
+                    writeln!(
+                        output,
+                        "[file {}, {} bytes, {}]",
+                        name.to_string_lossy(),
+                        data.len(),
+                        media_type
+                    )?;
+                }
+                PlanItem::Apology { suggestions } => {
+                    writeln!(output, "{}", self.lang.tr("unrecognized", &[]))?;
+                    if !suggestions.is_empty() {
+                        writeln!(output, "{}:", self.lang.tr("did-you-mean", &[]))?;
+                        for suggestion in suggestions {
+                            writeln!(output, "- {}", suggestion)?;
+                        }
+                    }
+                }
+                PlanItem::Error { category } => {
+                    writeln!(output, "error: {}", category.message(self.lang))?;
+                }
+                PlanItem::Section { title } => {
+                    writeln!(output, "== {} ==", title)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use clap::Parser;
+use std::io;
+use std::path::PathBuf;
+use yozuk_bot_core::{
+    init_logging, EngineOptions, InlineBinary, Lang, Limits, Location, LogFormat, TextPolicy,
+    Timezone,
+};
+use yozuk_repl::Repl;
+
+/// Reads commands from stdin and prints the responses the bots would send.
+///
+/// `:attach <path>` adds a file to the next command and `:quit` exits.
+#[derive(Parser)]
+#[clap(author, version, about)]
+pub struct Args {
+    /// Write file outputs to this directory
+    #[clap(long, default_value = "repl-output")]
+    pub output_dir: PathBuf,
+
+    /// Language of canned replies: "en", "de" or "ja"
+    #[clap(long, default_value = "en")]
+    pub lang: Lang,
+
+    /// How text outputs are sent: "file", "inline" or the maximum inline length
+    #[clap(long, default_value = "inline")]
+    pub text_policy: TextPolicy,
+
+    /// Never produce files; binary outputs are summarized or inlined instead
+    #[clap(long)]
+    pub text_only: bool,
+
+    /// Send binary outputs up to a size as text instead of files, e.g. "base64:64" or "hex:32"
+    #[clap(long)]
+    pub inline_binary: Option<InlineBinary>,
+
+    /// Timezone of the user, e.g. "Europe/Berlin"
+    #[clap(long)]
+    pub timezone: Option<Timezone>,
+
+    /// Location of the user as "<latitude>,<longitude>"
+    #[clap(long)]
+    pub location: Option<Location>,
+}
+
+fn main() -> Result<()> {
+    init_logging(LogFormat::Pretty)?;
+    let args = Args::parse();
+    let limits = Limits {
+        text_policy: args.text_policy,
+        text_only: args.text_only,
+        inline_binary: args.inline_binary,
+        ..Default::default()
+    };
+    let engine = EngineOptions {
+        timezone: args.timezone,
+        location: args.location,
+    };
+    let mut repl = Repl::new(limits, engine, args.lang, args.output_dir);
+    repl.run(io::stdin().lock(), &mut io::stdout())
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use yozuk_bot_core::{EngineOptions, Lang, Limits};
+use yozuk_repl::Repl;
+
+fn run(input: &str, output_dir: &std::path::Path) -> String {
+    let mut repl = Repl::new(
+        Limits::default(),
+        EngineOptions::default(),
+        Lang::En,
+        output_dir.into(),
+    );
+    let mut output = vec![];
+    repl.run(input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn prints_text_and_writes_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run("aGVsbG8= base64 decode\nhello to QRCode\n", dir.path());
+    assert_eq!(output, "hello\n[file qrcode.png, 383 bytes, image/png]\n");
+    assert_eq!(fs::read(dir.path().join("qrcode.png")).unwrap().len(), 383);
+}
+
+#[test]
+fn attaches_files_to_the_next_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "hello").unwrap();
+    let output = run(
+        &format!(":attach {}\nmd5\nmd5\n", input.display()),
+        dir.path(),
+    );
+    assert_eq!(
+        output,
+        "5d41402abc4b2a76b9719d911017c592\n\
+         error: The command couldn't process your input.\n\
+         No valid input source provided\n"
+    );
+}
+
+#[test]
+fn stops_at_quit() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(run("1 + 2\n:quit\n1 + 3\n", dir.path()), "3\n");
+}
@@ -0,0 +1,618 @@
+use anyhow::Result;
+use clap::Parser;
+use futures::{SinkExt, StreamExt};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_xmpp::parsers::iq::{Iq, IqType};
+use tokio_xmpp::parsers::message::{Body, Message, MessageType};
+use tokio_xmpp::parsers::muc::muc::{History, Muc};
+use tokio_xmpp::parsers::ns;
+use tokio_xmpp::parsers::ping::Ping;
+use tokio_xmpp::parsers::presence::{Presence, Type as PresenceType};
+use tokio_xmpp::parsers::stanza_error::{DefinedCondition, ErrorType, StanzaError};
+use tokio_xmpp::{AsyncClient, BareJid, Element, Event, Jid, Packet};
+use tracing::Instrument;
+use yozuk::Yozuk;
+use yozuk_bot_core::{
+    format_size, init_logging, plan_failure, plan_response, selftest, spawn_plan, ConfigFile,
+    EngineOptions, ErrorCategory, InlineBinary, Lang, Limits, Location, LogFormat, Metrics,
+    PlanItem, Redactor, ResponsePlan, ResultCache, TextPolicy, Timezone, CONFIG_HELP,
+    DEFAULT_UNCACHED_SKILLS,
+};
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+
+mod oob;
+mod session;
+mod upload;
+
+use session::Session;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Parser)]
+#[clap(author, version, about, after_help = CONFIG_HELP)]
+pub struct Args {
+    /// Read settings from a TOML file
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    #[clap(long)]
+    pub jid: Option<BareJid>,
+
+    #[clap(long, env("XMPP_PASSWORD"), hide_env_values = true)]
+    pub password: Option<String>,
+
+    /// Join a multi-user chat room (repeatable)
+    #[clap(long = "room")]
+    pub rooms: Vec<BareJid>,
+
+    /// Nickname used in multi-user chat rooms [default: yozuk]
+    #[clap(long)]
+    pub nick: Option<String>,
+
+    /// How text outputs are sent: "file", "inline" or the maximum inline length [default: inline]
+    #[clap(long)]
+    pub text_policy: Option<TextPolicy>,
+
+    /// Send files as a single ZIP archive when there are more than this many
+    #[clap(long)]
+    pub bundle_threshold: Option<usize>,
+
+    /// Serve Prometheus metrics on this address
+    #[clap(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Also redact text matching this regular expression from error replies and logs (repeatable)
+    #[clap(long = "redact")]
+    pub redact_patterns: Vec<String>,
+
+    /// Language of canned replies when the user's language is unknown: en, de or ja [default: en]
+    #[clap(long)]
+    pub lang: Option<Lang>,
+
+    /// Reply with the commands that would run instead of running them
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Timezone for users who haven't set their own, e.g. "Europe/Berlin"
+    #[clap(long)]
+    pub timezone: Option<Timezone>,
+
+    /// Location for users, as "<latitude>,<longitude>"
+    #[clap(long)]
+    pub location: Option<Location>,
+
+    /// Reuse the results of identical commands for this many seconds (0 disables the cache) [default: 0]
+    #[clap(long)]
+    pub cache_ttl: Option<u64>,
+
+    /// Never reuse results of this skill, e.g. "uuid" (repeatable) [default: random and time-dependent skills]
+    #[clap(long = "uncached-skill")]
+    pub uncached_skills: Vec<String>,
+
+    /// Cancel commands that take longer than this many seconds [default: 30]
+    #[clap(long)]
+    pub command_timeout: Option<u64>,
+
+    /// Append the matched skills and the processing time to replies
+    #[clap(long)]
+    pub verbose: bool,
+
+    /// Never send files; binary outputs are summarized or inlined instead
+    #[clap(long)]
+    pub text_only: bool,
+
+    /// Send binary outputs up to a size as text instead of files, e.g. "base64:64" or "hex:32"
+    #[clap(long)]
+    pub inline_binary: Option<InlineBinary>,
+
+    /// Send at most this many texts, code blocks and files per reply and note how many were left out [default: 20]
+    #[clap(long)]
+    pub max_blocks: Option<usize>,
+
+    /// Run a known command and exit with an error if its output is wrong, before connecting
+    #[clap(long)]
+    pub selftest: bool,
+
+    /// Log format: "pretty" or "json" [default: pretty]
+    #[clap(long)]
+    pub log_format: Option<LogFormat>,
+}
+
+pub struct Config {
+    pub jid: BareJid,
+    pub password: String,
+    pub rooms: Vec<BareJid>,
+    pub nick: String,
+    pub text_policy: TextPolicy,
+    pub bundle_threshold: Option<usize>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub redactor: Redactor,
+    pub lang: Lang,
+    pub dry_run: bool,
+    pub engine: EngineOptions,
+    pub cache: Option<Arc<ResultCache>>,
+    pub command_timeout: Duration,
+    pub verbose: bool,
+    pub text_only: bool,
+    pub inline_binary: Option<InlineBinary>,
+    pub max_blocks: usize,
+    pub selftest: bool,
+    pub log_format: LogFormat,
+}
+
+impl Config {
+    pub fn new(args: Args) -> Result<Self> {
+        let mut file = ConfigFile::load(args.config.as_deref())?;
+        let jid = file.required("jid", args.jid);
+        let password = file.secret("password", args.password);
+        let rooms = file.list("rooms", args.rooms);
+        let nick = file.value("nick", args.nick);
+        let text_policy = file.value("text_policy", args.text_policy);
+        let bundle_threshold = file.value("bundle_threshold", args.bundle_threshold);
+        let metrics_addr = file.value("metrics_addr", args.metrics_addr);
+        let redact_patterns = file.list("redact_patterns", args.redact_patterns);
+        let lang = file.value("lang", args.lang);
+        let dry_run = file.value("dry_run", args.dry_run.then_some(true));
+        let timezone = file.value("timezone", args.timezone);
+        let location = file.value("location", args.location);
+        let cache_ttl = file.value("cache_ttl", args.cache_ttl);
+        let uncached_skills = file.list("uncached_skills", args.uncached_skills);
+        let command_timeout = file.value("command_timeout", args.command_timeout);
+        let verbose = file.value("verbose", args.verbose.then_some(true));
+        let text_only = file.value("text_only", args.text_only.then_some(true));
+        let inline_binary = file.value("inline_binary", args.inline_binary);
+        let max_blocks = file.value("max_blocks", args.max_blocks);
+        let selftest = file.value("selftest", args.selftest.then_some(true));
+        let log_format = file.value("log_format", args.log_format);
+        file.finish()?;
+        let redactor = Redactor::new(redact_patterns)?;
+
+        // `finish` has failed if any required value is missing.
+        Ok(Self {
+            jid: jid.unwrap(),
+            password: password.unwrap(),
+            rooms,
+            nick: nick.unwrap_or_else(|| "yozuk".into()),
+            text_policy: text_policy.unwrap_or(TextPolicy::AlwaysInline),
+            bundle_threshold,
+            metrics_addr,
+            redactor,
+            lang: lang.unwrap_or_default(),
+            dry_run: dry_run.unwrap_or_default(),
+            engine: EngineOptions { timezone, location },
+            cache: cache_ttl.filter(|&ttl| ttl > 0).map(|ttl| {
+                let ttl = Duration::from_secs(ttl);
+                Arc::new(if uncached_skills.is_empty() {
+                    ResultCache::new(ttl, DEFAULT_UNCACHED_SKILLS.iter().copied())
+                } else {
+                    ResultCache::new(ttl, uncached_skills)
+                })
+            }),
+            command_timeout: Duration::from_secs(command_timeout.unwrap_or(30)),
+            verbose: verbose.unwrap_or_default(),
+            text_only: text_only.unwrap_or_default(),
+            inline_binary,
+            max_blocks: max_blocks.unwrap_or(20),
+            selftest: selftest.unwrap_or_default(),
+            log_format: log_format.unwrap_or_default(),
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::new(Args::try_parse()?)?;
+    init_logging(config.log_format)?;
+
+    let server = Arc::new(Server::new(config)?);
+    server.start().await
+}
+
+struct Server {
+    config: Config,
+    zuk: Arc<Yozuk>,
+    limits: Limits,
+    metrics: Arc<Metrics>,
+}
+
+struct Request {
+    to: Jid,
+    kind: MessageType,
+    text: String,
+    urls: Vec<String>,
+    username: Option<String>,
+    lang: Lang,
+}
+
+impl Server {
+    fn new(config: Config) -> Result<Self> {
+        let zuk = Arc::new(config.engine.build());
+        if config.selftest {
+            selftest(&zuk)?;
+        }
+        let limits = Limits {
+            text_policy: config.text_policy,
+            bundle_threshold: config.bundle_threshold,
+            redactor: config.redactor.clone(),
+            dry_run: config.dry_run,
+            cache: config.cache.clone(),
+            command_timeout: config.command_timeout,
+            text_only: config.text_only,
+            inline_binary: config.inline_binary,
+            max_blocks: Some(config.max_blocks),
+            verbose: config.verbose,
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::new("xmpp"));
+        if let Some(addr) = config.metrics_addr {
+            metrics.serve(addr)?;
+        }
+        Ok(Self {
+            config,
+            zuk,
+            limits,
+            metrics,
+        })
+    }
+
+    async fn start(self: Arc<Self>) -> Result<()> {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let client = AsyncClient::new(self.config.jid.clone(), self.config.password.clone());
+            let (mut sink, mut stream) = client.split();
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let writer = tokio::spawn(async move {
+                while let Some(stanza) = rx.recv().await {
+                    if let Err(err) = sink.send(Packet::Stanza(stanza)).await {
+                        tracing::error!("{}", err);
+                        break;
+                    }
+                }
+            });
+
+            let domain = BareJid::from_parts(None, &self.config.jid.domain());
+            let session = Arc::new(Session::new(tx, domain));
+            while let Some(event) = stream.next().await {
+                match event {
+                    Event::Online { bound_jid, .. } => {
+                        tracing::info!("connected as {}", bound_jid);
+                        backoff = MIN_BACKOFF;
+                        self.join(&session)?;
+                    }
+                    Event::Disconnected(err) => {
+                        tracing::warn!("disconnected: {}", err);
+                        break;
+                    }
+                    Event::Stanza(stanza) => {
+                        self.clone().handle_stanza(&session, stanza);
+                    }
+                }
+            }
+            writer.abort();
+
+            tracing::info!("reconnecting in {:?}", backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Announces the bot and joins the rooms; a room that can't be joined
+    /// doesn't keep the bot out of the others.
+    fn join(&self, session: &Session) -> Result<()> {
+        session.send(Presence::new(PresenceType::None))?;
+        for room in &self.config.rooms {
+            tracing::info!("joining {}", room);
+            if let Err(err) = self.join_room(session, room) {
+                tracing::error!("failed to join {}: {}", room, err);
+            }
+        }
+        Ok(())
+    }
+
+    fn join_room(&self, session: &Session, room: &BareJid) -> Result<()> {
+        let mut presence =
+            Presence::new(PresenceType::None).with_to(room.with_resource_str(&self.config.nick)?);
+        presence.add_payload(Muc::new().with_history(History::new().with_maxstanzas(0)));
+        session.send(presence)
+    }
+
+    fn handle_stanza(self: Arc<Self>, session: &Arc<Session>, stanza: Element) {
+        let result = match stanza.name() {
+            "iq" => Iq::try_from(stanza)
+                .map_err(Into::into)
+                .and_then(|iq| self.handle_iq(session, iq)),
+            "presence" => Presence::try_from(stanza)
+                .map_err(Into::into)
+                .and_then(|presence| self.handle_presence(session, presence)),
+            "message" => {
+                if let Some(request) = Message::try_from(stanza)
+                    .ok()
+                    .and_then(|msg| self.request(msg))
+                {
+                    let server = self.clone();
+                    let session = session.clone();
+                    let span =
+                        tracing::info_span!("message", platform = "xmpp", chat = %request.to);
+                    tokio::spawn(
+                        async move {
+                            if let Err(err) = server.handle_request(&session, request).await {
+                                tracing::error!(
+                                    "{}",
+                                    server.limits.redactor.redact(&err.to_string())
+                                );
+                            }
+                        }
+                        .instrument(span),
+                    );
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            tracing::error!("{}", self.limits.redactor.redact(&err.to_string()));
+        }
+    }
+
+    fn handle_iq(&self, session: &Session, iq: Iq) -> Result<()> {
+        let payload = match iq.payload {
+            IqType::Result(_) | IqType::Error(_) => {
+                session.resolve(iq);
+                return Ok(());
+            }
+            IqType::Get(ref payload) if Ping::try_from(payload.clone()).is_ok() => {
+                IqType::Result(None)
+            }
+            _ => IqType::Error(StanzaError::new(
+                ErrorType::Cancel,
+                DefinedCondition::ServiceUnavailable,
+                "en",
+                "",
+            )),
+        };
+        session.send(Iq {
+            from: None,
+            to: iq.from,
+            id: iq.id,
+            payload,
+        })
+    }
+
+    fn handle_presence(&self, session: &Session, presence: Presence) -> Result<()> {
+        match (presence.type_, presence.from) {
+            (PresenceType::Subscribe, Some(from)) => {
+                tracing::info!("accepting subscription from {}", from);
+                session.send(Presence::new(PresenceType::Subscribed).with_to(from.into_bare()))?;
+            }
+            // The server refused to let the bot into a room, e.g. because the
+            // room doesn't exist or the nickname is taken.
+            (PresenceType::Error, Some(from)) => {
+                let reason = presence
+                    .payloads
+                    .into_iter()
+                    .find_map(|payload| StanzaError::try_from(payload).ok())
+                    .map(|err| format!("{:?}", err.defined_condition))
+                    .unwrap_or_else(|| "unknown error".into());
+                tracing::error!("failed to join {}: {}", from.to_bare(), reason);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn request(&self, msg: Message) -> Option<Request> {
+        let from = msg.from.clone()?;
+        let (lang, text) = msg
+            .get_best_body(vec![])
+            .map(|(lang, body)| (Lang::from_locale(&lang), body.0.clone()))
+            .unwrap_or_default();
+        let lang = lang.unwrap_or(self.config.lang);
+        let urls = oob::urls(&msg.payloads);
+
+        let request = match msg.type_ {
+            MessageType::Chat | MessageType::Normal => {
+                let sender = from.to_bare();
+                if sender == self.config.jid {
+                    return None;
+                }
+                Request {
+                    to: from,
+                    kind: MessageType::Chat,
+                    text,
+                    urls,
+                    username: sender.node_str().map(str::to_string),
+                    lang,
+                }
+            }
+            MessageType::Groupchat => {
+                let nick = match &from {
+                    Jid::Full(from) => from.resource_str().to_string(),
+                    Jid::Bare(_) => return None,
+                };
+                let delayed = msg
+                    .payloads
+                    .iter()
+                    .any(|payload| payload.is("delay", ns::DELAY));
+                if nick == self.config.nick || delayed {
+                    return None;
+                }
+                Request {
+                    to: Jid::Bare(from.into_bare()),
+                    kind: MessageType::Groupchat,
+                    text: strip_nick(&text, &self.config.nick)?.to_string(),
+                    urls,
+                    username: Some(nick),
+                    lang,
+                }
+            }
+            _ => return None,
+        };
+
+        if request.text.trim().is_empty() && request.urls.is_empty() {
+            None
+        } else {
+            Some(request)
+        }
+    }
+
+    async fn handle_request(&self, session: &Session, request: Request) -> Result<()> {
+        self.metrics.message_received();
+        let _in_flight = self.metrics.in_flight();
+
+        let mut streams = vec![];
+        for url in &request.urls {
+            match oob::download(url).await {
+                Ok((stream, size)) => {
+                    self.metrics.attachment(size);
+                    streams.push(stream);
+                }
+                Err(err) if err.is::<oob::TooLarge>() => {
+                    let max = format_size(oob::MAX_FILE_SIZE);
+                    let plan = plan_failure(
+                        ErrorCategory::InputTooLarge,
+                        request.lang.tr("too-large", &[("max", &max)]),
+                        format!("attachment over {}", max),
+                    );
+                    return self.send_plan(session, &request, plan).await;
+                }
+                Err(err) => tracing::warn!("failed to download {}: {}", url, err),
+            }
+        }
+        let text = request.urls.iter().fold(request.text.clone(), |text, url| {
+            text.replace(url.as_str(), "")
+        });
+        let mut user = UserContext {
+            username: request.username.clone(),
+            locale: Some(request.lang.code().into()),
+            ..Default::default()
+        };
+        self.config.engine.apply(&mut user);
+
+        let zuk = self.zuk.clone();
+        let limits = self.limits.clone();
+        let input = text.clone();
+        let plan = spawn_plan(&self.limits, &text, move || {
+            plan_response(&zuk, &input, streams, &user, &limits)
+        })
+        .await;
+        self.metrics.record(&plan);
+        self.send_plan(session, &request, plan).await
+    }
+
+    async fn send_plan(
+        &self,
+        session: &Session,
+        request: &Request,
+        plan: ResponsePlan,
+    ) -> Result<()> {
+        let mut content = vec![];
+        for item in plan.items {
+            match item {
+                PlanItem::Text(text) => {
+                    content.push(text);
+                }
+                PlanItem::CodeBlock { text, .. } => {
+                    content.push(format!("```\n{}\n```", text));
+                }
+                PlanItem::File {
+                    name,
+                    media_type,
+                    data,
+                } => {
+                    if !content.is_empty() {
+                        self.reply(session, request, content.join("\n"))?;
+                        content.clear();
+                    }
+                    self.send_file(session, request, name, media_type, data)
+                        .await?;
+                }
+                PlanItem::Apology { suggestions } => {
+                    content.push(request.lang.tr("unrecognized", &[]));
+                    if !suggestions.is_empty() {
+                        content.push(format!(
+                            "{}: {}",
+                            request.lang.tr("did-you-mean", &[]),
+                            suggestions.join(", ")
+                        ));
+                    }
+                }
+                PlanItem::Error { category } => {
+                    content.push(category.message(request.lang));
+                }
+                PlanItem::Section { .. } => {}
+            }
+        }
+        if !content.is_empty() {
+            self.reply(session, request, content.join("\n"))?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_file(
+        &self,
+        session: &Session,
+        request: &Request,
+        filename: String,
+        media_type: MediaTypeBuf,
+        data: Bytes,
+    ) -> Result<()> {
+        let size = data.len();
+        if let Some(service) = upload::service(session).await {
+            match upload::upload(session, service, filename.clone(), &media_type, data).await {
+                Ok(url) => {
+                    let mut message = message(request, url.clone());
+                    message.payloads.push(oob::element(&url));
+                    return session
+                        .send(message)
+                        .map_err(|err| self.metrics.send_failure(err));
+                }
+                Err(err) => tracing::warn!("failed to upload {}: {}", filename, err),
+            }
+        }
+
+        let text = request.lang.tr(
+            "upload-unavailable",
+            &[
+                ("name", &filename),
+                ("size", &size.to_string()),
+                ("type", media_type.as_ref()),
+            ],
+        );
+        self.reply(session, request, text)
+    }
+
+    fn reply<T: Into<String>>(&self, session: &Session, request: &Request, text: T) -> Result<()> {
+        session
+            .send(message(request, text.into()))
+            .map_err(|err| self.metrics.send_failure(err))
+    }
+}
+
+fn message(request: &Request, text: String) -> Message {
+    let mut message = Message::new(Some(request.to.clone()));
+    message.type_ = request.kind.clone();
+    message.bodies.insert(String::new(), Body(text));
+    message
+}
+
+fn strip_nick<'a>(text: &'a str, nick: &str) -> Option<&'a str> {
+    let text = text.trim_start();
+    let head = text.get(..nick.len())?;
+    if head.to_lowercase() != nick.to_lowercase() {
+        return None;
+    }
+    let rest = &text[nick.len()..];
+    if rest.is_empty() || rest.starts_with([':', ',']) || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim_start_matches([':', ',']).trim_start())
+    } else {
+        None
+    }
+}
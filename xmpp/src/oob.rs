@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_xmpp::Element;
+use url::Host;
+use yozuk_bot_core::is_public_ip;
+use yozuk_sdk::prelude::*;
+
+const NS_OOB: &str = "jabber:x:oob";
+
+pub const MAX_FILE_SIZE: usize = 10485760;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct TooLarge;
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Too large file input")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// Collects the URLs of out-of-band attachments (XEP-0066).
+pub fn urls(payloads: &[Element]) -> Vec<String> {
+    payloads
+        .iter()
+        .filter(|elem| elem.is("x", NS_OOB))
+        .filter_map(|elem| elem.get_child("url", NS_OOB))
+        .map(|url| url.text().trim().to_string())
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .collect()
+}
+
+pub fn element(url: &str) -> Element {
+    Element::builder("x", NS_OOB)
+        .append(Element::builder("url", NS_OOB).append(url).build())
+        .build()
+}
+
+/// Resolves the host of the URL to a public address.
+async fn public_addr(url: &Url) -> Result<SocketAddr> {
+    let port = url.port_or_known_default().context("no port")?;
+    let addrs: Vec<SocketAddr> = match url.host().context("no host")? {
+        Host::Domain(domain) => tokio::net::lookup_host((domain, port)).await?.collect(),
+        Host::Ipv4(ip) => vec![(ip, port).into()],
+        Host::Ipv6(ip) => vec![(ip, port).into()],
+    };
+    match addrs.into_iter().find(|addr| is_public_ip(addr.ip())) {
+        Some(addr) => Ok(addr),
+        None => bail!(
+            "{} is not a public host",
+            url.host_str().unwrap_or_default()
+        ),
+    }
+}
+
+/// Downloads the attachment and returns it along with its size.
+///
+/// The URL comes from the sender, so only public hosts are fetched. The
+/// connection is pinned to the checked address and redirects aren't
+/// followed, so neither DNS nor the server can point it elsewhere.
+pub async fn download(url: &str) -> Result<(InputStream, usize)> {
+    let url = Url::parse(url)?;
+    let addr = public_addr(&url).await?;
+    let mut client = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .redirect(Policy::none());
+    if let Some(domain) = url.domain() {
+        client = client.resolve(domain, addr);
+    }
+    let res = client.build()?.get(url).send().await?.error_for_status()?;
+    if let Some(len) = res.content_length() {
+        if len > MAX_FILE_SIZE as u64 {
+            bail!(TooLarge);
+        }
+    }
+
+    let media_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ty| ty.to_str().ok())
+        .and_then(|ty| MediaTypeBuf::from_string(ty.to_string()).ok())
+        .unwrap_or_else(|| media_type!(APPLICATION / OCTET_STREAM).into());
+
+    let mut data = Vec::new();
+    let mut stream = res.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > MAX_FILE_SIZE {
+            bail!(TooLarge);
+        }
+    }
+    let size = data.len();
+    Ok((InputStream::new(Cursor::new(data), media_type), size))
+}
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, OnceCell};
+use tokio_xmpp::parsers::iq::{Iq, IqType};
+use tokio_xmpp::{BareJid, Element, Jid};
+
+const IQ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outgoing half of a single connection.
+///
+/// A new session is created for every (re)connection, so pending requests
+/// and discovered services never outlive the stream they belong to.
+pub struct Session {
+    tx: mpsc::UnboundedSender<Element>,
+    pending: Mutex<HashMap<String, oneshot::Sender<Iq>>>,
+    next_id: AtomicU64,
+    pub upload_service: OnceCell<Option<Jid>>,
+    pub domain: BareJid,
+}
+
+impl Session {
+    pub fn new(tx: mpsc::UnboundedSender<Element>, domain: BareJid) -> Self {
+        Self {
+            tx,
+            pending: Default::default(),
+            next_id: AtomicU64::new(0),
+            upload_service: OnceCell::new(),
+            domain,
+        }
+    }
+
+    pub fn send<T: Into<Element>>(&self, stanza: T) -> Result<()> {
+        self.tx
+            .send(stanza.into())
+            .map_err(|_| anyhow!("Connection closed"))
+    }
+
+    pub fn next_id(&self) -> String {
+        format!("yozuk-{}", self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Sends an `<iq/>` request and waits for the matching result.
+    pub async fn request(&self, iq: Iq) -> Result<Option<Element>> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(iq.id.clone(), tx);
+        let id = iq.id.clone();
+        if let Err(err) = self.send(iq) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        let result = tokio::time::timeout(IQ_TIMEOUT, rx).await;
+        self.pending.lock().unwrap().remove(&id);
+        match result?.map_err(|_| anyhow!("Connection closed"))?.payload {
+            IqType::Result(payload) => Ok(payload),
+            IqType::Error(err) => bail!("{:?}", err.defined_condition),
+            _ => bail!("Unexpected iq response"),
+        }
+    }
+
+    /// Routes an incoming `<iq type="result|error"/>` to its waiting request.
+    pub fn resolve(&self, iq: Iq) {
+        if let Some(tx) = self.pending.lock().unwrap().remove(&iq.id) {
+            let _ = tx.send(iq);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::session::Session;
+use anyhow::{bail, Result};
+use std::convert::TryFrom;
+use tokio_xmpp::parsers::disco::{
+    DiscoInfoQuery, DiscoInfoResult, DiscoItemsQuery, DiscoItemsResult,
+};
+use tokio_xmpp::parsers::http_upload::{Header, SlotRequest, SlotResult};
+use tokio_xmpp::parsers::iq::Iq;
+use tokio_xmpp::parsers::ns;
+use tokio_xmpp::Jid;
+use yozuk_sdk::prelude::*;
+use yozuk_sdk::Bytes;
+
+/// Returns the XEP-0363 upload service of the server, if any.
+pub async fn service(session: &Session) -> Option<Jid> {
+    session
+        .upload_service
+        .get_or_init(|| async {
+            match discover(session).await {
+                Ok(service) => service,
+                Err(err) => {
+                    tracing::warn!("upload service discovery failed: {}", err);
+                    None
+                }
+            }
+        })
+        .await
+        .clone()
+}
+
+async fn discover(session: &Session) -> Result<Option<Jid>> {
+    let domain = Jid::Bare(session.domain.clone());
+    let mut candidates = vec![domain.clone()];
+    let iq = Iq::from_get(session.next_id(), DiscoItemsQuery { node: None }).with_to(domain);
+    if let Some(payload) = session.request(iq).await? {
+        let items = DiscoItemsResult::try_from(payload)?;
+        candidates.extend(items.items.into_iter().map(|item| item.jid));
+    }
+
+    for jid in candidates {
+        let iq =
+            Iq::from_get(session.next_id(), DiscoInfoQuery { node: None }).with_to(jid.clone());
+        let info = match session.request(iq).await {
+            Ok(Some(payload)) => DiscoInfoResult::try_from(payload)?,
+            _ => continue,
+        };
+        if info
+            .features
+            .iter()
+            .any(|feature| feature.var == ns::HTTP_UPLOAD)
+        {
+            tracing::info!("found upload service: {}", jid);
+            return Ok(Some(jid));
+        }
+    }
+    Ok(None)
+}
+
+/// Uploads the data and returns the public download URL.
+pub async fn upload(
+    session: &Session,
+    service: Jid,
+    filename: String,
+    media_type: &MediaTypeBuf,
+    data: Bytes,
+) -> Result<String> {
+    let request = SlotRequest {
+        filename,
+        size: data.len() as u64,
+        content_type: Some(media_type.to_string()),
+    };
+    let iq = Iq::from_get(session.next_id(), request).with_to(service);
+    let slot = match session.request(iq).await? {
+        Some(payload) => SlotResult::try_from(payload)?,
+        None => bail!("Empty slot response"),
+    };
+
+    let mut request = reqwest::Client::new()
+        .put(&slot.put.url)
+        .header(reqwest::header::CONTENT_TYPE, media_type.to_string());
+    for header in slot.put.headers {
+        request = match header {
+            Header::Authorization(value) => request.header(reqwest::header::AUTHORIZATION, value),
+            Header::Cookie(value) => request.header(reqwest::header::COOKIE, value),
+            Header::Expires(value) => request.header(reqwest::header::EXPIRES, value),
+        };
+    }
+    request.body(data).send().await?.error_for_status()?;
+    Ok(slot.get.url)
+}